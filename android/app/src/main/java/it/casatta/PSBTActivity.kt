@@ -74,7 +74,8 @@ class PSBTActivity : ContextActivity() {
 
         for (i in psbtPretty.outputs.indices) {
             val output = psbtPretty.outputs[i]
-            outputsAdapter.list.add(TxInOutItem("output #$i", output.address, output.value, output.wallet_with_path ))
+            val title = if (output.change) "output #$i (change)" else "output #$i"
+            outputsAdapter.list.add(TxInOutItem(title, output.address, output.value, output.wallet_with_path ))
         }
 
         items.layoutManager = LinearLayoutManager(this)
@@ -71,7 +71,9 @@ class Data {
     data class Identifier(
         val kind: Kind,
         val name: String,
-        val network: String
+        val network: String,
+        val account: Int? = null,
+        val scheme: String? = null
     )
 
     data class MasterSecret(
@@ -80,7 +82,10 @@ class Data {
         val network: String,
         val key: String,
         val mnemonic: String?,
-        val dice: Dice?
+        val dice: Dice?,
+        val derivation_path: String?,
+        val passphrase: Boolean?,
+        val codex32: String?
     )
 
     data class Dice(
@@ -121,6 +126,7 @@ class Data {
 
     data class TxOut(
         val address: String,
+        val change: Boolean,
         val value: String,
         val wallet_with_path: String?
     )
@@ -144,7 +150,8 @@ class Data {
         val fee: Fee,
         val info: List<String>,
         val psbt_file: String,
-        val balances: String
+        val balances: String,
+        val replaces: String?
     )
 
     data class GetAddressOutput(
@@ -192,6 +199,7 @@ class Data {
         WALLET("Wallet"),
         WALLET_INDEXES("WalletIndexes"),
         WALLET_SIGNATURE("WalletSignature"),
+        WALLET_LABELS("WalletLabels"),
         MASTER_SECRET("MasterSecret"),
         DESCRIPTOR_PUBLIC_KEY("DescriptorPublicKey"),
         PSBT("Psbt")
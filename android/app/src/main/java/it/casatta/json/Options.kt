@@ -68,7 +68,8 @@ class Options {
 
     data class ExportOptions(
         val kind: Data.Kind,
-        val name: String
+        val name: String,
+        val psbt_v2: Boolean = false
     )
 
 }
\ No newline at end of file
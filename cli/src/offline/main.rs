@@ -21,6 +21,19 @@ struct FirmaOfflineCommands {
     /// when true, reading from stdin is expected and blocking
     #[structopt(short, long)]
     encrypt: bool,
+
+    /// Flag to indicate usage of a BIP39 passphrase in random, restore, dice, derive-key, sign,
+    /// sign-wallet and split, when true the passphrase is read from the first line of stdin,
+    /// before the encryption key. Not accepted by restore --nature slip39, shares created by
+    /// split already contain the seed derived with the passphrase
+    #[structopt(long)]
+    passphrase: bool,
+
+    /// Flag to indicate the SLIP-39 shares given to restore are encrypted with a SLIP-39
+    /// passphrase, when true it's read from stdin after the BIP39 passphrase and before the
+    /// encryption key
+    #[structopt(long)]
+    slip39_passphrase: bool,
 }
 
 #[derive(StructOpt, Debug)]
@@ -40,11 +53,17 @@ enum FirmaOfflineSubcommands {
     /// Restore a json key from xprv or mnemonic
     Restore(offline::restore::RestoreOptions),
 
+    /// Derive the descriptor public key of another account of a key
+    DeriveKey(offline::derive_key::DeriveKeyOptions),
+
+    /// Split the seed of a key in SLIP-39 shares
+    Split(offline::split::SplitOptions),
+
     /// List wallets, keys and PSBTs
     List(common::list::ListOptions),
 
     /// Sign a wallet json containing the descriptor to avoid tampering
-    SignWallet(WalletNameOptions),
+    SignWallet(offline::sign_wallet::SignWalletOptions),
 
     /// Verify a wallet json containing the descriptor to avoid tampering
     VerifyWallet(WalletNameOptions),
@@ -62,10 +81,23 @@ fn main() -> Result<()> {
     let cmd = FirmaOfflineCommands::from_args();
     let FirmaOfflineCommands {
         mut context,
-        subcommand,
+        mut subcommand,
         encrypt,
+        passphrase,
+        slip39_passphrase,
     } = cmd;
 
+    let passphrase = if passphrase {
+        Some(read_passphrase()?)
+    } else {
+        None
+    };
+    let slip39_passphrase = if slip39_passphrase {
+        Some(read_passphrase()?)
+    } else {
+        None
+    };
+
     if encrypt {
         context.read_encryption_key()?;
     }
@@ -75,6 +107,16 @@ fn main() -> Result<()> {
         context, encrypt, subcommand
     );
 
+    if let Some(passphrase) = passphrase {
+        subcommand.set_passphrase(passphrase)?;
+    }
+    if let Some(slip39_passphrase) = slip39_passphrase {
+        match &mut subcommand {
+            Restore(opt) => opt.slip39_passphrase = Some(slip39_passphrase),
+            _ => return Err("Only restore uses a SLIP-39 passphrase".into()),
+        }
+    }
+
     let value = match launch_subcommand(&context, subcommand) {
         Ok(value) => value,
         Err(e) => e.to_json(),
@@ -85,6 +127,29 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+fn read_passphrase() -> Result<String> {
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+impl FirmaOfflineSubcommands {
+    fn set_passphrase(&mut self, passphrase: String) -> Result<()> {
+        let field = match self {
+            Dice(opt) => &mut opt.passphrase,
+            Random(opt) => &mut opt.passphrase,
+            Restore(opt) => &mut opt.passphrase,
+            DeriveKey(opt) => &mut opt.passphrase,
+            Sign(opt) => &mut opt.passphrase,
+            SignWallet(opt) => &mut opt.passphrase,
+            Split(opt) => &mut opt.passphrase,
+            _ => return Err("The command does not use a passphrase".into()),
+        };
+        *field = Some(passphrase);
+        Ok(())
+    }
+}
+
 fn launch_subcommand(
     context: &OfflineContext,
     subcommand: FirmaOfflineSubcommands,
@@ -95,6 +160,8 @@ fn launch_subcommand(
         Random(opt) => context.create_key(opt)?.try_into(),
         Print(opt) => context.print(opt)?.try_into(),
         Restore(opt) => context.restore(opt)?.try_into(),
+        DeriveKey(opt) => context.derive_key(opt)?.try_into(),
+        Split(opt) => context.split(opt)?.try_into(),
         List(opt) => context.list(opt)?.try_into(),
         SignWallet(opt) => context.sign_wallet(opt)?.try_into(),
         VerifyWallet(opt) => context.verify_wallet(opt)?.try_into(),
@@ -27,12 +27,19 @@ struct FirmaOnlineCommands {
 
 #[derive(StructOpt, Debug)]
 enum FirmaOnlineSubcommands {
-    /// Connect a bitcoin node
+    /// Connect a bitcoin node or an electrum server
     Connect(ConnectOptions),
 
     /// Create a new watch-only wallet
     CreateWallet(firma::online::create_wallet::CreateWalletOptions),
 
+    /// Import in the node the wallet addresses up to a derivation index, use `rescan` to find
+    /// funds they already received
+    ExtendRange(firma::online::extend_range::ExtendRangeOptions),
+
+    /// Migrate the legacy watch-only wallet in the node to a descriptor wallet
+    MigrateWallet(firma::online::migrate_wallet::MigrateWalletOptions),
+
     /// Rescan the blockchain, useful when importing an existing wallet
     Rescan(firma::online::rescan::RescanOptions),
 
@@ -42,6 +49,19 @@ enum FirmaOnlineSubcommands {
     /// Create a new transaction as unsigned PSBT
     CreateTx(firma::online::create_tx::CreateTxOptions),
 
+    /// Create a PSBT replacing an unconfirmed transaction with a higher fee (RBF)
+    BumpFee(firma::online::bump_fee::BumpFeeOptions),
+
+    /// Create a PSBT spending outputs of an unconfirmed transaction to raise the fee rate of
+    /// both (CPFP)
+    Cpfp(firma::online::cpfp::CpfpOptions),
+
+    /// Create a PSBT gathering the wallet coins in one output to a new wallet address
+    Consolidate(firma::online::consolidate::ConsolidateOptions),
+
+    /// Create a PSBT sending the whole wallet balance to an address
+    Sweep(firma::online::consolidate::SweepOptions),
+
     /// Combine signed PSBT from offline signers and send the resulting tx
     SendTx(firma::online::send_tx::SendTxOptions),
 
@@ -51,6 +71,27 @@ enum FirmaOnlineSubcommands {
     /// View wallet coins
     ListCoins(WalletNameOptions),
 
+    /// View wallet addresses in a range of derivation indexes
+    ListAddresses(firma::online::list_addresses::ListAddressesOptions),
+
+    /// View wallet transactions
+    ListTransactions(firma::online::list_transactions::ListTransactionsOptions),
+
+    /// Set a label of the wallet (BIP329)
+    Label(firma::online::labels::LabelOptions),
+
+    /// Exclude a coin from the automatic coin selection
+    Freeze(firma::online::labels::FreezeOptions),
+
+    /// Include again a frozen coin in the automatic coin selection
+    Unfreeze(firma::online::labels::FreezeOptions),
+
+    /// Import wallet labels from a BIP329 file
+    ImportLabels(firma::online::labels::LabelsFileOptions),
+
+    /// Export wallet labels to a BIP329 file
+    ExportLabels(firma::online::labels::LabelsFileOptions),
+
     /// Import the file containing a firma json object
     Import(PathOptions),
 }
@@ -85,16 +126,29 @@ fn start() -> Result<Value> {
 
     match subcommand {
         Connect(opt) => {
-            let _ = opt.daemon_opts.make_client(None, context.network)?;
+            opt.daemon_opts.check(context.network)?;
             context.write_daemon_opts(opt.daemon_opts)?.try_into()
         }
         CreateWallet(opt) => context.create_wallet(&opt)?.try_into(),
         GetAddress(opt) => context.get_address(&opt)?.try_into(),
         CreateTx(opt) => context.create_tx(&opt)?.try_into(),
+        BumpFee(opt) => context.bump_fee(&opt)?.try_into(),
+        Cpfp(opt) => context.cpfp(&opt)?.try_into(),
+        Consolidate(opt) => context.consolidate(&opt)?.try_into(),
+        Sweep(opt) => context.sweep(&opt)?.try_into(),
         SendTx(opt) => context.send_tx(&opt)?.try_into(),
         Balance(opt) => context.balance(&opt)?.try_into(),
+        ExtendRange(opt) => context.extend_range(&opt)?.try_into(),
+        MigrateWallet(opt) => Ok(context.migrate_wallet(&opt)?),
         Rescan(opt) => Ok(context.rescan(&opt)?),
         ListCoins(opt) => context.list_coins(&opt)?.try_into(),
+        ListAddresses(opt) => context.list_addresses(&opt)?.try_into(),
+        ListTransactions(opt) => context.list_transactions(&opt)?.try_into(),
+        Label(opt) => context.label(&opt)?.try_into(),
+        Freeze(opt) => context.freeze(&opt, true)?.try_into(),
+        Unfreeze(opt) => context.freeze(&opt, false)?.try_into(),
+        ImportLabels(opt) => context.import_labels(&opt)?.try_into(),
+        ExportLabels(opt) => context.export_labels(&opt)?.try_into(),
         Import(opt) => context.import(&opt),
     }
 }
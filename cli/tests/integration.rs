@@ -53,6 +53,7 @@ fn integration_test() {
         .online_create_wallet(2, &key_names, &name_2of2, true)
         .unwrap();
     assert_eq!(&created_2of2_wallet.id.name, &name_2of2);
+    assert!(created_2of2_wallet.descriptor.contains("/<0;1>/*"));
     assert_eq!(
         firma_2of2
             .online_create_wallet(2, &key_names, &name_2of2, false)
@@ -185,6 +186,7 @@ fn integration_test() {
         .info
         .iter()
         .any(|msg| msg.contains("#Sending_to_a_different_script_type"))); // core generates a different address type
+    assert!(sign_a.outputs.iter().any(|o| o.change)); // change goes to the internal chain
     let sign_b = firma_2of3
         .offline_sign(&psbt_name, &key_names_2of3[1], &name_2of3)
         .unwrap();
@@ -246,6 +248,46 @@ fn integration_test() {
     let expected = balance_2of3_2.confirmed.satoshi - value_sent - sign_a.fee.absolute.unwrap();
     assert_eq!(expected, balance_2of3_3.confirmed.satoshi);
 
+    // create firma 2of2 p2sh nested segwit wallet with the same keys of the 2of2
+    let name_sh_wsh = "nshwsh".to_string();
+    let created_sh_wsh_wallet = firma_2of2
+        .online_create_wallet_script_type(2, &key_names, &name_sh_wsh, true, "sh-wsh")
+        .unwrap();
+    assert!(created_sh_wsh_wallet
+        .descriptor
+        .starts_with("sh(wsh(sortedmulti(2,"));
+    let _result = firma_2of2.offline_sign_wallet(&name_sh_wsh).unwrap();
+    let address_sh_wsh = firma_2of2.online_get_address(&name_sh_wsh).unwrap().address;
+    assert!(address_sh_wsh.script_pubkey().is_p2sh());
+    let fund_sh_wsh = 100_000_000;
+    client_send_to_address(&bitcoind.client, &address_sh_wsh, fund_sh_wsh).unwrap();
+    bitcoind.client.generate_to_address(1, &address).unwrap();
+    let balance_sh_wsh = firma_2of2.online_balance(&name_sh_wsh).unwrap();
+    assert_eq!(fund_sh_wsh, balance_sh_wsh.confirmed.satoshi);
+
+    // create a tx from firma sh-wsh wallet and send back to bitcoind
+    let value_sent = rng.gen_range(1_000, 1_000_000);
+    let recipients = vec![(address.clone(), value_sent)];
+    let psbt_name = rnd_string();
+    let _create_tx = firma_2of2
+        .online_create_tx(recipients, &psbt_name, &name_sh_wsh)
+        .unwrap();
+    let sign_a = firma_2of2
+        .offline_sign(&psbt_name, &r1.id.name, &name_sh_wsh)
+        .unwrap();
+    let sign_b = firma_2of2
+        .offline_sign(&psbt_name, &r2.id.name, &name_sh_wsh)
+        .unwrap();
+    assert_eq!(sign_a.fee.absolute, sign_b.fee.absolute);
+    let sent_tx = firma_2of2
+        .online_send_tx(vec![&psbt_name], &name_sh_wsh)
+        .unwrap();
+    assert!(sent_tx.broadcasted);
+    bitcoind.client.generate_to_address(1, &address).unwrap();
+    let balance_sh_wsh_2 = firma_2of2.online_balance(&name_sh_wsh).unwrap();
+    let expected = fund_sh_wsh - value_sent - sign_a.fee.absolute.unwrap();
+    assert_eq!(expected, balance_sh_wsh_2.confirmed.satoshi);
+
     let coins_output = firma_2of3.online_list_coins(&name_2of3).unwrap();
     assert!(!coins_output.coins.is_empty());
 
@@ -256,7 +298,7 @@ fn integration_test() {
     let list_wallets = firma_2of2.offline_list(Kind::Wallet, None).unwrap();
     assert!(list_wallets.wallets.iter().any(|w| w.id.name == name_2of2));
     let list_psbt = firma_2of2.offline_list(Kind::Psbt, None).unwrap();
-    assert_eq!(list_psbt.psbts.len(), 2);
+    assert_eq!(list_psbt.psbts.len(), 3);
     let result = firma_2of3.online_rescan(&name_2of3); // TODO test restore a wallet, find funds with rescan
     assert!(result.is_ok());
 
@@ -351,9 +393,33 @@ impl FirmaCommand {
         names: &[String],
         wallet_name: &str,
         allow_wallet_already_exists: bool,
+    ) -> Result<Wallet> {
+        self.online_create_wallet_script_type(
+            required_sig,
+            names,
+            wallet_name,
+            allow_wallet_already_exists,
+            "wsh",
+        )
+    }
+
+    pub fn online_create_wallet_script_type(
+        &self,
+        required_sig: u8,
+        names: &[String],
+        wallet_name: &str,
+        allow_wallet_already_exists: bool,
+        script_type: &str,
     ) -> Result<Wallet> {
         let required_sig = format!("{}", required_sig);
-        let mut args = vec!["-r", &required_sig, "--wallet-name", wallet_name];
+        let mut args = vec![
+            "-r",
+            &required_sig,
+            "--wallet-name",
+            wallet_name,
+            "--script-type",
+            script_type,
+        ];
         if allow_wallet_already_exists {
             args.push("--allow-wallet-already-exists");
         }
@@ -7,6 +7,7 @@ use crate::offline::print::PrintOptions;
 use crate::offline::random::RandomOptions;
 use crate::offline::restore::RestoreOptions;
 use crate::offline::sign::SignOptions;
+use crate::offline::sign_wallet::SignWalletOptions;
 use crate::online::WalletNameOptions;
 use crate::*;
 use android_logger::Config;
@@ -99,7 +100,7 @@ fn rust_call(c_str: &CStr) -> Result<CString> {
             context.export(&opts)?
         }
         "sign_wallet" => {
-            let opts: WalletNameOptions = serde_json::from_value(args)?;
+            let opts: SignWalletOptions = serde_json::from_value(args)?;
             let result = context.sign_wallet(&opts)?;
             serde_json::to_value(result)?
         }
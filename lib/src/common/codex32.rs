@@ -0,0 +1,396 @@
+//!
+//! # Codex32
+//!
+//! BIP93 checksummed SSSS-aware BIP32 seeds, shares can be created and verified by hand
+//!
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+const HRP: &str = "ms";
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const HEADER_CHARS: usize = 6;
+const SHORT_CHECKSUM_CHARS: usize = 13;
+const LONG_CHECKSUM_CHARS: usize = 15;
+const MAX_SHORT_DATA_CHARS: usize = 93;
+const MIN_LONG_DATA_CHARS: usize = 96;
+const SECRET_INDEX: u8 = 16; // 's'
+
+/// Codex32 possible errors
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// Human readable part must be `ms`
+    InvalidHrp,
+
+    /// Character not in the bech32 charset or mixed case
+    InvalidChar(char),
+
+    /// Invalid data length or non zero padding
+    InvalidLength,
+
+    /// Checksum failed
+    ChecksumFailed,
+
+    /// Threshold must be 0 for the secret share only or between 2 and 9
+    InvalidThreshold,
+
+    /// Shares have different identifier, threshold or length
+    MismatchedShares,
+
+    /// Less shares than the threshold
+    NotEnoughShares,
+
+    /// Seed must be between 16 and 64 bytes
+    InvalidSeedLength,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidHrp => write!(f, "Codex32 strings must start with ms1"),
+            Error::InvalidChar(c) => write!(f, "Invalid codex32 character {}", c),
+            Error::InvalidLength => write!(f, "Invalid codex32 length"),
+            Error::ChecksumFailed => write!(f, "Codex32 checksum failed"),
+            Error::InvalidThreshold => write!(f, "Invalid codex32 threshold"),
+            Error::MismatchedShares => write!(f, "Codex32 shares are not part of the same set"),
+            Error::NotEnoughShares => write!(f, "Not enough codex32 shares"),
+            Error::InvalidSeedLength => write!(f, "Invalid codex32 seed length"),
+        }
+    }
+}
+
+/// A codex32 share, the data part after `ms1` as 5 bits values
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Share(Vec<u8>);
+
+fn polymod(values: &[u8], long: bool) -> u128 {
+    let (generator, top, mask): ([u128; 5], u32, u128) = if long {
+        (
+            [
+                0x3d59d273535ea62d897,
+                0x7a9becb6361c6c51507,
+                0x543f9b7e6c38d8a2a0e,
+                0x0c577eaeccf1990d13c,
+                0x1887f74f8dc71b10651,
+            ],
+            70,
+            0x3fffffffffffffffff,
+        )
+    } else {
+        (
+            [
+                0x19dc500ce73fde210,
+                0x1bfae00def77fe529,
+                0x1fbd920fffe7bee52,
+                0x1739640bdeee3fdad,
+                0x07729a039cfc75f5a,
+            ],
+            60,
+            0x0fffffffffffffff,
+        )
+    };
+    let mut residue: u128 = 0x23181b3;
+    for v in values {
+        let b = residue >> top;
+        residue = ((residue & mask) << 5) ^ *v as u128;
+        for (i, g) in generator.iter().enumerate() {
+            if (b >> i) & 1 == 1 {
+                residue ^= g;
+            }
+        }
+    }
+    residue
+}
+
+fn target(long: bool) -> u128 {
+    if long {
+        0x43381e570bf4798ab26
+    } else {
+        0x10ce0795c2fd1e62a
+    }
+}
+
+fn checksum_chars(data_len: usize) -> usize {
+    if data_len > MAX_SHORT_DATA_CHARS {
+        LONG_CHECKSUM_CHARS
+    } else {
+        SHORT_CHECKSUM_CHARS
+    }
+}
+
+/// multiplication in GF(32) modulo x^5 + x^3 + 1
+fn mul(mut a: u8, b: u8) -> u8 {
+    let mut result = 0;
+    for i in 0..5 {
+        if (b >> i) & 1 == 1 {
+            result ^= a;
+        }
+        a <<= 1;
+        if a >= 32 {
+            a ^= 41;
+        }
+    }
+    result
+}
+
+fn inv(a: u8) -> u8 {
+    (1..32).find(|b| mul(a, *b) == 1).unwrap_or(0)
+}
+
+fn char_value(c: char) -> Result<u8, Error> {
+    CHARSET
+        .iter()
+        .position(|x| *x as char == c.to_ascii_lowercase())
+        .map(|p| p as u8)
+        .ok_or(Error::InvalidChar(c))
+}
+
+impl Share {
+    /// the secret share with threshold 0 of the given `seed`, `identifier` are 4 bech32 chars
+    pub fn from_seed(seed: &[u8], identifier: &str) -> Result<Self, Error> {
+        if seed.len() < 16 || seed.len() > 64 {
+            return Err(Error::InvalidSeedLength);
+        }
+        let identifier: Vec<u8> = identifier
+            .chars()
+            .map(char_value)
+            .collect::<Result<_, _>>()?;
+        if identifier.len() != 4 {
+            return Err(Error::InvalidLength);
+        }
+        let mut data = vec![char_value('0')?];
+        data.extend(identifier);
+        data.push(SECRET_INDEX);
+
+        let mut acc = 0u32;
+        let mut acc_bits = 0;
+        for byte in seed {
+            acc = (acc << 8) | *byte as u32;
+            acc_bits += 8;
+            while acc_bits >= 5 {
+                acc_bits -= 5;
+                data.push(((acc >> acc_bits) & 31) as u8);
+            }
+        }
+        if acc_bits > 0 {
+            data.push(((acc << (5 - acc_bits)) & 31) as u8);
+        }
+
+        let checksum_len = checksum_chars(data.len() + SHORT_CHECKSUM_CHARS);
+        let long = checksum_len == LONG_CHECKSUM_CHARS;
+        let mut values = data.clone();
+        values.resize(values.len() + checksum_len, 0);
+        let polymod = polymod(&values, long) ^ target(long);
+        for i in (0..checksum_len).rev() {
+            data.push(((polymod >> (5 * i)) & 31) as u8);
+        }
+        Ok(Share(data))
+    }
+
+    /// 0 if the share is the secret of an unshared seed, otherwise the number of shares needed
+    pub fn threshold(&self) -> u8 {
+        (CHARSET[self.0[0] as usize] as char)
+            .to_digit(10)
+            .unwrap_or(0) as u8
+    }
+
+    pub fn identifier(&self) -> String {
+        self.0[1..5]
+            .iter()
+            .map(|v| CHARSET[*v as usize] as char)
+            .collect()
+    }
+
+    pub fn index(&self) -> char {
+        CHARSET[self.0[5] as usize] as char
+    }
+
+    /// the master seed contained in the secret share
+    pub fn seed(&self) -> Result<Vec<u8>, Error> {
+        if self.0[5] != SECRET_INDEX {
+            return Err(Error::NotEnoughShares);
+        }
+        let payload = &self.0[HEADER_CHARS..self.0.len() - checksum_chars(self.0.len())];
+        let mut seed = vec![];
+        let mut acc = 0u32;
+        let mut acc_bits = 0;
+        for v in payload {
+            acc = (acc << 5) | *v as u32;
+            acc_bits += 5;
+            if acc_bits >= 8 {
+                acc_bits -= 8;
+                seed.push((acc >> acc_bits) as u8);
+                acc &= (1 << acc_bits) - 1;
+            }
+        }
+        if acc_bits > 4 {
+            return Err(Error::InvalidLength);
+        }
+        Ok(seed)
+    }
+}
+
+impl Display for Share {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let data: String = self
+            .0
+            .iter()
+            .map(|v| CHARSET[*v as usize] as char)
+            .collect();
+        write!(f, "{}1{}", HRP, data)
+    }
+}
+
+impl FromStr for Share {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.chars().any(|c| c.is_ascii_lowercase()) && s.chars().any(|c| c.is_ascii_uppercase()) {
+            return Err(Error::InvalidChar(
+                s.chars().find(|c| c.is_ascii_uppercase()).unwrap_or(' '),
+            ));
+        }
+        let (hrp, data) = s.rsplit_once('1').ok_or(Error::InvalidHrp)?;
+        if !hrp.eq_ignore_ascii_case(HRP) {
+            return Err(Error::InvalidHrp);
+        }
+        let data: Vec<u8> = data.chars().map(char_value).collect::<Result<_, _>>()?;
+        let long = data.len() >= MIN_LONG_DATA_CHARS;
+        let min = HEADER_CHARS + SHORT_CHECKSUM_CHARS + 26; // 128 bits
+        if data.len() < min || (data.len() > MAX_SHORT_DATA_CHARS && !long) {
+            return Err(Error::InvalidLength);
+        }
+        if polymod(&data, long) != target(long) {
+            return Err(Error::ChecksumFailed);
+        }
+        match (CHARSET[data[0] as usize] as char).to_digit(10) {
+            Some(0) if data[5] != SECRET_INDEX => return Err(Error::InvalidThreshold),
+            Some(0) | Some(2..=9) => (),
+            _ => return Err(Error::InvalidThreshold),
+        }
+        let payload_bits = (data.len() - HEADER_CHARS - checksum_chars(data.len())) * 5;
+        if payload_bits % 8 > 4 {
+            return Err(Error::InvalidLength);
+        }
+        Ok(Share(data))
+    }
+}
+
+/// Recover the secret share from a threshold of `shares`
+pub fn combine(shares: &[Share]) -> Result<Share, Error> {
+    let first = shares.first().ok_or(Error::NotEnoughShares)?;
+    if let Some(secret) = shares.iter().find(|s| s.0[5] == SECRET_INDEX) {
+        return Ok(secret.clone());
+    }
+    let mut distinct: Vec<&Share> = vec![];
+    for share in shares {
+        if share.0.len() != first.0.len() || share.0[..5] != first.0[..5] {
+            return Err(Error::MismatchedShares);
+        }
+        if !distinct.iter().any(|s| s.0[5] == share.0[5]) {
+            distinct.push(share);
+        }
+    }
+    let threshold = first.threshold() as usize;
+    if distinct.len() < threshold {
+        return Err(Error::NotEnoughShares);
+    }
+    let distinct = &distinct[..threshold];
+
+    // lagrange interpolation at the secret index of every char
+    let x = SECRET_INDEX;
+    let indexes: Vec<u8> = distinct.iter().map(|s| s.0[5]).collect();
+    let mut n = 1;
+    let mut weights = vec![];
+    for i in indexes.iter() {
+        n = mul(n, i ^ x);
+        let mut m = 1;
+        for j in indexes.iter() {
+            m = mul(m, (if i == j { x } else { *i }) ^ j);
+        }
+        weights.push(m);
+    }
+    let weights: Vec<u8> = weights.iter().map(|m| mul(n, inv(*m))).collect();
+    let mut result = vec![0u8; first.0.len()];
+    for (share, w) in distinct.iter().zip(weights.iter()) {
+        for (r, v) in result.iter_mut().zip(share.0.iter()) {
+            *r ^= mul(*w, *v);
+        }
+    }
+    let long = result.len() >= MIN_LONG_DATA_CHARS;
+    if polymod(&result, long) != target(long) {
+        return Err(Error::ChecksumFailed);
+    }
+    Ok(Share(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::hex::{FromHex, ToHex};
+
+    #[test]
+    fn test_codex32_vectors() {
+        // BIP93 test vectors
+        let share = Share::from_str("ms10testsxxxxxxxxxxxxxxxxxxxxxxxxxx4nzvca9cmczlw").unwrap();
+        assert_eq!(share.threshold(), 0);
+        assert_eq!(share.identifier(), "test");
+        assert_eq!(share.index(), 's');
+        assert_eq!(
+            share.seed().unwrap().to_hex(),
+            "318c6318c6318c6318c6318c6318c631"
+        );
+
+        let shares = [
+            Share::from_str("MS12NAMEA320ZYXWVUTSRQPNMLKJHGFEDCAXRPP870HKKQRM").unwrap(),
+            Share::from_str("MS12NAMECACDEFGHJKLMNPQRSTUVWXYZ023FTR2GDZMPY6PN").unwrap(),
+        ];
+        assert_eq!(combine(&shares[..1]), Err(Error::NotEnoughShares));
+        let secret = combine(&shares).unwrap();
+        assert_eq!(
+            secret.to_string(),
+            "ms12names6xqguzttxkeqnjsjzv4jv3nz5k3kwgsphuh6evw"
+        );
+        assert_eq!(
+            secret.seed().unwrap().to_hex(),
+            "d1808e096b35b209ca12132b264662a5"
+        );
+
+        let long = Share::from_str("MS100C8VSM32ZXFGUHPCHTLUPZRY9X8GF2TVDW0S3JN54KHCE6MUA7LQPZYGSFJD6AN074RXVCEMLH8WU3TK925ACDEFGHJKLMNPQRSTUVWXY06FHPV80UNDVARHRAK").unwrap();
+        assert_eq!(long.seed().unwrap().to_hex(), "dc5423251cb87175ff8110c8531d0952d8d73e1194e95b5f19d6f9df7c01111104c9baecdfea8cccc677fb9ddc8aec5553b86e528bcadfdcc201c17c638c47e9");
+
+        assert_eq!(
+            Share::from_str("ms10testsxxxxxxxxxxxxxxxxxxxxxxxxxx4nzvca9cmczlx"),
+            Err(Error::ChecksumFailed)
+        );
+        assert_eq!(
+            Share::from_str("ms10testsxxxxxxxxxxxxxxxxxxxxxxxxxx4nzvca9cmczlW"),
+            Err(Error::InvalidChar('W'))
+        );
+        assert_eq!(
+            Share::from_str("xs10testsxxxxxxxxxxxxxxxxxxxxxxxxxx4nzvca9cmczlw"),
+            Err(Error::InvalidHrp)
+        );
+    }
+
+    #[test]
+    fn test_codex32_from_seed() {
+        for hex in [
+            "318c6318c6318c6318c6318c6318c631",
+            "dc5423251cb87175ff8110c8531d0952d8d73e1194e95b5f19d6f9df7c01111104c9baecdfea8cccc677fb9ddc8aec5553b86e528bcadfdcc201c17c638c47e9",
+        ]
+        .iter()
+        {
+            let seed = Vec::<u8>::from_hex(hex).unwrap();
+            let share = Share::from_seed(&seed, "test").unwrap();
+            let parsed = Share::from_str(&share.to_string()).unwrap();
+            assert_eq!(parsed, share);
+            assert_eq!(parsed.seed().unwrap(), seed);
+        }
+        let seed = Vec::<u8>::from_hex("318c6318c6318c6318c6318c6318c631").unwrap();
+        assert_eq!(
+            Share::from_seed(&seed[..15], "test"),
+            Err(Error::InvalidSeedLength)
+        );
+    }
+}
@@ -3,6 +3,10 @@ use crate::list::ListOptions;
 use crate::offline::decrypt::EncryptionKey;
 use crate::offline::sign::find_or_create;
 use crate::offline::sign::get_psbt_name;
+use crate::offline::Scheme;
+use crate::online::backend::BackendKind;
+use crate::online::electrum::ElectrumClient;
+use crate::psbt_v2::psbt_v2_to_v0;
 use crate::*;
 use bitcoin::blockdata::constants::genesis_block;
 use bitcoin::consensus::deserialize;
@@ -74,13 +78,24 @@ impl_context!(OfflineContext);
 
 #[derive(StructOpt, Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct DaemonOpts {
-    /// Bitcoin node rpc url
+    /// Bitcoin node rpc url, or electrum server `host:port` with the electrum backend
     #[structopt(long)]
     pub url: String,
 
-    /// Bitcoin node cookie file
+    /// Bitcoin node cookie file, required by the bitcoind backend
     #[structopt(long)]
-    pub cookie_file: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cookie_file: Option<PathBuf>,
+
+    /// Source of chain data (bitcoind, electrum)
+    #[structopt(long, default_value = "bitcoind")]
+    #[serde(default)]
+    pub backend: BackendKind,
+
+    /// Allow the unencrypted connection to an electrum server not on the loopback interface
+    #[structopt(long)]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub insecure: bool,
 }
 
 impl DaemonOpts {
@@ -92,7 +107,8 @@ impl DaemonOpts {
             None => self.url.to_string(),
         };
         debug!("creating client with url {}", url);
-        let client = Client::new(&url, Auth::CookieFile(self.cookie_file.clone()))?;
+        let cookie_file = self.cookie_file.clone().ok_or(Error::MissingDaemonOpts)?;
+        let client = Client::new(&url, Auth::CookieFile(cookie_file))?;
         let node_genesis = client.get_block_hash(0)?;
         let firma_genesis = genesis_block(network).block_hash();
         if node_genesis != firma_genesis {
@@ -103,6 +119,16 @@ impl DaemonOpts {
         }
         Ok(client)
     }
+
+    /// connect to the daemon checking it's on `network`
+    pub fn check(&self, network: Network) -> Result<()> {
+        match self.backend {
+            BackendKind::Bitcoind => self.make_client(None, network).map(|_| ()),
+            BackendKind::Electrum => {
+                ElectrumClient::new(&self.url, network, self.insecure).map(|_| ())
+            }
+        }
+    }
 }
 
 impl Context {
@@ -141,7 +167,7 @@ impl Context {
     }
 
     pub fn write_keys(&self, master_key: &MasterSecret) -> Result<()> {
-        self.write(master_key)?;
+        self.write(&master_key.locked()?)?;
         let public: DescriptorPublicKey = master_key.as_desc_pub_key()?;
         self.write(&public)
     }
@@ -187,13 +213,73 @@ impl Context {
     ) -> Result<Vec<miniscript::DescriptorPublicKey>> {
         let mut result = vec![];
         for name in names {
-            let id = Identifier::new(self.network, Kind::DescriptorPublicKey, name);
-            let json: DescriptorPublicKey = id.read(&self.datadir, &self.encryption_key)?;
+            let json = self.read_desc_pub_key_from_name(name)?;
             result.push(json.key()?);
         }
         Ok(result)
     }
 
+    /// `name:scheme:account` and `name:account` refer to an additional account derived with
+    /// `derive-key`, the latter only if the account number is derived with one scheme
+    fn read_desc_pub_key_from_name(&self, name: &str) -> Result<DescriptorPublicKey> {
+        let invalid = || Error::Generic(format!("invalid account in {}", name));
+        let parts: Vec<&str> = name.split(':').collect();
+        match parts[..] {
+            [key_name] => {
+                let id = Identifier::new(self.network, Kind::DescriptorPublicKey, key_name);
+                id.read(&self.datadir, &self.encryption_key)
+            }
+            [key_name, scheme, account] => {
+                let scheme: Scheme = scheme.parse().map_err(|_| invalid())?;
+                let account = account.parse().map_err(|_| invalid())?;
+                let id = Identifier::new(self.network, Kind::DescriptorPublicKey, key_name)
+                    .with_account(scheme, account);
+                id.read(&self.datadir, &self.encryption_key)
+            }
+            [key_name, account] => {
+                let account: u32 = account.parse().map_err(|_| invalid())?;
+                let mut found: Vec<_> = self
+                    .read_all_desc_pub_keys(key_name)?
+                    .into_iter()
+                    .filter(|k| k.id.account == Some(account))
+                    .collect();
+                match found.len() {
+                    0 => Err(format!("account {} of {} not found", account, key_name).into()),
+                    1 => Ok(found.remove(0)),
+                    _ => Err(format!(
+                        "account {} of {} is derived with multiple schemes, use `name:scheme:account`",
+                        account, key_name
+                    )
+                    .into()),
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// all the descriptor public keys of the key `name`, the one created with the key and the
+    /// additional accounts
+    pub fn read_all_desc_pub_keys(&self, name: &str) -> Result<Vec<DescriptorPublicKey>> {
+        let id = Identifier::new(self.network, Kind::DescriptorPublicKey, name);
+        let mut result = vec![id.read(&self.datadir, &self.encryption_key)?];
+        let path = id.as_path_buf(&self.datadir, false)?;
+        let dir = path.parent().ok_or(Error::MissingKey)?;
+        for entry in std::fs::read_dir(dir)? {
+            let file_name = entry?.file_name();
+            let account = file_name
+                .to_str()
+                .and_then(|f| f.strip_prefix("descriptor_public_key_"))
+                .and_then(|f| f.strip_suffix(".json"))
+                .and_then(|f| f.rsplit_once('_'))
+                .and_then(|(scheme, account)| Some((scheme.parse().ok()?, account.parse().ok()?)));
+            if let Some((scheme, account)) = account {
+                let id = id.with_account(scheme, account);
+                result.push(id.read(&self.datadir, &self.encryption_key)?);
+            }
+        }
+        Ok(result)
+    }
+
     pub fn read_encryption_key(&mut self) -> Result<()> {
         // read encryption key from stdin and initialize encryption_key field
         let mut buffer = vec![];
@@ -209,6 +295,7 @@ impl Context {
             .psbt
             .as_bytes()
             .map_err(|_| Error::PsbtBadStringEncoding(opt.psbt.kind()))?;
+        let bytes = psbt_v2_to_v0(&bytes)?;
         let mut psbt: BitcoinPsbt = deserialize(&bytes).map_err(Error::PsbtCannotDeserialize)?;
 
         self.save_psbt(&mut psbt)?;
@@ -341,6 +428,9 @@ pub mod tests {
             .context
             .create_key(&RandomOptions {
                 key_name: key_name.to_string(),
+                language: Default::default(),
+                derivation: Default::default(),
+                passphrase: None,
             })
             .unwrap();
         assert!(
@@ -40,6 +40,9 @@ pub enum Error {
     IncompatibleNetworks,
     IncompatibleGenesis { node: BlockHash, firma: BlockHash },
     Mnemonic(crate::common::mnemonic::Error),
+    Slip39(crate::common::slip39::Error),
+    MiniscriptPsbt(miniscript::psbt::Error),
+    Codex32(crate::common::codex32::Error),
     PsbtNotChangedAfterMerge,
     PsbtBadStringEncoding(String),
     MaybeEncryptedWrongState,
@@ -52,6 +55,11 @@ pub enum Error {
     WalletSignatureNotVerified,
     WrongKeyType,
     MissingUtxoAndNotFinalized,
+    PassphraseRequired,
+    WrongPassphrase,
+    PassphraseWithoutMnemonic,
+    InsufficientFunds { available: u64, needed: u64 },
+    Electrum(String),
 
     // External
     BitcoinRpc(bitcoincore_rpc::Error),
@@ -77,6 +85,7 @@ pub enum Error {
     Miniscript(miniscript::Error),
     MiniscriptDescriptor(descriptor::DescriptorKeyParseError),
     MiniscriptConversion(descriptor::ConversionError),
+    MiniscriptCompiler(miniscript::policy::compiler::CompilerError),
     Bmp(qr_code::bmp_monochrome::BmpError),
     Encryption(aes_gcm_siv::aead::Error),
     PsbtCannotDeserialize(bitcoin::consensus::encode::Error),
@@ -114,10 +123,17 @@ impl_error!(std::ffi::NulError, Nul);
 impl_error!(std::num::ParseIntError, ParseInt);
 impl_error!(miniscript::Error, Miniscript);
 impl_error!(crate::common::mnemonic::Error, Mnemonic);
+impl_error!(crate::common::slip39::Error, Slip39);
+impl_error!(miniscript::psbt::Error, MiniscriptPsbt);
+impl_error!(crate::common::codex32::Error, Codex32);
 impl_error!(qr_code::bmp_monochrome::BmpError, Bmp);
 impl_error!(aes_gcm_siv::aead::Error, Encryption);
 impl_error!(descriptor::DescriptorKeyParseError, MiniscriptDescriptor);
 impl_error!(descriptor::ConversionError, MiniscriptConversion);
+impl_error!(
+    miniscript::policy::compiler::CompilerError,
+    MiniscriptCompiler
+);
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
@@ -186,6 +202,17 @@ impl fmt::Display for Error {
             Error::MissingUtxoAndNotFinalized => {
                 write!(f, "witness_utxo and non_witness_utxo are both None")
             }
+            Error::PassphraseRequired => write!(f, "The key requires the BIP39 passphrase"),
+            Error::WrongPassphrase => write!(f, "The BIP39 passphrase does not match the key"),
+            Error::PassphraseWithoutMnemonic => {
+                write!(f, "A BIP39 passphrase can be used only with a mnemonic")
+            }
+            Error::InsufficientFunds { available, needed } => write!(
+                f,
+                "Insufficient funds, available {} sat but {} sat needed",
+                available, needed
+            ),
+            Error::Electrum(e) => write!(f, "Electrum server error {}", e),
 
             Error::BitcoinRpc(e) => write!(f, "{:?}", e),
             Error::Serde(e) => write!(f, "{:?}", e),
@@ -210,7 +237,11 @@ impl fmt::Display for Error {
             Error::Miniscript(e) => write!(f, "{:?}", e),
             Error::MiniscriptDescriptor(e) => write!(f, "{:?}", e),
             Error::MiniscriptConversion(e) => write!(f, "{:?}", e),
+            Error::MiniscriptCompiler(e) => write!(f, "{}", e),
             Error::Mnemonic(e) => write!(f, "{:?}", e),
+            Error::Slip39(e) => write!(f, "{}", e),
+            Error::MiniscriptPsbt(e) => write!(f, "{}", e),
+            Error::Codex32(e) => write!(f, "{}", e),
             Error::Bmp(e) => write!(f, "{:?}", e),
         }
     }
@@ -1,6 +1,8 @@
 use crate::common::entities::identifier::Identifier;
 use crate::offline::decrypt::decrypt;
+use crate::offline::descriptor::is_multipath;
 use crate::online::PathOptions;
+use crate::psbt_v2::psbt_to_v2;
 use crate::*;
 use log::debug;
 use serde::{Deserialize, Serialize};
@@ -16,6 +18,11 @@ pub struct ExportOptions {
     /// The name of the object to export
     #[structopt(long)]
     pub name: String,
+
+    /// Export a PSBT in the BIP370 version 2 form
+    #[structopt(long)]
+    #[serde(default)]
+    pub psbt_v2: bool,
 }
 
 impl Context {
@@ -32,6 +39,7 @@ impl Context {
             Kind::Wallet => self.write(&from_value::<Wallet>(c)?)?,
             Kind::WalletIndexes => self.write(&from_value::<WalletIndexes>(c)?)?,
             Kind::WalletSignature => self.write(&from_value::<WalletSignature>(c)?)?,
+            Kind::WalletLabels => self.write(&from_value::<WalletLabels>(c)?)?,
             Kind::MasterSecret => self.write(&from_value::<MasterSecret>(c)?)?,
             Kind::DescriptorPublicKey => self.write(&from_value::<DescriptorPublicKey>(c)?)?,
             Kind::Psbt => self.write(&from_value::<Psbt>(c)?)?,
@@ -42,15 +50,40 @@ impl Context {
     pub fn export(&self, opt: &ExportOptions) -> Result<Value> {
         debug!("export {:?}", opt);
         let id = Identifier::new(self.network, opt.kind, &opt.name);
-        id.read(&self.datadir, &self.encryption_key)
+        let mut value: Value = id.read(&self.datadir, &self.encryption_key)?;
+        if let Kind::Wallet = opt.kind {
+            // coordinators not supporting BIP389 need the single path descriptors
+            let wallet: Wallet = from_value(value.clone())?;
+            if is_multipath(&wallet.descriptor) {
+                value["receive_descriptor"] = wallet.receive_descriptor()?.into();
+                value["change_descriptor"] = wallet.change_descriptor()?.into();
+            }
+        }
+        if opt.psbt_v2 {
+            if opt.kind != Kind::Psbt {
+                return Err("`psbt_v2` applies only to PSBT kind".into());
+            }
+            let psbt: Psbt = from_value(value.clone())?;
+            value["psbt"] = base64::encode(psbt_to_v2(&psbt.psbt()?)?).into();
+        }
+        if let Kind::MasterSecret = opt.kind {
+            let master_secret: MasterSecret = from_value(value.clone())?;
+            if let Some(secret) = master_secret.as_codex32()? {
+                value["codex32"] = secret.to_string().into();
+            }
+        }
+        Ok(value)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::context::tests::TestContext;
+    use crate::import_export::ExportOptions;
+    use crate::online::create_wallet::DEFAULT_IMPORT_RANGE;
     use crate::online::PathOptions;
-    use crate::{Identifier, Kind, WalletIndexes};
+    use crate::{Identifier, Kind, Wallet, WalletIndexes};
+    use bitcoin::Network;
 
     #[test]
     fn test_import() {
@@ -58,6 +91,8 @@ mod tests {
         let i = WalletIndexes {
             id: Identifier::new_test(Kind::WalletIndexes),
             main: 0,
+            change: 0,
+            imported: DEFAULT_IMPORT_RANGE,
         };
         context.write(&i).unwrap();
         let path = i.id.as_path_buf(&context.datadir, false).unwrap();
@@ -67,4 +102,22 @@ mod tests {
         let read = second_context.read::<WalletIndexes>(&i.id.name).unwrap();
         assert_eq!(i, read);
     }
+
+    #[test]
+    fn test_export_multipath_wallet() {
+        let context = TestContext::default();
+        let wallet = Wallet::new("wsh(sortedmulti(1,[a2ebe04e/48'/1'/0'/2']tpubDEXDRpvW2srXCSjAvC36zYkSE3jxT1wf7JXDo35Ln4NZpmaMNhq8o9coH9U9BQ5bAN4WDGxXV9d426iYKGorFF5wvv4Wv63cZsCotiXGGkD/<0;1>/*))", Network::Testnet);
+        context.write(&wallet).unwrap();
+        let opt = ExportOptions {
+            kind: Kind::Wallet,
+            name: wallet.id.name.clone(),
+            psbt_v2: false,
+        };
+        let value = context.export(&opt).unwrap();
+        assert_eq!(value["descriptor"], wallet.descriptor);
+        let receive = value["receive_descriptor"].as_str().unwrap();
+        assert!(receive.contains("/0/*))#"));
+        let change = value["change_descriptor"].as_str().unwrap();
+        assert!(change.contains("/1/*))#"));
+    }
 }
@@ -80,7 +80,12 @@ mod tests {
     #[test]
     fn test_list() {
         let key_name = "list".to_string();
-        let rand_opts = RandomOptions { key_name };
+        let rand_opts = RandomOptions {
+            key_name,
+            language: Default::default(),
+            derivation: Default::default(),
+            passphrase: None,
+        };
         let context = TestContext::default();
         let context = &context.context;
         let _key = context.create_key(&rand_opts).unwrap();
@@ -16,15 +16,16 @@
 //! # BIP39 mnemonic
 //!
 //! Mnemonic code for generating deterministic keys
-//! mnemonic in english
+//! mnemonic in any of the BIP39 languages
 //!
 use bitcoin::hashes::{sha256, sha512, Hash, HashEngine, Hmac, HmacEngine};
 use bitcoin::util::bip158::{BitStreamReader, BitStreamWriter};
 use core::fmt::{Display, Formatter};
 use rand::{thread_rng, RngCore};
 use serde::{Deserialize, Serialize};
-use std::io::Cursor;
+use std::io::{self, Cursor};
 use std::str::FromStr;
+use unicode_normalization::UnicodeNormalization;
 
 /// Represent the BIP39 mnemonic as a vector of values representing 11 bits each
 /// It is conveniently stored in usize to be used as index of the WORDS const vec
@@ -50,32 +51,121 @@ pub enum Error {
     CannotExtend,
 }
 
+/// BIP39 wordlists
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Language {
+    English,
+    ChineseSimplified,
+    ChineseTraditional,
+    Czech,
+    French,
+    Italian,
+    Japanese,
+    Korean,
+    Portuguese,
+    Spanish,
+}
+
+// `#[default]` on enum variants requires rust 1.62
+#[allow(clippy::derivable_impls)]
+impl Default for Language {
+    fn default() -> Self {
+        Language::English
+    }
+}
+
+impl Language {
+    /// All the languages, in the order they are tried when detecting the language of a mnemonic
+    pub const ALL: [Language; 10] = [
+        Language::English,
+        Language::ChineseSimplified,
+        Language::ChineseTraditional,
+        Language::Czech,
+        Language::French,
+        Language::Italian,
+        Language::Japanese,
+        Language::Korean,
+        Language::Portuguese,
+        Language::Spanish,
+    ];
+
+    /// The 2048 words of the language, NFKD normalized
+    pub fn words(&self) -> Vec<&'static str> {
+        let list = match self {
+            Language::English => return WORDS.to_vec(),
+            Language::ChineseSimplified => include_str!("wordlist/chinese_simplified.txt"),
+            Language::ChineseTraditional => include_str!("wordlist/chinese_traditional.txt"),
+            Language::Czech => include_str!("wordlist/czech.txt"),
+            Language::French => include_str!("wordlist/french.txt"),
+            Language::Italian => include_str!("wordlist/italian.txt"),
+            Language::Japanese => include_str!("wordlist/japanese.txt"),
+            Language::Korean => include_str!("wordlist/korean.txt"),
+            Language::Portuguese => include_str!("wordlist/portuguese.txt"),
+            Language::Spanish => include_str!("wordlist/spanish.txt"),
+        };
+        list.lines().collect()
+    }
+}
+
+impl FromStr for Language {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "english" => Ok(Language::English),
+            "chinese-simplified" => Ok(Language::ChineseSimplified),
+            "chinese-traditional" => Ok(Language::ChineseTraditional),
+            "czech" => Ok(Language::Czech),
+            "french" => Ok(Language::French),
+            "italian" => Ok(Language::Italian),
+            "japanese" => Ok(Language::Japanese),
+            "korean" => Ok(Language::Korean),
+            "portuguese" => Ok(Language::Portuguese),
+            "spanish" => Ok(Language::Spanish),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("({}) valid values are: english, chinese-simplified, chinese-traditional, czech, french, italian, japanese, korean, portuguese, spanish", s),
+            )),
+        }
+    }
+}
+
 impl FromStr for Mnemonic {
     type Err = Error;
 
-    /// Parse a string into a mnemonic, if valid according to the rules
+    /// Parse a string into a mnemonic, if valid according to the rules, the language is detected
+    /// by trying every wordlist containing all the words
     fn from_str(s: &str) -> Result<Mnemonic, Error> {
-        let words: Vec<_> = s.split(' ').collect();
+        let normalized: String = s.nfkd().collect();
+        let words: Vec<_> = normalized.split_whitespace().collect();
         if words.len() < 6 || words.len() % 6 != 0 {
             return Err(Error::MnemonicNotDivisibleBy6);
         }
-        let mut data = Vec::new();
-        let mut writer = BitStreamWriter::new(&mut data);
-        for word in &words {
-            if let Ok(idx) = WORDS.binary_search(word) {
+        let mut result = Err(Error::UnknownWord);
+        for language in Language::ALL.iter() {
+            let list = language.words();
+            let indexes: Option<Vec<_>> = words
+                .iter()
+                .map(|word| list.iter().position(|w| w == word))
+                .collect();
+            let indexes = match indexes {
+                Some(indexes) => indexes,
+                None => continue,
+            };
+            let mut data = Vec::new();
+            let mut writer = BitStreamWriter::new(&mut data);
+            for idx in indexes {
                 writer.write(idx as u64, 11).unwrap();
-            } else {
-                return Err(Error::UnknownWord);
             }
+            writer.flush().unwrap();
+            let l = data.len();
+            let (payload, checksum) = data.split_at(l - if l > 33 { 2 } else { 1 });
+            if Self::checksum(payload).as_slice() == checksum {
+                return Ok(Mnemonic(words.join(" ")));
+            }
+            result = Err(Error::ChecksumFailed);
         }
-        writer.flush().unwrap();
-        let l = data.len();
-        let (payload, checksum) = data.split_at(l - if l > 33 { 2 } else { 1 });
-        if Self::checksum(payload).as_slice() != checksum {
-            return Err(Error::ChecksumFailed);
-        }
-
-        Ok(Mnemonic(s.to_string()))
+        result
     }
 }
 
@@ -91,8 +181,11 @@ impl Mnemonic {
     /// it is the pbkdf2 algorithm specific for BIP39
     /// with a single block and using c = 2048 and Hmac with Sha512
     pub fn to_seed(&self, pd_passphrase: Option<&str>) -> Seed {
-        let orig_engine: HmacEngine<sha512::Hash> = HmacEngine::new(self.to_string().as_bytes());
-        let passphrase = "mnemonic".to_owned() + pd_passphrase.unwrap_or("");
+        let mnemonic: String = self.0.nfkd().collect();
+        let orig_engine: HmacEngine<sha512::Hash> = HmacEngine::new(mnemonic.as_bytes());
+        let passphrase: String = format!("mnemonic{}", pd_passphrase.unwrap_or(""))
+            .nfkd()
+            .collect();
         let salt = passphrase.as_bytes();
 
         let mut engine = orig_engine.clone();
@@ -131,8 +224,13 @@ impl Mnemonic {
         Self::new(random.as_slice())
     }
 
-    /// create a mnemonic for some data
+    /// create an english mnemonic for some data
     pub fn new(data: &[u8]) -> Result<Mnemonic, Error> {
+        Self::new_in(data, Language::English)
+    }
+
+    /// create a mnemonic for some data with the words of `language`
+    pub fn new_in(data: &[u8], language: Language) -> Result<Mnemonic, Error> {
         if data.len() % 4 != 0 {
             return Err(Error::DataNotDivisibleBy4);
         }
@@ -141,9 +239,10 @@ impl Mnemonic {
         let mut cursor = Cursor::new(&with_checksum);
         let mut reader = BitStreamReader::new(&mut cursor);
         let mlen = data.len() * 3 / 4;
+        let words = language.words();
         let mut mnemonic = Vec::new();
         for _ in 0..mlen {
-            mnemonic.push(WORDS[reader.read(11).unwrap() as usize].to_string());
+            mnemonic.push(words[reader.read(11).unwrap() as usize]);
         }
         Ok(Mnemonic(mnemonic.join(" ")))
     }
@@ -225,6 +324,96 @@ mod test {
         )
         .is_err());
     }
+
+    #[test]
+    fn test_mnemonic_japanese() {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("test_data/BIP39_JP.json");
+        let mut file = File::open(d).unwrap();
+        let mut data = String::new();
+        file.read_to_string(&mut data).unwrap();
+
+        let json: Value = serde_json::from_str(&data).unwrap();
+        let tests = json.as_array().unwrap();
+        assert_eq!(tests.len(), 24);
+
+        for t in tests {
+            let values = t.as_array().unwrap();
+            let data = Vec::from_hex(values[0].as_str().unwrap()).unwrap();
+            let m = values[1].as_str().unwrap();
+            let passphrase = values[2].as_str().unwrap();
+            let expected_seed = Vec::from_hex(values[3].as_str().unwrap()).unwrap();
+
+            let mnemonic = Mnemonic::from_str(m).unwrap();
+            assert_eq!(mnemonic.to_seed(Some(passphrase)).0, expected_seed);
+            let created = Mnemonic::new_in(&data, Language::Japanese).unwrap();
+            assert_eq!(created, mnemonic);
+            assert_eq!(created.to_seed(Some(passphrase)).0, expected_seed);
+        }
+    }
+
+    #[test]
+    fn test_mnemonic_nfkd() {
+        // https://github.com/MetacoSA/NBitcoin/blob/master/NBitcoin.Tests/data/bip39_vectors.es.json
+        // https://github.com/MetacoSA/NBitcoin/blob/master/NBitcoin.Tests/data/bip39_vectors.en.json
+        // the french vector is not included, it's a 15 words mnemonic which is not supported
+        let tests = [
+            (
+                Language::Spanish,
+                "camello pomelo toque oponer urgente lástima merengue cutis tirón pudor pomo barco",
+                "el español se habla en muchos países",
+                "67a2cf87b9d110dd5210275fd4d7a107a0a0dd9446e02f3822f177365786ae440b8873693c88f732834af90785753d989a367f7094230901b204c567718ce6be",
+            ),
+            (
+                Language::English,
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+                "nullius　à　nym.zone ¹teſts² English",
+                "61f3aa13adcf5f4b8661fc062501d67eca3a53fc0ed129076ad7a22983b6b5ed0e84e47b24cff23b7fca57e127f62f28c1584ed487872d4bfbc773257bdbc434",
+            ),
+        ];
+        for (language, m, passphrase, expected_seed) in tests.iter() {
+            let expected_seed = Vec::from_hex(expected_seed).unwrap();
+            for m in [m.to_string(), m.nfkd().collect()].iter() {
+                let mnemonic = Mnemonic::from_str(m).unwrap();
+                let words = language.words();
+                assert!(mnemonic.to_string().split(' ').all(|w| words.contains(&w)));
+                assert_eq!(mnemonic.to_seed(Some(passphrase)).0, expected_seed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mnemonic_languages() {
+        for language in Language::ALL.iter() {
+            let words = language.words();
+            assert_eq!(words.len(), 2048);
+            let unique: std::collections::HashSet<_> = words.iter().collect();
+            assert_eq!(unique.len(), 2048, "{:?} has duplicates", language);
+
+            for data in [[0u8; 16], [0x7fu8; 16], [0x80u8; 16], [0xffu8; 16]].iter() {
+                let mnemonic = Mnemonic::new_in(data, *language).unwrap();
+                let parsed = Mnemonic::from_str(&mnemonic.to_string()).unwrap();
+                assert_eq!(parsed, mnemonic);
+                let seed = mnemonic.to_seed(Some("TREZOR"));
+                assert_eq!(parsed.to_seed(Some("TREZOR")), seed);
+                let composed: String = mnemonic.to_string().nfc().collect();
+                assert_eq!(Mnemonic::from_str(&composed).unwrap(), mnemonic);
+            }
+        }
+        assert_eq!(
+            Mnemonic::new_in(&[0u8; 16], Language::Spanish)
+                .unwrap()
+                .to_string(),
+            "ábaco ábaco ábaco ábaco ábaco ábaco ábaco ábaco ábaco ábaco ábaco abierto"
+                .nfkd()
+                .collect::<String>()
+        );
+        assert_eq!(
+            Language::from_str("chinese-simplified").unwrap(),
+            Language::ChineseSimplified
+        );
+        assert!(Language::from_str("klingon").is_err());
+    }
 }
 
 const WORDS: [&str; 2048] = [
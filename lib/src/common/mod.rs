@@ -5,16 +5,20 @@ use bitcoin::consensus::{deserialize, serialize};
 use bitcoin::util::key;
 use bitcoin::{Network, Script, Transaction};
 use log::{LevelFilter, Metadata, Record};
+use miniscript::{Miniscript, Segwitv0};
 use std::fs::OpenOptions;
 use std::io::BufWriter;
 use std::io::Write;
 
+pub mod codex32;
 pub mod context;
 pub mod error;
 pub mod import_export;
 pub mod list;
 pub mod mnemonic;
+pub mod psbt_v2;
 pub mod qr;
+pub mod slip39;
 
 static LOGGER: SimpleLogger = SimpleLogger;
 
@@ -55,8 +59,9 @@ pub fn init_logger() {
         .expect("cannot initialize logging");
 }
 
+/// decode the PSBT, BIP370 version 2 PSBTs are converted to version 0
 pub fn psbt_from_base64(s: &str) -> Result<(Vec<u8>, BitcoinPsbt)> {
-    let bytes = base64::decode(s)?;
+    let bytes = psbt_v2::psbt_v2_to_v0(&base64::decode(s)?)?;
     let psbt = deserialize(&bytes)?;
     Ok((bytes, psbt))
 }
@@ -74,17 +79,36 @@ pub fn estimate_weight(psbt: &BitcoinPsbt) -> Result<usize> {
     for input in psbt.inputs.iter() {
         let (script, factor) = match (&input.redeem_script, &input.witness_script) {
             (Some(redeem_script), None) => (redeem_script, 4),
+            (Some(redeem_script), Some(witness_script)) => {
+                // p2sh nested segwit, the script_sig pushes the redeem script
+                spending_weight += (redeem_script.len() + 1) * 4;
+                (witness_script, 1)
+            }
             (_, Some(witness_script)) => (witness_script, 1), // factor=1 for segwit discount
             _ => return Err("both redeem and witness script are None".into()),
         };
-        //TODO signature are less in NofM where N<M
-        let current = script.len() + expected_signatures(script)? * 72; // using 72 as average signature size
+        let current = script.len() + expected_satisfaction_size(script)?;
         spending_weight += current * factor;
     }
 
     Ok(unsigned_weight + spending_weight)
 }
 
+/// size of the elements satisfying the script, for scripts other than multisig the script is
+/// parsed as miniscript to compute its maximum satisfaction size
+fn expected_satisfaction_size(script: &Script) -> Result<usize> {
+    let is_multi = script.as_bytes().last() == Some(&opcodes::all::OP_CHECKMULTISIG.into_u8());
+    if !is_multi {
+        if let Ok(miniscript) = Miniscript::<key::PublicKey, Segwitv0>::parse(script) {
+            if let Ok(size) = miniscript.max_satisfaction_size() {
+                return Ok(size);
+            }
+        }
+    }
+    //TODO signature are less in NofM where N<M
+    Ok(expected_signatures(script)? * 72) // using 72 as average signature size
+}
+
 fn expected_signatures(script: &Script) -> Result<usize> {
     let bytes = script.as_bytes();
     Ok(
@@ -161,9 +185,12 @@ pub fn check_compatibility(n1: Network, n2: Network) -> Result<()> {
 
 #[cfg(test)]
 pub mod tests {
-    use crate::{check_compatibility, strip_witness};
+    use crate::common::{expected_satisfaction_size, expected_signatures};
+    use crate::{check_compatibility, estimate_weight, strip_witness, BitcoinPsbt};
     use bitcoin::consensus::deserialize;
-    use bitcoin::{Network, Transaction};
+    use bitcoin::util::key;
+    use bitcoin::{Network, Script, Transaction};
+    use miniscript::{Miniscript, Segwitv0};
     use rand::distributions::Alphanumeric;
     use rand::{thread_rng, Rng};
 
@@ -190,6 +217,36 @@ pub mod tests {
         assert!(check_compatibility(Network::Testnet, Network::Bitcoin).is_err());
     }
 
+    #[test]
+    fn test_estimate_weight_miniscript() {
+        let script = "2102e3829e8c07369beab86113f125021ea9bb952ca6a92b25bb653807655d67d840ac7364210292b39c5b6df3e7ca94639c8e02b3c26120cbc3b67adedab6535f740e5b6fcd2ead0350cd00b268"; // or_d(pk(A),and_v(v:pk(B),older(52560)))
+        let script: Script = hex::decode(script).unwrap().into();
+        let ms = Miniscript::<key::PublicKey, Segwitv0>::parse(&script).unwrap();
+        let size = expected_satisfaction_size(&script).unwrap();
+        assert_eq!(size, ms.max_satisfaction_size().unwrap());
+        assert!(size < expected_signatures(&script).unwrap() * 72 + 2);
+
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![Default::default()],
+            output: vec![],
+        };
+        let mut psbt = BitcoinPsbt::from_unsigned_tx(tx.clone()).unwrap();
+        psbt.inputs[0].witness_script = Some(script.clone());
+        assert_eq!(
+            estimate_weight(&psbt).unwrap(),
+            tx.get_weight() + script.len() + size
+        );
+
+        let redeem_script = script.to_v0_p2wsh();
+        psbt.inputs[0].redeem_script = Some(redeem_script.clone());
+        assert_eq!(
+            estimate_weight(&psbt).unwrap(),
+            tx.get_weight() + script.len() + size + (redeem_script.len() + 1) * 4
+        );
+    }
+
     pub fn rnd_string() -> String {
         thread_rng().sample_iter(&Alphanumeric).take(10).collect()
     }
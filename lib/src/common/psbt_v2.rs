@@ -0,0 +1,526 @@
+//! Conversion between BIP174 version 0 PSBTs, the only ones supported by the bitcoin library, and
+//! BIP370 version 2 PSBTs, done on the raw key-value maps so that unknown fields are preserved
+
+use crate::*;
+use bitcoin::consensus::{deserialize, serialize, Decodable, Encodable};
+use bitcoin::{OutPoint, Script, Transaction, TxIn, TxOut, Txid, VarInt};
+use std::io::{Cursor, Read};
+
+const MAGIC: &[u8] = b"psbt\xff";
+
+const GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const GLOBAL_TX_VERSION: u8 = 0x02;
+const GLOBAL_FALLBACK_LOCKTIME: u8 = 0x03;
+const GLOBAL_INPUT_COUNT: u8 = 0x04;
+const GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+const GLOBAL_TX_MODIFIABLE: u8 = 0x06;
+const GLOBAL_VERSION: u8 = 0xFB;
+
+const IN_PREVIOUS_TXID: u8 = 0x0E;
+const IN_OUTPUT_INDEX: u8 = 0x0F;
+const IN_SEQUENCE: u8 = 0x10;
+const IN_REQUIRED_TIME_LOCKTIME: u8 = 0x11;
+const IN_REQUIRED_HEIGHT_LOCKTIME: u8 = 0x12;
+
+const OUT_AMOUNT: u8 = 0x03;
+const OUT_SCRIPT: u8 = 0x04;
+
+/// locktimes lower than this are block heights, the others are timestamps
+const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+type Map = Vec<(Vec<u8>, Vec<u8>)>;
+
+struct RawPsbt {
+    global: Map,
+    inputs: Vec<Map>,
+    outputs: Vec<Map>,
+}
+
+fn read_var_int(cursor: &mut Cursor<&[u8]>) -> Result<usize> {
+    let value = VarInt::consensus_decode(&mut *cursor)?.0 as usize;
+    let remaining = cursor.get_ref().len() - cursor.position() as usize;
+    if value > remaining {
+        return Err("PSBT length exceeds the data".into());
+    }
+    Ok(value)
+}
+
+fn read_map(cursor: &mut Cursor<&[u8]>) -> Result<Map> {
+    let mut map = vec![];
+    loop {
+        let key_len = read_var_int(cursor)?;
+        if key_len == 0 {
+            return Ok(map);
+        }
+        let mut key = vec![0u8; key_len];
+        cursor.read_exact(&mut key)?;
+        let value_len = read_var_int(cursor)?;
+        let mut value = vec![0u8; value_len];
+        cursor.read_exact(&mut value)?;
+        map.push((key, value));
+    }
+}
+
+fn write_map(out: &mut Vec<u8>, map: &mut Map) {
+    map.sort();
+    for (key, value) in map.iter() {
+        VarInt(key.len() as u64)
+            .consensus_encode(&mut *out)
+            .unwrap();
+        out.extend(key);
+        VarInt(value.len() as u64)
+            .consensus_encode(&mut *out)
+            .unwrap();
+        out.extend(value);
+    }
+    out.push(0x00);
+}
+
+/// the value of the field `key_type` without key data
+fn get(map: &[(Vec<u8>, Vec<u8>)], key_type: u8) -> Option<&[u8]> {
+    map.iter()
+        .find(|(k, _)| k.as_slice() == [key_type])
+        .map(|(_, v)| v.as_slice())
+}
+
+fn get_decoded<T: Decodable>(map: &[(Vec<u8>, Vec<u8>)], key_type: u8) -> Result<Option<T>> {
+    Ok(match get(map, key_type) {
+        Some(value) => Some(deserialize(value)?),
+        None => None,
+    })
+}
+
+fn get_required<T: Decodable>(map: &[(Vec<u8>, Vec<u8>)], key_type: u8) -> Result<T> {
+    get_decoded(map, key_type)?
+        .ok_or_else(|| format!("PSBT v2 misses required field 0x{:02x}", key_type).into())
+}
+
+fn remove(map: &mut Map, key_types: &[u8]) {
+    map.retain(|(k, _)| !(k.len() == 1 && key_types.contains(&k[0])));
+}
+
+fn insert<T: Encodable>(map: &mut Map, key_type: u8, value: &T) {
+    map.push((vec![key_type], serialize(value)));
+}
+
+fn parse(bytes: &[u8]) -> Result<RawPsbt> {
+    if !bytes.starts_with(MAGIC) {
+        return Err("invalid PSBT magic bytes".into());
+    }
+    let mut cursor = Cursor::new(&bytes[MAGIC.len()..]);
+    let global = read_map(&mut cursor)?;
+    let (input_count, output_count) = match get_decoded::<Transaction>(&global, GLOBAL_UNSIGNED_TX)?
+    {
+        Some(tx) => (tx.input.len(), tx.output.len()),
+        None if !declares_v2(&global) => return Err("PSBT misses the unsigned transaction".into()),
+        None => (
+            get_required::<VarInt>(&global, GLOBAL_INPUT_COUNT)?.0 as usize,
+            get_required::<VarInt>(&global, GLOBAL_OUTPUT_COUNT)?.0 as usize,
+        ),
+    };
+    let mut inputs = vec![];
+    for _ in 0..input_count {
+        inputs.push(read_map(&mut cursor)?);
+    }
+    let mut outputs = vec![];
+    for _ in 0..output_count {
+        outputs.push(read_map(&mut cursor)?);
+    }
+    Ok(RawPsbt {
+        global,
+        inputs,
+        outputs,
+    })
+}
+
+impl RawPsbt {
+    fn serialize(mut self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        write_map(&mut out, &mut self.global);
+        for input in self.inputs.iter_mut() {
+            write_map(&mut out, input);
+        }
+        for output in self.outputs.iter_mut() {
+            write_map(&mut out, output);
+        }
+        out
+    }
+}
+
+fn declares_v2(global: &[(Vec<u8>, Vec<u8>)]) -> bool {
+    get(global, GLOBAL_VERSION) == Some(&2u32.to_le_bytes()[..])
+}
+
+/// true if `bytes` is a BIP370 version 2 PSBT
+pub fn is_psbt_v2(bytes: &[u8]) -> bool {
+    parse(bytes)
+        .map(|raw| declares_v2(&raw.global))
+        .unwrap_or(false)
+}
+
+/// the first field of `map` with type in `key_types`, key data is allowed
+fn find_type(map: &[(Vec<u8>, Vec<u8>)], key_types: &[u8]) -> Option<u8> {
+    map.iter()
+        .filter_map(|(k, _)| k.first())
+        .find(|t| key_types.contains(t))
+        .cloned()
+}
+
+/// a version 0 PSBT must not contain the fields introduced by BIP370
+fn check_v0(raw: &RawPsbt) -> Result<()> {
+    let global = [
+        GLOBAL_TX_VERSION,
+        GLOBAL_FALLBACK_LOCKTIME,
+        GLOBAL_INPUT_COUNT,
+        GLOBAL_OUTPUT_COUNT,
+        GLOBAL_TX_MODIFIABLE,
+    ];
+    let input = [
+        IN_PREVIOUS_TXID,
+        IN_OUTPUT_INDEX,
+        IN_SEQUENCE,
+        IN_REQUIRED_TIME_LOCKTIME,
+        IN_REQUIRED_HEIGHT_LOCKTIME,
+    ];
+    let output = [OUT_AMOUNT, OUT_SCRIPT];
+    let found = find_type(&raw.global, &global)
+        .or_else(|| raw.inputs.iter().find_map(|m| find_type(m, &input)))
+        .or_else(|| raw.outputs.iter().find_map(|m| find_type(m, &output)));
+    match found {
+        Some(key_type) => Err(format!("PSBT v0 contains the v2 field 0x{:02x}", key_type).into()),
+        None => Ok(()),
+    }
+}
+
+/// a version 2 PSBT must contain the fields required by BIP370 and not the unsigned transaction
+fn check_v2(raw: &RawPsbt) -> Result<()> {
+    if get(&raw.global, GLOBAL_UNSIGNED_TX).is_some() {
+        return Err("PSBT v2 contains the unsigned transaction".into());
+    }
+    get_required::<i32>(&raw.global, GLOBAL_TX_VERSION)?;
+    get_required::<VarInt>(&raw.global, GLOBAL_INPUT_COUNT)?;
+    get_required::<VarInt>(&raw.global, GLOBAL_OUTPUT_COUNT)?;
+    for map in raw.inputs.iter() {
+        get_required::<Txid>(map, IN_PREVIOUS_TXID)?;
+        get_required::<u32>(map, IN_OUTPUT_INDEX)?;
+        let time: Option<u32> = get_decoded(map, IN_REQUIRED_TIME_LOCKTIME)?;
+        if time.unwrap_or(LOCKTIME_THRESHOLD) < LOCKTIME_THRESHOLD {
+            return Err("PSBT v2 input requires a time locktime lower than 500000000".into());
+        }
+        let height: Option<u32> = get_decoded(map, IN_REQUIRED_HEIGHT_LOCKTIME)?;
+        let height = height.unwrap_or(1);
+        if height == 0 || height >= LOCKTIME_THRESHOLD {
+            return Err("PSBT v2 input requires a height locktime not in 1..500000000".into());
+        }
+    }
+    for map in raw.outputs.iter() {
+        get_required::<i64>(map, OUT_AMOUNT)?;
+        if get(map, OUT_SCRIPT).is_none() {
+            return Err("PSBT v2 output misses the script".into());
+        }
+    }
+    Ok(())
+}
+
+/// the locktime of the transaction as defined in BIP370, from the inputs requirements or the
+/// fallback one
+fn lock_time(global: &[(Vec<u8>, Vec<u8>)], inputs: &[Map]) -> Result<u32> {
+    let mut times = vec![];
+    let mut heights = vec![];
+    let mut all_heights = true;
+    let mut all_times = true;
+    for input in inputs {
+        let time: Option<u32> = get_decoded(input, IN_REQUIRED_TIME_LOCKTIME)?;
+        let height: Option<u32> = get_decoded(input, IN_REQUIRED_HEIGHT_LOCKTIME)?;
+        if time.is_some() || height.is_some() {
+            all_heights &= height.is_some();
+            all_times &= time.is_some();
+        }
+        times.extend(time);
+        heights.extend(height);
+    }
+    if times.is_empty() && heights.is_empty() {
+        return Ok(get_decoded(global, GLOBAL_FALLBACK_LOCKTIME)?.unwrap_or(0));
+    }
+    match (all_heights, all_times) {
+        (true, _) => Ok(heights.into_iter().max().unwrap_or(0)),
+        (false, true) => Ok(times.into_iter().max().unwrap_or(0)),
+        (false, false) => Err("PSBT v2 inputs require incompatible locktimes".into()),
+    }
+}
+
+/// convert the serialized PSBT to version 0 if it's a version 2, otherwise it's returned as is
+pub fn psbt_v2_to_v0(bytes: &[u8]) -> Result<Vec<u8>> {
+    if !bytes.starts_with(MAGIC) {
+        // not a PSBT, the error is given by the caller deserialization
+        return Ok(bytes.to_vec());
+    }
+    let mut raw = parse(bytes)?;
+    if !declares_v2(&raw.global) {
+        check_v0(&raw)?;
+        return Ok(bytes.to_vec());
+    }
+    check_v2(&raw)?;
+
+    let mut input = vec![];
+    for map in raw.inputs.iter() {
+        input.push(TxIn {
+            previous_output: OutPoint {
+                txid: get_required::<Txid>(map, IN_PREVIOUS_TXID)?,
+                vout: get_required(map, IN_OUTPUT_INDEX)?,
+            },
+            script_sig: Script::new(),
+            sequence: get_decoded(map, IN_SEQUENCE)?.unwrap_or(0xFFFF_FFFF),
+            witness: vec![],
+        });
+    }
+    let mut output = vec![];
+    for map in raw.outputs.iter() {
+        let value: i64 = get_required(map, OUT_AMOUNT)?;
+        if value < 0 {
+            return Err("PSBT v2 output has a negative amount".into());
+        }
+        let script = get(map, OUT_SCRIPT).ok_or("PSBT v2 output misses the script")?;
+        output.push(TxOut {
+            value: value as u64,
+            script_pubkey: Script::from(script.to_vec()),
+        });
+    }
+    let tx = Transaction {
+        version: get_required(&raw.global, GLOBAL_TX_VERSION)?,
+        lock_time: lock_time(&raw.global, &raw.inputs)?,
+        input,
+        output,
+    };
+
+    remove(
+        &mut raw.global,
+        &[
+            GLOBAL_TX_VERSION,
+            GLOBAL_FALLBACK_LOCKTIME,
+            GLOBAL_INPUT_COUNT,
+            GLOBAL_OUTPUT_COUNT,
+            GLOBAL_TX_MODIFIABLE,
+            GLOBAL_VERSION,
+        ],
+    );
+    insert(&mut raw.global, GLOBAL_UNSIGNED_TX, &tx);
+    for map in raw.inputs.iter_mut() {
+        remove(
+            map,
+            &[
+                IN_PREVIOUS_TXID,
+                IN_OUTPUT_INDEX,
+                IN_SEQUENCE,
+                IN_REQUIRED_TIME_LOCKTIME,
+                IN_REQUIRED_HEIGHT_LOCKTIME,
+            ],
+        );
+    }
+    for map in raw.outputs.iter_mut() {
+        remove(map, &[OUT_AMOUNT, OUT_SCRIPT]);
+    }
+    Ok(raw.serialize())
+}
+
+/// serialize the PSBT as version 2, the transaction fields are moved from the unsigned
+/// transaction to the global, inputs and outputs maps
+pub fn psbt_to_v2(psbt: &BitcoinPsbt) -> Result<Vec<u8>> {
+    let tx = &psbt.global.unsigned_tx;
+    let mut raw = parse(&serialize(psbt))?;
+
+    remove(&mut raw.global, &[GLOBAL_UNSIGNED_TX, GLOBAL_VERSION]);
+    insert(&mut raw.global, GLOBAL_TX_VERSION, &tx.version);
+    insert(&mut raw.global, GLOBAL_FALLBACK_LOCKTIME, &tx.lock_time);
+    insert(
+        &mut raw.global,
+        GLOBAL_INPUT_COUNT,
+        &VarInt(tx.input.len() as u64),
+    );
+    insert(
+        &mut raw.global,
+        GLOBAL_OUTPUT_COUNT,
+        &VarInt(tx.output.len() as u64),
+    );
+    insert(&mut raw.global, GLOBAL_VERSION, &2u32);
+    for (map, txin) in raw.inputs.iter_mut().zip(tx.input.iter()) {
+        insert(map, IN_PREVIOUS_TXID, &txin.previous_output.txid);
+        insert(map, IN_OUTPUT_INDEX, &txin.previous_output.vout);
+        insert(map, IN_SEQUENCE, &txin.sequence);
+    }
+    for (map, txout) in raw.outputs.iter_mut().zip(tx.output.iter()) {
+        insert(map, OUT_AMOUNT, &(txout.value as i64));
+        map.push((vec![OUT_SCRIPT], txout.script_pubkey.to_bytes()));
+    }
+    Ok(raw.serialize())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::psbt_v2::*;
+    use crate::{psbt_from_base64, BitcoinPsbt, Psbt};
+    use bitcoin::consensus::{deserialize, serialize};
+    use bitcoin::hashes::hex::FromHex;
+
+    const TXID: &str = "75ddabb27b8845f5247975c8a5ba7c6f336c4570708ebe230caf6db5217ae858";
+    const SCRIPT: &str = "0014d85c2b71d0060b09c9886aeb815e50991dda124d";
+
+    /// a PSBT v2 with one input and one output assembled with the fields required by BIP370,
+    /// modified by `edit` before serialization
+    fn v2_psbt(edit: impl Fn(&mut RawPsbt)) -> Vec<u8> {
+        let mut global = vec![];
+        insert(&mut global, GLOBAL_TX_VERSION, &2i32);
+        insert(&mut global, GLOBAL_INPUT_COUNT, &VarInt(1));
+        insert(&mut global, GLOBAL_OUTPUT_COUNT, &VarInt(1));
+        insert(&mut global, GLOBAL_VERSION, &2u32);
+        let mut input = vec![];
+        insert(&mut input, IN_PREVIOUS_TXID, &Txid::from_hex(TXID).unwrap());
+        insert(&mut input, IN_OUTPUT_INDEX, &1u32);
+        let mut output = vec![];
+        insert(&mut output, OUT_AMOUNT, &100_000i64);
+        output.push((vec![OUT_SCRIPT], Vec::from_hex(SCRIPT).unwrap()));
+        let mut raw = RawPsbt {
+            global,
+            inputs: vec![input],
+            outputs: vec![output],
+        };
+        edit(&mut raw);
+        raw.serialize()
+    }
+
+    fn v2_to_tx(bytes: &[u8]) -> Result<Transaction> {
+        let v0 = psbt_v2_to_v0(bytes)?;
+        Ok(deserialize::<BitcoinPsbt>(&v0)?.global.unsigned_tx)
+    }
+
+    #[test]
+    fn test_psbt_v2_valid() {
+        let tx = v2_to_tx(&v2_psbt(|_| ())).unwrap();
+        assert_eq!(tx.version, 2);
+        assert_eq!(tx.lock_time, 0);
+        assert_eq!(
+            tx.input[0].previous_output.to_string(),
+            format!("{}:1", TXID)
+        );
+        assert_eq!(tx.input[0].sequence, 0xFFFF_FFFF);
+        assert_eq!(tx.output[0].value, 100_000);
+        assert_eq!(
+            tx.output[0].script_pubkey.to_bytes(),
+            Vec::from_hex(SCRIPT).unwrap()
+        );
+
+        let bytes = v2_psbt(|raw| {
+            insert(&mut raw.global, GLOBAL_FALLBACK_LOCKTIME, &10u32);
+            insert(&mut raw.global, GLOBAL_TX_MODIFIABLE, &3u8);
+            insert(&mut raw.inputs[0], IN_SEQUENCE, &0xFFFF_FFFDu32);
+        });
+        let tx = v2_to_tx(&bytes).unwrap();
+        assert_eq!(tx.lock_time, 10);
+        assert_eq!(tx.input[0].sequence, 0xFFFF_FFFD);
+
+        let with_locktime = |key_type, locktime: u32| {
+            v2_psbt(move |raw| insert(&mut raw.inputs[0], key_type, &locktime))
+        };
+        for locktime in [LOCKTIME_THRESHOLD, u32::MAX].iter() {
+            let bytes = with_locktime(IN_REQUIRED_TIME_LOCKTIME, *locktime);
+            assert_eq!(v2_to_tx(&bytes).unwrap().lock_time, *locktime);
+        }
+        for locktime in [1, LOCKTIME_THRESHOLD - 1].iter() {
+            let bytes = with_locktime(IN_REQUIRED_HEIGHT_LOCKTIME, *locktime);
+            assert_eq!(v2_to_tx(&bytes).unwrap().lock_time, *locktime);
+        }
+    }
+
+    #[test]
+    fn test_psbt_v2_invalid() {
+        for key_type in [GLOBAL_TX_VERSION, GLOBAL_INPUT_COUNT, GLOBAL_OUTPUT_COUNT].iter() {
+            let bytes = v2_psbt(|raw| remove(&mut raw.global, &[*key_type]));
+            assert!(psbt_v2_to_v0(&bytes).is_err(), "missing 0x{:02x}", key_type);
+        }
+        for key_type in [IN_PREVIOUS_TXID, IN_OUTPUT_INDEX].iter() {
+            let bytes = v2_psbt(|raw| remove(&mut raw.inputs[0], &[*key_type]));
+            assert!(psbt_v2_to_v0(&bytes).is_err(), "missing 0x{:02x}", key_type);
+        }
+        for key_type in [OUT_AMOUNT, OUT_SCRIPT].iter() {
+            let bytes = v2_psbt(|raw| remove(&mut raw.outputs[0], &[*key_type]));
+            assert!(psbt_v2_to_v0(&bytes).is_err(), "missing 0x{:02x}", key_type);
+        }
+
+        let tx = v2_to_tx(&v2_psbt(|_| ())).unwrap();
+        let bytes = v2_psbt(|raw| insert(&mut raw.global, GLOBAL_UNSIGNED_TX, &tx));
+        assert!(psbt_v2_to_v0(&bytes).is_err(), "v2 with unsigned tx");
+
+        let bytes = v2_psbt(|raw| {
+            insert(
+                &mut raw.inputs[0],
+                IN_REQUIRED_TIME_LOCKTIME,
+                &(LOCKTIME_THRESHOLD - 1),
+            )
+        });
+        assert!(psbt_v2_to_v0(&bytes).is_err(), "time locktime is a height");
+        for height in [0, LOCKTIME_THRESHOLD].iter() {
+            let bytes =
+                v2_psbt(|raw| insert(&mut raw.inputs[0], IN_REQUIRED_HEIGHT_LOCKTIME, height));
+            assert!(psbt_v2_to_v0(&bytes).is_err(), "height locktime {}", height);
+        }
+    }
+
+    #[test]
+    fn test_psbt_v0_with_v2_fields() {
+        let bytes = include_bytes!("../../test_data/sign/psbt_bip.signed.json");
+        let psbt_json: Psbt = serde_json::from_slice(bytes).unwrap();
+        let v0 = serialize(&psbt_json.psbt().unwrap());
+        assert!(psbt_v2_to_v0(&v0).is_ok());
+
+        let with_field = |edit: &dyn Fn(&mut RawPsbt)| {
+            let mut raw = parse(&v0).unwrap();
+            edit(&mut raw);
+            raw.serialize()
+        };
+        for key_type in 0x02..=0x06 {
+            let bytes = with_field(&|raw| insert(&mut raw.global, key_type, &1u32));
+            assert!(psbt_v2_to_v0(&bytes).is_err(), "global 0x{:02x}", key_type);
+        }
+        for key_type in 0x0e..=0x12 {
+            let bytes = with_field(&|raw| insert(&mut raw.inputs[0], key_type, &1u32));
+            assert!(psbt_v2_to_v0(&bytes).is_err(), "input 0x{:02x}", key_type);
+        }
+        for key_type in 0x03..=0x04 {
+            let bytes = with_field(&|raw| insert(&mut raw.outputs[0], key_type, &1u32));
+            assert!(psbt_v2_to_v0(&bytes).is_err(), "output 0x{:02x}", key_type);
+        }
+    }
+
+    #[test]
+    fn test_psbt_v2_roundtrip() {
+        let bytes = include_bytes!("../../test_data/sign/psbt_bip.signed.json");
+        let psbt_json: Psbt = serde_json::from_slice(bytes).unwrap();
+        let psbt = psbt_json.psbt().unwrap();
+        assert!(!is_psbt_v2(&serialize(&psbt)));
+
+        let v2 = psbt_to_v2(&psbt).unwrap();
+        assert!(is_psbt_v2(&v2));
+        assert!(deserialize::<BitcoinPsbt>(&v2).is_err());
+
+        let v0 = psbt_v2_to_v0(&v2).unwrap();
+        assert_eq!(deserialize::<BitcoinPsbt>(&v0).unwrap(), psbt);
+        let (_, from_v2) = psbt_from_base64(&base64::encode(&v2)).unwrap();
+        assert_eq!(from_v2, psbt);
+
+        let v0 = serialize(&psbt);
+        assert_eq!(psbt_v2_to_v0(&v0).unwrap(), v0);
+    }
+
+    #[test]
+    fn test_psbt_v2_lock_time() {
+        let bytes = include_bytes!("../../test_data/sign/psbt_bip.signed.json");
+        let psbt_json: Psbt = serde_json::from_slice(bytes).unwrap();
+        let mut psbt = psbt_json.psbt().unwrap();
+        psbt.global.unsigned_tx.lock_time = 600_000;
+
+        let v2 = psbt_to_v2(&psbt).unwrap();
+        let v0 = psbt_v2_to_v0(&v2).unwrap();
+        let tx = deserialize::<BitcoinPsbt>(&v0).unwrap().global.unsigned_tx;
+        assert_eq!(tx.lock_time, 600_000);
+    }
+}
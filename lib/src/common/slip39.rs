@@ -0,0 +1,622 @@
+//!
+//! # SLIP-39 Shamir's Secret-Sharing for Mnemonic Codes
+//!
+//! Split a master secret in groups of shares, a threshold of groups each with a threshold of
+//! member shares is needed to reconstruct it
+//!
+use bitcoin::hashes::{sha256, Hash, HashEngine, Hmac, HmacEngine};
+use rand::{thread_rng, Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+const RADIX_BITS: usize = 10;
+const ID_EXP_WORDS: usize = 2;
+const METADATA_WORDS: usize = ID_EXP_WORDS + 2 + CHECKSUM_WORDS;
+const CHECKSUM_WORDS: usize = 3;
+const MIN_SECRET_BYTES: usize = 16;
+const MAX_SHARES: u8 = 16;
+const SECRET_INDEX: u8 = 255;
+const DIGEST_INDEX: u8 = 254;
+const DIGEST_BYTES: usize = 4;
+const ROUNDS: u8 = 4;
+const BASE_ITERATIONS: u32 = 10000;
+const CUSTOMIZATION: &[u8] = b"shamir";
+const CUSTOMIZATION_EXTENDABLE: &[u8] = b"shamir_extendable";
+
+/// SLIP-39 possible errors
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// Share contains an unknown word
+    UnknownWord(String),
+
+    /// Share has not enough words or the padding is wrong
+    InvalidLength,
+
+    /// Share checksum failed
+    ChecksumFailed,
+
+    /// The digest share doesn't match the recovered secret
+    DigestFailed,
+
+    /// Secret must be at least 16 bytes and of even length
+    InvalidSecretLength,
+
+    /// Thresholds must be between 1 and the count, member threshold 1 requires 1 member
+    InvalidThreshold,
+
+    /// Shares are not part of the same split
+    MismatchedShares,
+
+    /// Not enough shares or groups to reconstruct the secret
+    NotEnoughShares,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::UnknownWord(w) => write!(f, "Unknown SLIP-39 word {}", w),
+            Error::InvalidLength => write!(f, "Invalid SLIP-39 share length"),
+            Error::ChecksumFailed => write!(f, "SLIP-39 share checksum failed"),
+            Error::DigestFailed => write!(f, "SLIP-39 shares digest failed"),
+            Error::InvalidSecretLength => write!(f, "Invalid SLIP-39 secret length"),
+            Error::InvalidThreshold => write!(f, "Invalid SLIP-39 threshold"),
+            Error::MismatchedShares => write!(f, "SLIP-39 shares are not part of the same split"),
+            Error::NotEnoughShares => write!(f, "Not enough SLIP-39 shares"),
+        }
+    }
+}
+
+/// A single share, encoded as a list of words
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Share {
+    pub identifier: u16,
+    pub extendable: bool,
+    pub iteration_exponent: u8,
+    pub group_index: u8,
+    pub group_threshold: u8,
+    pub group_count: u8,
+    pub member_index: u8,
+    pub member_threshold: u8,
+    pub value: Vec<u8>,
+}
+
+/// `threshold` of `count` members shares of a group
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct GroupSpec {
+    pub threshold: u8,
+    pub count: u8,
+}
+
+impl FromStr for GroupSpec {
+    type Err = std::io::Error;
+
+    /// parse `M-of-N`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let err = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("({}) valid values are like 2-of-3", s),
+            )
+        };
+        let (threshold, count) = s.split_once("-of-").ok_or_else(err)?;
+        Ok(GroupSpec {
+            threshold: threshold.parse().map_err(|_| err())?,
+            count: count.parse().map_err(|_| err())?,
+        })
+    }
+}
+
+fn words() -> Vec<&'static str> {
+    include_str!("wordlist/slip39.txt").lines().collect()
+}
+
+fn customization(extendable: bool) -> &'static [u8] {
+    if extendable {
+        CUSTOMIZATION_EXTENDABLE
+    } else {
+        CUSTOMIZATION
+    }
+}
+
+fn rs1024_polymod(values: &[u16]) -> u32 {
+    const GEN: [u32; 10] = [
+        0xE0E040, 0x1C1C080, 0x3838100, 0x7070200, 0xE0E0009, 0x1C0C2412, 0x38086C24, 0x3090FC48,
+        0x21B1F890, 0x3F3F120,
+    ];
+    let mut chk = 1u32;
+    for v in values {
+        let b = chk >> 20;
+        chk = ((chk & 0xFFFFF) << 10) ^ (*v as u32);
+        for (i, g) in GEN.iter().enumerate() {
+            if (b >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+fn with_customization(extendable: bool, data: &[u16]) -> Vec<u16> {
+    let mut values: Vec<u16> = customization(extendable)
+        .iter()
+        .map(|b| *b as u16)
+        .collect();
+    values.extend_from_slice(data);
+    values
+}
+
+impl Display for Share {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut indexes = vec![
+            self.identifier >> 5,
+            ((self.identifier & 31) << 5)
+                | ((self.extendable as u16) << 4)
+                | self.iteration_exponent as u16,
+            ((self.group_index as u16) << 6)
+                | (((self.group_threshold - 1) as u16) << 2)
+                | (((self.group_count - 1) as u16) >> 2),
+            ((((self.group_count - 1) as u16) & 3) << 8)
+                | ((self.member_index as u16) << 4)
+                | (self.member_threshold - 1) as u16,
+        ];
+
+        // value is left padded with zero bits to a multiple of 10 bits
+        let value_words = (self.value.len() * 8 + RADIX_BITS - 1) / RADIX_BITS;
+        let mut acc = 0u32;
+        let mut acc_bits = value_words * RADIX_BITS - self.value.len() * 8;
+        let mut value_indexes = vec![];
+        for byte in self.value.iter() {
+            acc = (acc << 8) | *byte as u32;
+            acc_bits += 8;
+            while acc_bits >= RADIX_BITS {
+                acc_bits -= RADIX_BITS;
+                value_indexes.push(((acc >> acc_bits) & 1023) as u16);
+            }
+        }
+        indexes.extend(value_indexes);
+
+        let mut values = with_customization(self.extendable, &indexes);
+        values.extend_from_slice(&[0, 0, 0]);
+        let polymod = rs1024_polymod(&values) ^ 1;
+        for i in (0..CHECKSUM_WORDS).rev() {
+            indexes.push(((polymod >> (10 * i)) & 1023) as u16);
+        }
+
+        let words = words();
+        let mnemonic: Vec<_> = indexes.iter().map(|i| words[*i as usize]).collect();
+        write!(f, "{}", mnemonic.join(" "))
+    }
+}
+
+impl FromStr for Share {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words = words();
+        let indexes = s
+            .split_whitespace()
+            .map(|w| {
+                let w = w.to_lowercase();
+                words
+                    .binary_search(&w.as_str())
+                    .map(|i| i as u16)
+                    .map_err(|_| Error::UnknownWord(w.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if indexes.len() < METADATA_WORDS + (MIN_SECRET_BYTES * 8 + RADIX_BITS - 1) / RADIX_BITS {
+            return Err(Error::InvalidLength);
+        }
+        let extendable = (indexes[1] >> 4) & 1 == 1;
+        if rs1024_polymod(&with_customization(extendable, &indexes)) != 1 {
+            return Err(Error::ChecksumFailed);
+        }
+
+        let value_words = &indexes[ID_EXP_WORDS + 2..indexes.len() - CHECKSUM_WORDS];
+        let padding = value_words.len() * RADIX_BITS % 16;
+        if padding > 8 {
+            return Err(Error::InvalidLength);
+        }
+        let mut value = vec![];
+        let mut acc = 0u32;
+        let mut acc_bits = 0usize;
+        for (i, index) in value_words.iter().enumerate() {
+            acc = (acc << RADIX_BITS) | *index as u32;
+            acc_bits += RADIX_BITS;
+            if i == 0 {
+                if acc >> (RADIX_BITS - padding) != 0 {
+                    return Err(Error::InvalidLength);
+                }
+                acc_bits -= padding;
+            }
+            while acc_bits >= 8 {
+                acc_bits -= 8;
+                value.push((acc >> acc_bits) as u8);
+            }
+            acc &= (1 << acc_bits) - 1;
+        }
+
+        let share = Share {
+            identifier: (indexes[0] << 5) | (indexes[1] >> 5),
+            extendable,
+            iteration_exponent: (indexes[1] & 15) as u8,
+            group_index: (indexes[2] >> 6) as u8,
+            group_threshold: ((indexes[2] >> 2) & 15) as u8 + 1,
+            group_count: ((((indexes[2] & 3) << 2) | (indexes[3] >> 8)) as u8) + 1,
+            member_index: ((indexes[3] >> 4) & 15) as u8,
+            member_threshold: (indexes[3] & 15) as u8 + 1,
+            value,
+        };
+        if share.group_threshold > share.group_count {
+            return Err(Error::InvalidThreshold);
+        }
+        Ok(share)
+    }
+}
+
+/// GF(256) with the Rijndael polynomial
+struct Field {
+    exp: [u8; 255],
+    log: [u8; 256],
+}
+
+impl Field {
+    fn new() -> Self {
+        let mut exp = [0u8; 255];
+        let mut log = [0u8; 256];
+        let mut poly = 1u16;
+        for (i, e) in exp.iter_mut().enumerate() {
+            *e = poly as u8;
+            log[poly as usize] = i as u8;
+            poly = (poly << 1) ^ poly;
+            if poly & 0x100 != 0 {
+                poly ^= 0x11b;
+            }
+        }
+        Field { exp, log }
+    }
+
+    /// lagrange interpolation of the `shares` points evaluated at `x`
+    fn interpolate(&self, shares: &[(u8, Vec<u8>)], x: u8) -> Result<Vec<u8>, Error> {
+        if let Some((_, value)) = shares.iter().find(|(i, _)| *i == x) {
+            return Ok(value.clone());
+        }
+        let len = shares.first().ok_or(Error::NotEnoughShares)?.1.len();
+        if shares.iter().any(|(_, v)| v.len() != len) {
+            return Err(Error::MismatchedShares);
+        }
+        let log = |v: u8| self.log[v as usize] as u32;
+        let log_prod: u32 = shares.iter().map(|(i, _)| log(i ^ x)).sum();
+        let mut result = vec![0u8; len];
+        for (i, value) in shares.iter() {
+            let others: u32 = shares.iter().map(|(j, _)| log(j ^ i)).sum();
+            let log_basis = (log_prod + 255 * shares.len() as u32 - log(i ^ x) - others) % 255;
+            for (r, v) in result.iter_mut().zip(value.iter()) {
+                if *v != 0 {
+                    *r ^= self.exp[((log(*v) + log_basis) % 255) as usize];
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+fn digest(random: &[u8], secret: &[u8]) -> Vec<u8> {
+    let mut engine = HmacEngine::<sha256::Hash>::new(random);
+    engine.input(secret);
+    Hmac::<sha256::Hash>::from_engine(engine)[..DIGEST_BYTES].to_vec()
+}
+
+fn split_secret(
+    field: &Field,
+    threshold: u8,
+    count: u8,
+    secret: &[u8],
+) -> Result<Vec<(u8, Vec<u8>)>, Error> {
+    if threshold == 0 || threshold > count || count > MAX_SHARES {
+        return Err(Error::InvalidThreshold);
+    }
+    if threshold == 1 {
+        return Ok((0..count).map(|i| (i, secret.to_vec())).collect());
+    }
+    let mut rng = thread_rng();
+    let mut shares: Vec<(u8, Vec<u8>)> = (0..threshold - 2)
+        .map(|i| {
+            let mut value = vec![0u8; secret.len()];
+            rng.fill_bytes(&mut value);
+            (i, value)
+        })
+        .collect();
+    let mut random = vec![0u8; secret.len() - DIGEST_BYTES];
+    rng.fill_bytes(&mut random);
+    let mut digest_share = digest(&random, secret);
+    digest_share.extend(random);
+
+    let mut base = shares.clone();
+    base.push((DIGEST_INDEX, digest_share));
+    base.push((SECRET_INDEX, secret.to_vec()));
+    for i in threshold - 2..count {
+        shares.push((i, field.interpolate(&base, i)?));
+    }
+    Ok(shares)
+}
+
+fn recover_secret(
+    field: &Field,
+    threshold: u8,
+    shares: &[(u8, Vec<u8>)],
+) -> Result<Vec<u8>, Error> {
+    if shares.len() < threshold as usize {
+        return Err(Error::NotEnoughShares);
+    }
+    let shares = &shares[..threshold as usize];
+    if threshold == 1 {
+        return Ok(shares[0].1.clone());
+    }
+    let secret = field.interpolate(shares, SECRET_INDEX)?;
+    let digest_share = field.interpolate(shares, DIGEST_INDEX)?;
+    if digest(&digest_share[DIGEST_BYTES..], &secret) != digest_share[..DIGEST_BYTES] {
+        return Err(Error::DigestFailed);
+    }
+    Ok(secret)
+}
+
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32, len: usize) -> Vec<u8> {
+    let mut result = vec![];
+    for block in 1..=((len + 31) / 32) as u32 {
+        let mut engine = HmacEngine::<sha256::Hash>::new(password);
+        engine.input(salt);
+        engine.input(&block.to_be_bytes());
+        let mut u = Hmac::<sha256::Hash>::from_engine(engine).into_inner();
+        let mut t = u;
+        for _ in 1..iterations {
+            let mut engine = HmacEngine::<sha256::Hash>::new(password);
+            engine.input(&u);
+            u = Hmac::<sha256::Hash>::from_engine(engine).into_inner();
+            for (t, u) in t.iter_mut().zip(u.iter()) {
+                *t ^= u;
+            }
+        }
+        result.extend_from_slice(&t);
+    }
+    result.truncate(len);
+    result
+}
+
+/// the 4 rounds Feistel network encrypting the master secret with the passphrase
+fn feistel(
+    secret: &[u8],
+    passphrase: &[u8],
+    iteration_exponent: u8,
+    identifier: u16,
+    extendable: bool,
+    encrypt: bool,
+) -> Vec<u8> {
+    let half = secret.len() / 2;
+    let (mut l, mut r) = (secret[..half].to_vec(), secret[half..].to_vec());
+    let mut salt = vec![];
+    if !extendable {
+        salt.extend_from_slice(CUSTOMIZATION);
+        salt.extend_from_slice(&identifier.to_be_bytes());
+    }
+    let iterations = (BASE_ITERATIONS << iteration_exponent) / ROUNDS as u32;
+    let rounds: Vec<u8> = if encrypt {
+        (0..ROUNDS).collect()
+    } else {
+        (0..ROUNDS).rev().collect()
+    };
+    for i in rounds {
+        let mut password = vec![i];
+        password.extend_from_slice(passphrase);
+        let mut round_salt = salt.clone();
+        round_salt.extend_from_slice(&r);
+        let f = pbkdf2_sha256(&password, &round_salt, iterations, r.len());
+        let new_r: Vec<u8> = l.iter().zip(f.iter()).map(|(a, b)| a ^ b).collect();
+        l = r;
+        r = new_r;
+    }
+    r.extend(l);
+    r
+}
+
+/// Split `secret` in groups of shares, `group_threshold` groups are needed to recover it
+pub fn split(
+    secret: &[u8],
+    passphrase: &[u8],
+    group_threshold: u8,
+    groups: &[GroupSpec],
+    iteration_exponent: u8,
+) -> Result<Vec<Vec<Share>>, Error> {
+    if secret.len() < MIN_SECRET_BYTES || secret.len() & 1 != 0 {
+        return Err(Error::InvalidSecretLength);
+    }
+    if groups
+        .iter()
+        .any(|g| g.threshold == 1 && g.count > 1 || g.threshold == 0 || g.threshold > g.count)
+    {
+        return Err(Error::InvalidThreshold);
+    }
+    let identifier = thread_rng().gen::<u16>() & 0x7FFF;
+    let extendable = true;
+    let encrypted = feistel(
+        secret,
+        passphrase,
+        iteration_exponent,
+        identifier,
+        extendable,
+        true,
+    );
+    let field = Field::new();
+    let group_shares = split_secret(&field, group_threshold, groups.len() as u8, &encrypted)?;
+
+    let mut result = vec![];
+    for ((group_index, group_secret), group) in group_shares.iter().zip(groups.iter()) {
+        let members = split_secret(&field, group.threshold, group.count, group_secret)?;
+        let shares = members
+            .into_iter()
+            .map(|(member_index, value)| Share {
+                identifier,
+                extendable,
+                iteration_exponent,
+                group_index: *group_index,
+                group_threshold,
+                group_count: groups.len() as u8,
+                member_index,
+                member_threshold: group.threshold,
+                value,
+            })
+            .collect();
+        result.push(shares);
+    }
+    Ok(result)
+}
+
+/// Recover the secret from enough `shares`
+pub fn combine(shares: &[Share], passphrase: &[u8]) -> Result<Vec<u8>, Error> {
+    let first = shares.first().ok_or(Error::NotEnoughShares)?;
+    let mut groups: BTreeMap<u8, Vec<&Share>> = BTreeMap::new();
+    for share in shares {
+        if share.identifier != first.identifier
+            || share.extendable != first.extendable
+            || share.iteration_exponent != first.iteration_exponent
+            || share.group_threshold != first.group_threshold
+            || share.group_count != first.group_count
+            || share.value.len() != first.value.len()
+        {
+            return Err(Error::MismatchedShares);
+        }
+        let group = groups.entry(share.group_index).or_default();
+        if !group.iter().any(|s| s.member_index == share.member_index) {
+            group.push(share);
+        }
+    }
+
+    let field = Field::new();
+    let mut group_secrets = vec![];
+    for (group_index, members) in groups.iter() {
+        let threshold = members[0].member_threshold;
+        if members.iter().any(|s| s.member_threshold != threshold) {
+            return Err(Error::MismatchedShares);
+        }
+        if members.len() < threshold as usize {
+            continue;
+        }
+        let points: Vec<_> = members
+            .iter()
+            .map(|s| (s.member_index, s.value.clone()))
+            .collect();
+        group_secrets.push((*group_index, recover_secret(&field, threshold, &points)?));
+    }
+    let encrypted = recover_secret(&field, first.group_threshold, &group_secrets)?;
+    Ok(feistel(
+        &encrypted,
+        passphrase,
+        first.iteration_exponent,
+        first.identifier,
+        first.extendable,
+        false,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::hex::{FromHex, ToHex};
+
+    fn parse(shares: &[&str]) -> Vec<Share> {
+        shares.iter().map(|s| Share::from_str(s).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_slip39_vectors() {
+        // from the SLIP-39 test vectors, passphrase "TREZOR"
+        let shares = parse(&["duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision keyboard"]);
+        let secret = combine(&shares, b"TREZOR").unwrap();
+        assert_eq!(secret.to_hex(), "bb54aac4b89dc868ba37d9cc21b2cece");
+
+        let shares = parse(&[
+            "shadow pistol academic always adequate wildlife fancy gross oasis cylinder mustang wrist rescue view short owner flip making coding armed",
+            "shadow pistol academic acid actress prayer class unknown daughter sweater depict flip twice unkind craft early superior advocate guest smoking",
+        ]);
+        let secret = combine(&shares, b"TREZOR").unwrap();
+        assert_eq!(secret.to_hex(), "b43ceb7e57a0ea8766221624d01b0864");
+        assert_eq!(
+            combine(&shares[..1], b"TREZOR"),
+            Err(Error::NotEnoughShares)
+        );
+
+        // round trip of the share encoding
+        for share in shares.iter() {
+            assert_eq!(&Share::from_str(&share.to_string()).unwrap(), share);
+        }
+
+        let err = Share::from_str("duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision kidney");
+        assert_eq!(err, Err(Error::ChecksumFailed));
+        let err = Share::from_str("duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision bitcoin");
+        assert_eq!(err, Err(Error::UnknownWord("bitcoin".to_string())));
+    }
+
+    #[test]
+    fn test_slip39_split_combine() {
+        let secret =
+            Vec::<u8>::from_hex("bb54aac4b89dc868ba37d9cc21b2cecebb54aac4b89dc868ba37d9cc21b2cece")
+                .unwrap();
+        let groups = [
+            GroupSpec {
+                threshold: 1,
+                count: 1,
+            },
+            GroupSpec {
+                threshold: 2,
+                count: 3,
+            },
+            GroupSpec {
+                threshold: 3,
+                count: 5,
+            },
+        ];
+        let shares = split(&secret, b"", 2, &groups, 0).unwrap();
+        assert_eq!(
+            shares.iter().map(|g| g.len()).collect::<Vec<_>>(),
+            [1, 3, 5]
+        );
+        let words = shares[0][0].to_string().split(' ').count();
+        assert_eq!(words, 33);
+        let shares: Vec<Share> = shares
+            .iter()
+            .flatten()
+            .map(|s| Share::from_str(&s.to_string()).unwrap())
+            .collect();
+
+        let chosen = [&shares[0], &shares[2], &shares[3]];
+        let chosen: Vec<_> = chosen.iter().map(|s| (*s).clone()).collect();
+        assert_eq!(combine(&chosen, b"").unwrap(), secret);
+        assert_ne!(combine(&chosen, b"wrong").unwrap(), secret);
+
+        let chosen = vec![
+            shares[1].clone(),
+            shares[2].clone(),
+            shares[4].clone(),
+            shares[5].clone(),
+            shares[6].clone(),
+        ];
+        assert_eq!(combine(&chosen, b"").unwrap(), secret);
+
+        let chosen = vec![shares[0].clone(), shares[1].clone(), shares[4].clone()];
+        assert_eq!(combine(&chosen, b""), Err(Error::NotEnoughShares));
+
+        let invalid = [GroupSpec {
+            threshold: 1,
+            count: 2,
+        }];
+        assert_eq!(
+            split(&secret, b"", 1, &invalid, 0).unwrap_err(),
+            Error::InvalidThreshold
+        );
+        assert_eq!(
+            split(&secret[..15], b"", 1, &groups[..1], 0).unwrap_err(),
+            Error::InvalidSecretLength
+        );
+    }
+}
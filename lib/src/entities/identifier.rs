@@ -1,4 +1,5 @@
 use crate::offline::decrypt::{decrypt, EncryptionKey, MaybeEncrypted};
+use crate::offline::Scheme;
 use crate::{expand_tilde, Error, Result, StringEncoding};
 use bitcoin::Network;
 use core::fmt::Formatter;
@@ -15,6 +16,7 @@ pub enum Kind {
     Wallet,
     WalletIndexes,
     WalletSignature,
+    WalletLabels,
     MasterSecret,
     DescriptorPublicKey,
     Psbt,
@@ -23,7 +25,9 @@ pub enum Kind {
 impl Kind {
     pub fn dir(&self) -> &str {
         match self {
-            Kind::Wallet | Kind::WalletIndexes | Kind::WalletSignature => "wallets",
+            Kind::Wallet | Kind::WalletIndexes | Kind::WalletSignature | Kind::WalletLabels => {
+                "wallets"
+            }
             Kind::MasterSecret | Kind::DescriptorPublicKey => "keys",
             Kind::Psbt => "psbts",
         }
@@ -34,6 +38,7 @@ impl Kind {
             Kind::Wallet => "wallet.json",
             Kind::WalletIndexes => "wallet_indexes.json",
             Kind::WalletSignature => "wallet_signature.json",
+            Kind::WalletLabels => "wallet_labels.json",
             Kind::MasterSecret => "master_secret.json",
             Kind::DescriptorPublicKey => "descriptor_public_key.json",
             Kind::Psbt => "psbt.json",
@@ -55,13 +60,14 @@ impl FromStr for Kind {
             "Wallet" => Ok(Kind::Wallet),
             "WalletIndexes" => Ok(Kind::WalletIndexes),
             "WalletSignature" => Ok(Kind::WalletSignature),
+            "WalletLabels" => Ok(Kind::WalletLabels),
             "MasterSecret" => Ok(Kind::MasterSecret),
             "DescriptorPublicKey" => Ok(Kind::DescriptorPublicKey),
             "Psbt" => Ok(Kind::Psbt),
             _ => Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
                 format!(
-                    "({}) valid values are: Wallet, WalletSignature, MasterSecret, Psbt",
+                    "({}) valid values are: Wallet, WalletSignature, WalletLabels, MasterSecret, Psbt",
                     s
                 ),
             )),
@@ -74,6 +80,13 @@ pub struct Identifier {
     pub kind: Kind,
     pub name: String,
     pub network: Network,
+    /// account of a `DescriptorPublicKey` derived in addition to the one created with the key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<u32>,
+    /// derivation scheme of `account`, accounts with the same number and different schemes are
+    /// different keys
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<Scheme>,
 }
 
 pub trait Identifiable {
@@ -94,6 +107,8 @@ impl Identifier {
             network,
             kind,
             name: name.to_string(),
+            account: None,
+            scheme: None,
         }
     }
 
@@ -102,6 +117,16 @@ impl Identifier {
             network: self.network,
             kind: new_kind,
             name: self.name.clone(),
+            account: self.account,
+            scheme: self.scheme,
+        }
+    }
+
+    pub fn with_account(&self, scheme: Scheme, account: u32) -> Self {
+        Identifier {
+            account: Some(account),
+            scheme: Some(scheme),
+            ..self.clone()
         }
     }
 
@@ -118,7 +143,16 @@ impl Identifier {
             fs::create_dir_all(&path)?;
             debug!("created {:?}", path);
         }
-        path.push(self.kind.name());
+        match (self.scheme, self.account) {
+            (Some(scheme), Some(account)) => {
+                let name = self
+                    .kind
+                    .name()
+                    .replace(".json", &format!("_{}_{}.json", scheme, account));
+                path.push(name)
+            }
+            _ => path.push(self.kind.name()),
+        }
         Ok(path)
     }
 
@@ -169,6 +203,7 @@ impl Identifier {
 #[cfg(test)]
 mod tests {
     use crate::common::tests::rnd_string;
+    use crate::offline::Scheme;
     use crate::{Identifier, Kind};
     use bitcoin::Network;
 
@@ -178,6 +213,8 @@ mod tests {
                 kind,
                 name: rnd_string(),
                 network: Network::Testnet,
+                account: None,
+                scheme: None,
             }
         }
     }
@@ -188,11 +225,30 @@ mod tests {
             network: Network::Bitcoin,
             kind: Kind::MasterSecret,
             name: "a1".to_string(),
+            account: None,
+            scheme: None,
         };
         let expected = "\"/bitcoin/keys/a1/master_secret.json\"";
         let result = format!("{:?}", id.as_path_buf("/", false).unwrap());
         assert_eq!(expected, result);
 
+        let account_id = id
+            .with_kind(Kind::DescriptorPublicKey)
+            .with_account(Scheme::Bip87, 3);
+        let expected = "\"/bitcoin/keys/a1/descriptor_public_key_bip87_3.json\"";
+        let result = format!("{:?}", account_id.as_path_buf("/", false).unwrap());
+        assert_eq!(expected, result);
+        let other_scheme = id
+            .with_kind(Kind::DescriptorPublicKey)
+            .with_account(Scheme::Bip48Nested, 3);
+        assert_ne!(
+            account_id.as_path_buf("/", false).unwrap(),
+            other_scheme.as_path_buf("/", false).unwrap()
+        );
+        let value = serde_json::to_value(&account_id).unwrap();
+        assert_eq!(value["account"], 3);
+        assert_eq!(value["scheme"], "Bip87");
+
         let expected = r#"{"kind":"MasterSecret","name":"a1","network":"bitcoin"}"#;
         let result = serde_json::to_value(&id).unwrap();
         assert_eq!(expected, result.to_string());
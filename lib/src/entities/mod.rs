@@ -1,12 +1,12 @@
 pub mod identifier;
 pub mod persisted;
 
-use crate::{psbt_from_base64, BitcoinPsbt, DaemonOpts, Result};
+use crate::{BitcoinPsbt, DaemonOpts, Result};
 use bitcoin::bech32::FromBase32;
+use bitcoin::hashes::Hash;
 use bitcoin::util::bip32::{DerivationPath, Fingerprint};
 use bitcoin::util::psbt::raw;
 use bitcoin::{bech32, Address, Amount, OutPoint, Txid};
-use bitcoincore_rpc::bitcoincore_rpc_json::WalletCreateFundedPsbtResult;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashSet;
@@ -50,6 +50,29 @@ pub struct CreateTxOutput {
     pub psbt_name: String,
     pub funded_psbt: Psbt,
     pub address_reused: HashSet<Address>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BumpFeeOutput {
+    pub psbt_name: String,
+    pub funded_psbt: Psbt,
+    pub replaced_txid: Txid,
+    pub original_fee: u64,
+    pub fee: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CpfpOutput {
+    pub psbt_name: String,
+    pub funded_psbt: Psbt,
+    pub parent_txid: Txid,
+    pub parent_vsize: u64,
+    pub parent_fee: u64,
+    pub child_vsize: u64,
+    pub child_fee: u64,
+    pub package_fee_rate: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
@@ -73,6 +96,58 @@ pub struct Coin {
     pub amount: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub unconfirmed: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<Address>,
+    /// derivation of the coin script, `m/0/i` for receiving and `m/1/i` for change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<DerivationPath>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frozen: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ListTransactionsOutput {
+    pub transactions: Vec<TransactionEntry>,
+    /// number of transactions of the wallet, to paginate the result
+    pub total: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TransactionEntry {
+    pub txid: Txid,
+    /// satoshi received minus spent by the wallet, fee included
+    pub net_amount: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee: Option<u64>,
+    pub confirmations: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_time: Option<u64>,
+    /// the PSBT in the firma datadir which created this transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psbt_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ListAddressesOutput {
+    pub addresses: Vec<AddressEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AddressEntry {
+    pub index: u32,
+    pub address: Address,
+    pub path: DerivationPath,
+    /// the index is lower than the next one given by `get-address`
+    pub given_out: bool,
+    pub received: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -91,6 +166,9 @@ pub struct TxIn {
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct TxOut {
     pub address: String,
+    /// true if the output goes to the change chain of a known wallet
+    #[serde(default)]
+    pub change: bool,
     #[serde(flatten)]
     pub common: TxCommonInOut,
 }
@@ -111,6 +189,8 @@ pub struct PsbtPrettyPrint {
     pub info: Vec<String>,
     pub psbt_file: PathBuf,
     pub balances: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replaces: Option<Txid>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
@@ -134,6 +214,13 @@ pub struct VerifyWalletResult {
     pub verified: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SplitOutput {
+    pub group_threshold: u8,
+    /// SLIP-39 shares of every group
+    pub groups: Vec<Vec<String>>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct EncodedQrs {
     pub qrs: Vec<StringEncoding>,
@@ -161,7 +248,9 @@ pub struct Balance {
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Balances {
     pub mine: Balance,
-    pub watchonly: Balance,
+    /// missing in descriptor wallets, where watch-only balances are in `mine`
+    #[serde(default)]
+    pub watchonly: Option<Balance>,
 }
 
 impl StringEncoding {
@@ -214,15 +303,33 @@ pub fn get_name_key() -> raw::ProprietaryKey {
     }
 }
 
-pub fn psbt_from_rpc(psbt: &WalletCreateFundedPsbtResult, name: &str) -> Result<BitcoinPsbt> {
-    let (_, mut psbt_with_name) = psbt_from_base64(&psbt.psbt)?;
-
-    psbt_with_name
-        .global
+pub fn set_psbt_name(psbt: &mut BitcoinPsbt, name: &str) {
+    psbt.global
         .proprietary
         .insert(get_name_key(), name.as_bytes().to_vec());
+}
 
-    Ok(psbt_with_name)
+fn get_replaces_key() -> raw::ProprietaryKey {
+    ProprietaryKey {
+        prefix: b"firma".to_vec(),
+        subtype: 0u8,
+        key: b"replaces".to_vec(),
+    }
+}
+
+/// mark the PSBT as a replacement of the transaction `txid`
+pub fn set_psbt_replaces(psbt: &mut BitcoinPsbt, txid: &Txid) {
+    psbt.global
+        .proprietary
+        .insert(get_replaces_key(), txid.to_vec());
+}
+
+/// the txid of the transaction replaced by this PSBT, if any
+pub fn get_psbt_replaces(psbt: &BitcoinPsbt) -> Option<Txid> {
+    psbt.global
+        .proprietary
+        .get(&get_replaces_key())
+        .and_then(|v| Txid::from_slice(v).ok())
 }
 
 macro_rules! impl_try_into {
@@ -238,19 +345,27 @@ macro_rules! impl_try_into {
 }
 
 impl_try_into!(CreateTxOutput);
+impl_try_into!(BumpFeeOutput);
+impl_try_into!(CpfpOutput);
 impl_try_into!(SendTxOutput);
 impl_try_into!(BalanceOutput);
 impl_try_into!(ListCoinsOutput);
+impl_try_into!(ListTransactionsOutput);
+impl_try_into!(ListAddressesOutput);
 impl_try_into!(GetAddressOutput);
 impl_try_into!(ListOutput);
 
 impl_try_into!(PsbtPrettyPrint);
 impl_try_into!(VerifyWalletResult);
+impl_try_into!(SplitOutput);
 impl_try_into!(DaemonOpts);
 
 impl_try_into!(WalletSignature);
 impl_try_into!(MasterSecret);
+impl_try_into!(DescriptorPublicKey);
 impl_try_into!(Wallet);
+impl_try_into!(WalletLabels);
+impl_try_into!(WalletIndexes);
 
 macro_rules! impl_traits {
     ( $for:ty, $val:expr, $k:expr  ) => {
@@ -276,12 +391,13 @@ impl_traits!(WalletSignature, false, Kind::WalletSignature);
 impl_traits!(MasterSecret, false, Kind::MasterSecret);
 impl_traits!(Wallet, false, Kind::Wallet);
 impl_traits!(WalletIndexes, true, Kind::WalletIndexes);
+impl_traits!(WalletLabels, true, Kind::WalletLabels);
 impl_traits!(DescriptorPublicKey, false, Kind::DescriptorPublicKey);
 impl_traits!(Psbt, true, Kind::Psbt);
 
 #[cfg(test)]
 mod tests {
-    use crate::Wallet;
+    use crate::{Balances, Wallet};
 
     #[test]
     fn test_cbor_wallet() {
@@ -295,4 +411,12 @@ mod tests {
         assert_eq!(vec_cbor.len(), 682);
         assert_eq!(vec_packed_cbor.len(), 632);
     }
+
+    #[test]
+    fn test_balances() {
+        // `getbalances` of a descriptor wallet, without the watchonly field
+        let balances: Balances = serde_json::from_str(r#"{"mine":{"trusted":0.001,"untrusted_pending":0.0,"immature":0.0},"lastprocessedblock":{"hash":"00","height":1}}"#).unwrap();
+        assert!(balances.watchonly.is_none());
+        assert_eq!(balances.mine.trusted.as_sat(), 100_000);
+    }
 }
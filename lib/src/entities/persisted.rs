@@ -1,18 +1,24 @@
 // https://dreampuf.github.io/GraphvizOnline/#digraph%20G%20%7B%0A%20%20%22.firma%22%20-%3E%20%22%5Bnetwork%5D%22%0A%20%20%0A%20%20%22%5Bnetwork%5D%22%20-%3E%20wallets%0A%20%20%22%5Bnetwork%5D%22%20-%3E%20keys%0A%20%20%22%5Bnetwork%5D%22%20-%3E%20psbts%0A%20%20%22%5Bnetwork%5D%22%20-%3E%20%22daemon_opts%22%20%0A%20%20%0A%20%20keys%20-%3E%20%22%5Bkey%20name%5D%22%0A%20%20%22master_secret%22%20%5Bshape%3DSquare%5D%0A%20%20%22descriptor_public_key%22%20%5Bshape%3DSquare%5D%0A%20%20%22%5Bkey%20name%5D%22%20-%3E%20%22master_secret%22%20%0A%20%20%22%5Bkey%20name%5D%22%20-%3E%20%22descriptor_public_key%22%20%0A%20%20%0A%20%20wallets%20-%3E%20%22%5Bwallet%20name%5D%22%0A%20%20%22wallet%22%20%5Bshape%3DSquare%5D%0A%20%20%22wallet_indexes%22%20%5Bshape%3DSquare%5D%0A%20%20%22daemon_opts%22%20%5Bshape%3DSquare%5D%0A%20%20%22wallet_signature%22%20%5Bshape%3DSquare%5D%0A%20%20%22%5Bwallet%20name%5D%22%20-%3E%20%22wallet%22%20%0A%20%20%22%5Bwallet%20name%5D%22%20-%3E%20%22wallet_indexes%22%20%0A%20%20%22%5Bwallet%20name%5D%22%20-%3E%20%22wallet_signature%22%20%0A%20%20%0A%20%20psbts%20-%3E%20%22%5Bpsbt%20name%5D%22%0A%20%20%22psbt%22%20%5Bshape%3DSquare%5D%0A%20%20%22%5Bpsbt%20name%5D%22%20-%3E%20%22psbt%22%20%0A%7D
 
+use crate::codex32;
 use crate::mnemonic::Mnemonic;
-use crate::offline::descriptor::{parse_descriptor_with_checksum, ExtendedDescriptor};
+use crate::offline::descriptor::{
+    change_descriptor, parse_descriptor_with_checksum, receive_descriptor, ExtendedDescriptor,
+};
 use crate::offline::sign::get_psbt_name;
 use crate::offline::sign_wallet::WALLET_SIGN_DERIVATION;
+use crate::offline::taproot::{is_taproot, TrMulti};
+use crate::offline::DerivationOptions;
 use crate::{
     check_compatibility, psbt_from_base64, psbt_to_base64, BitcoinPsbt, Error, Identifier, Kind,
     Result,
 };
+use bitcoin::bech32::ToBase32;
 use bitcoin::secp256k1::{Secp256k1, Signing};
 use bitcoin::util::bip32::{DerivationPath, ExtendedPrivKey, ExtendedPubKey, Fingerprint};
-use bitcoin::{secp256k1, Network};
+use bitcoin::{secp256k1, Network, OutPoint};
 use log::debug;
-use miniscript::descriptor::{DescriptorXKey, Wildcard};
+use miniscript::descriptor::{DescriptorXKey, ShInner, Wildcard};
 use miniscript::{Descriptor, ForEachKey};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
@@ -28,6 +34,15 @@ pub struct Wallet {
 pub struct WalletIndexes {
     pub id: Identifier,
     pub main: u32,
+    #[serde(default)]
+    pub change: u32,
+    /// last index of both chains imported in the bitcoin node
+    #[serde(default = "default_imported")]
+    pub imported: u32,
+}
+
+fn default_imported() -> u32 {
+    crate::online::create_wallet::DEFAULT_IMPORT_RANGE
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -46,6 +61,17 @@ pub struct MasterSecret {
     pub mnemonic: Option<Mnemonic>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub dice: Option<Dice>,
+    /// Derivation of the descriptor public key, `m/48'/coin'/0'/2'` if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derivation_path: Option<DerivationPath>,
+    /// The key is derived from the mnemonic with a BIP39 passphrase which is not persisted,
+    /// the persisted `key` is derived without the passphrase and `fingerprint` is the one of the
+    /// key with the passphrase
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub passphrase: bool,
+    /// The codex32 secret share the key has been restored from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codex32: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -65,6 +91,65 @@ pub struct Psbt {
     pub psbt: String,
 }
 
+/// Labels of the wallet in the BIP329 format, outputs labeled not spendable are frozen
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WalletLabels {
+    pub id: Identifier,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+}
+
+/// What a BIP329 label refers to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelType {
+    Tx,
+    Addr,
+    Pubkey,
+    Input,
+    Output,
+    Xpub,
+}
+
+impl FromStr for LabelType {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "tx" => Ok(LabelType::Tx),
+            "addr" => Ok(LabelType::Addr),
+            "pubkey" => Ok(LabelType::Pubkey),
+            "input" => Ok(LabelType::Input),
+            "output" => Ok(LabelType::Output),
+            "xpub" => Ok(LabelType::Xpub),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "({}) valid values are: tx, addr, pubkey, input, output, xpub",
+                    s
+                ),
+            )),
+        }
+    }
+}
+
+/// A BIP329 label record
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Label {
+    #[serde(rename = "type")]
+    pub label_type: LabelType,
+    /// txid, address, outpoint or key depending on `label_type`
+    #[serde(rename = "ref")]
+    pub reference: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    /// only for outputs, false if the coin is frozen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spendable: Option<bool>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Dice {
     pub launches: String,
@@ -73,13 +158,32 @@ pub struct Dice {
 }
 
 impl Wallet {
+    /// descriptor of the change chain `/1/*`, derived from the (signed) wallet descriptor
+    pub fn change_descriptor(&self) -> Result<String> {
+        change_descriptor(&self.descriptor)
+    }
+
+    /// descriptor of the receive chain `/0/*`
+    pub fn receive_descriptor(&self) -> Result<String> {
+        receive_descriptor(&self.descriptor)
+    }
+
+    /// keys of the receive descriptor
     pub fn extract_desc_pub_keys(&self) -> Result<Vec<miniscript::DescriptorPublicKey>> {
+        let receive = self.receive_descriptor()?;
+        if is_taproot(&receive) {
+            let descriptor: TrMulti = receive.parse()?;
+            return Ok(descriptor.keys);
+        }
         let mut desc_pub_keys = vec![];
-        let end = self.descriptor.find('#').unwrap_or(self.descriptor.len());
-        let descriptor: miniscript::Descriptor<miniscript::DescriptorPublicKey> =
-            self.descriptor[..end].parse().unwrap();
-        if let Descriptor::Wsh(miniscript) = descriptor {
-            miniscript.for_each_key(|k| {
+        let descriptor = parse_descriptor_with_checksum(&receive)?;
+        let is_wsh = match &descriptor {
+            Descriptor::Wsh(_) => true,
+            Descriptor::Sh(sh) => matches!(sh.as_inner(), ShInner::Wsh(_)),
+            _ => false,
+        };
+        if is_wsh {
+            descriptor.for_each_key(|k| {
                 desc_pub_keys.push(k.as_key().clone());
                 true
             });
@@ -114,7 +218,7 @@ impl Wallet {
         result
     }
     pub fn descriptor(&self) -> Result<ExtendedDescriptor> {
-        parse_descriptor_with_checksum(&self.descriptor)
+        parse_descriptor_with_checksum(&self.receive_descriptor()?)
     }
 }
 
@@ -147,6 +251,9 @@ impl MasterSecret {
             fingerprint,
             mnemonic: None,
             dice: None,
+            derivation_path: None,
+            passphrase: false,
+            codex32: None,
             id: Identifier::new(network, Kind::MasterSecret, name),
         })
     }
@@ -162,20 +269,95 @@ impl MasterSecret {
             fingerprint,
             mnemonic: Some(mnemonic),
             dice: None,
+            derivation_path: None,
+            passphrase: false,
+            codex32: None,
             id: Identifier::new(network, Kind::MasterSecret, name),
         })
     }
 
-    fn path(&self) -> DerivationPath {
-        //TODO copay multisig derivation, not sure
-        let n = match self.network {
-            Network::Bitcoin => "0",
-            Network::Testnet => "1",
-            Network::Regtest => "2",
-            Network::Signet => "3",
-        };
-        // m / 48' / coin_type' / account' / script_type' / change / address_index
-        DerivationPath::from_str(&format!("m/48'/{}'/0'/2'", n)).unwrap()
+    pub fn from_codex32(network: Network, secret: &codex32::Share, name: &str) -> Result<Self> {
+        let key = ExtendedPrivKey::new_master(network, &secret.seed()?)?;
+        let mut master_secret = MasterSecret::from_xprv(network, key, name)?;
+        master_secret.codex32 = Some(secret.to_string());
+        Ok(master_secret)
+    }
+
+    /// the seed as codex32 secret share, available if the key has been created from a mnemonic
+    /// without passphrase or restored from codex32, the identifier is the bech32 encoding of the
+    /// first 20 bits of the fingerprint
+    pub fn as_codex32(&self) -> Result<Option<codex32::Share>> {
+        if let Some(secret) = self.codex32.as_ref() {
+            return Ok(Some(codex32::Share::from_str(secret)?));
+        }
+        match self.mnemonic.as_ref() {
+            Some(mnemonic) if !self.passphrase => {
+                let fingerprint = hex::decode(&self.fingerprint)?;
+                let identifier = fingerprint.to_base32()[..4]
+                    .iter()
+                    .map(|u| u.to_char())
+                    .collect::<String>();
+                let seed = mnemonic.to_seed(None);
+                Ok(Some(codex32::Share::from_seed(&seed.0, &identifier)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub(crate) fn path(&self) -> DerivationPath {
+        match &self.derivation_path {
+            Some(path) => path.clone(),
+            // m / 48' / coin_type' / account' / script_type' / change / address_index
+            None => DerivationOptions::default().path(self.network).unwrap(),
+        }
+    }
+
+    /// derive the key from the mnemonic with the BIP39 `passphrase`, if any
+    pub fn with_passphrase(mut self, passphrase: Option<&str>) -> Result<Self> {
+        if let Some(passphrase) = passphrase {
+            let mnemonic = self
+                .mnemonic
+                .as_ref()
+                .ok_or(Error::PassphraseWithoutMnemonic)?;
+            let seed = mnemonic.to_seed(Some(passphrase));
+            self.key = ExtendedPrivKey::new_master(self.network, &seed.0)?;
+            self.fingerprint = self.key.fingerprint(&Secp256k1::signing_only()).to_string();
+            self.passphrase = true;
+        }
+        Ok(self)
+    }
+
+    /// the key as persisted, without the passphrase
+    pub fn locked(&self) -> Result<Self> {
+        let mut locked = self.clone();
+        if self.passphrase {
+            let mnemonic = self
+                .mnemonic
+                .as_ref()
+                .ok_or(Error::PassphraseWithoutMnemonic)?;
+            let seed = mnemonic.to_seed(None);
+            locked.key = ExtendedPrivKey::new_master(self.network, &seed.0)?;
+        }
+        Ok(locked)
+    }
+
+    /// the key usable for signing, a persisted key created with a passphrase requires it
+    pub fn unlock(&self, passphrase: Option<&str>) -> Result<Self> {
+        if !self.passphrase {
+            return Ok(self.clone());
+        }
+        let passphrase = passphrase.ok_or(Error::PassphraseRequired)?;
+        let unlocked = self.clone().with_passphrase(Some(passphrase))?;
+        if unlocked.fingerprint != self.fingerprint {
+            return Err(Error::WrongPassphrase);
+        }
+        Ok(unlocked)
+    }
+
+    /// set the derivation of the descriptor public key
+    pub fn with_derivation(mut self, opt: &DerivationOptions) -> Result<Self> {
+        self.derivation_path = Some(opt.path(self.network)?);
+        Ok(self)
     }
 
     pub fn as_desc_prv_key<T: Signing>(&self, secp: &Secp256k1<T>) -> Result<ExtendedPrivKey> {
@@ -187,7 +369,16 @@ impl MasterSecret {
         &self,
         secp: &Secp256k1<T>,
     ) -> Result<ExtendedPrivKey> {
-        let k = self.as_desc_prv_key(secp)?;
+        self.as_wallet_sign_prv_key_at(secp, &self.path())
+    }
+
+    /// the key signing wallets, derived from the account at `path`
+    pub fn as_wallet_sign_prv_key_at<T: Signing>(
+        &self,
+        secp: &Secp256k1<T>,
+        path: &DerivationPath,
+    ) -> Result<ExtendedPrivKey> {
+        let k = self.key.derive_priv(secp, path)?;
         Ok(k.derive_priv(
             secp,
             &DerivationPath::from_str(&format!("m/0/{}", WALLET_SIGN_DERIVATION))?,
@@ -206,16 +397,34 @@ impl MasterSecret {
     /// returns the public part of the key, it is an expensive method cause it's initializing a
     /// secp context
     pub fn as_desc_pub_key(&self) -> Result<DescriptorPublicKey> {
+        let id = self.id.with_kind(Kind::DescriptorPublicKey);
+        self.desc_pub_key_at(id, &self.path())
+    }
+
+    /// returns the public part of the key derived for the account in `opt`, persisted in addition
+    /// to the one returned by `as_desc_pub_key`
+    pub fn as_account_desc_pub_key(&self, opt: &DerivationOptions) -> Result<DescriptorPublicKey> {
+        let id = self
+            .id
+            .with_kind(Kind::DescriptorPublicKey)
+            .with_account(opt.scheme, opt.account);
+        self.desc_pub_key_at(id, &opt.path(self.network)?)
+    }
+
+    fn desc_pub_key_at(
+        &self,
+        id: Identifier,
+        path: &DerivationPath,
+    ) -> Result<DescriptorPublicKey> {
         let secp = Secp256k1::signing_only();
-        let xprv_derived = self.as_desc_prv_key(&secp)?;
+        let xprv_derived = self.key.derive_priv(&secp, path)?;
         let xpub = ExtendedPubKey::from_private(&secp, &xprv_derived);
         let desc_pub_key = miniscript::DescriptorPublicKey::XPub(DescriptorXKey {
-            origin: Some((self.key.fingerprint(&secp), self.path())),
+            origin: Some((self.key.fingerprint(&secp), path.clone())),
             xkey: xpub,
             derivation_path: DerivationPath::from_str("m/0")?,
             wildcard: Wildcard::Unhardened,
         });
-        let id = self.id.with_kind(Kind::DescriptorPublicKey);
         Ok(DescriptorPublicKey {
             id,
             desc_pub_key: desc_pub_key.to_string(),
@@ -237,6 +446,70 @@ impl Psbt {
     }
 }
 
+impl WalletLabels {
+    pub fn new(network: Network, wallet_name: &str) -> Self {
+        WalletLabels {
+            id: Identifier::new(network, Kind::WalletLabels, wallet_name),
+            labels: vec![],
+        }
+    }
+
+    fn find(&self, label_type: LabelType, reference: &str) -> Option<&Label> {
+        self.labels
+            .iter()
+            .find(|l| l.label_type == label_type && l.reference == reference)
+    }
+
+    /// the label text of `reference`, if any
+    pub fn label(&self, label_type: LabelType, reference: &str) -> Option<&str> {
+        self.find(label_type, reference)
+            .and_then(|l| l.label.as_deref())
+    }
+
+    /// true if the output `outpoint` must not be spent
+    pub fn is_frozen(&self, outpoint: &OutPoint) -> bool {
+        self.find(LabelType::Output, &outpoint.to_string())
+            .and_then(|l| l.spendable)
+            == Some(false)
+    }
+
+    /// insert `label` or update the existing one with the same type and reference, fields
+    /// missing in `label` are kept
+    pub fn set(&mut self, label: Label) {
+        let existing = self
+            .labels
+            .iter_mut()
+            .find(|l| l.label_type == label.label_type && l.reference == label.reference);
+        match existing {
+            Some(existing) => {
+                existing.label = label.label.or_else(|| existing.label.take());
+                existing.origin = label.origin.or_else(|| existing.origin.take());
+                existing.spendable = label.spendable.or(existing.spendable);
+            }
+            None => self.labels.push(label),
+        }
+    }
+
+    /// parse labels in the BIP329 JSON lines format, empty lines are skipped
+    pub fn from_bip329(content: &str) -> Result<Vec<Label>> {
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+
+    /// the labels in the BIP329 JSON lines format
+    pub fn to_bip329(&self) -> Result<String> {
+        let mut content = String::new();
+        for label in self.labels.iter() {
+            content.push_str(&serde_json::to_string(label)?);
+            content.push('\n');
+        }
+        Ok(content)
+    }
+}
+
 impl From<(&BitcoinPsbt, Network)> for Psbt {
     fn from(psbt_and_network: (&BitcoinPsbt, Network)) -> Self {
         let (psbt, network) = psbt_and_network;
@@ -0,0 +1,98 @@
+use crate::offline::DerivationOptions;
+use crate::*;
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+/// Derive the descriptor public key of another account of an existing master key
+#[derive(StructOpt, Debug, Serialize, Deserialize)]
+#[structopt(name = "derive-key")]
+pub struct DeriveKeyOptions {
+    /// Name of the key
+    #[structopt(short, long)]
+    pub key_name: String,
+
+    #[structopt(flatten)]
+    #[serde(flatten)]
+    pub derivation: DerivationOptions,
+
+    /// BIP39 passphrase, never persisted, in the CLI it's read from stdin with `--passphrase`
+    #[structopt(skip)]
+    #[serde(default, skip_serializing)]
+    pub passphrase: Option<String>,
+}
+
+impl OfflineContext {
+    /// persist the descriptor public key in
+    /// `keys/<name>/descriptor_public_key_<scheme>_<account>.json`, use
+    /// `<name>:<scheme>:<account>` as key name to refer to it, or `<name>:<account>` if the
+    /// account is derived with one scheme only
+    pub fn derive_key(&self, opt: &DeriveKeyOptions) -> Result<DescriptorPublicKey> {
+        let secret: MasterSecret = self.read(&opt.key_name)?;
+        let secret = secret.unlock(opt.passphrase.as_deref())?;
+        let public = secret.as_account_desc_pub_key(&opt.derivation)?;
+        self.write(&public)?;
+        Ok(public)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::context::tests::TestContext;
+    use crate::offline::derive_key::DeriveKeyOptions;
+    use crate::offline::random::RandomOptions;
+    use crate::offline::{DerivationOptions, Scheme};
+
+    #[test]
+    fn test_derive_key() {
+        let context = TestContext::default();
+        let rand_opts = RandomOptions::new_random();
+        let key_name = rand_opts.key_name.clone();
+        let key = context.create_key(&rand_opts).unwrap();
+
+        let opt = DeriveKeyOptions {
+            key_name: key_name.clone(),
+            derivation: DerivationOptions {
+                scheme: Scheme::Bip87,
+                account: 1,
+            },
+            passphrase: None,
+        };
+        let public = context.derive_key(&opt).unwrap();
+        assert_eq!(public.origin_path().unwrap().to_string(), "m/87'/1'/1'");
+        assert!(context.derive_key(&opt).is_err(), "account overwritten");
+
+        let all = context.read_all_desc_pub_keys(&key_name).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0], key.as_desc_pub_key().unwrap());
+        assert_eq!(all[1], public);
+
+        let names = vec![key_name.clone(), format!("{}:1", key_name)];
+        let keys = context.read_desc_pub_keys_from_names(&names).unwrap();
+        assert_eq!(keys[1], public.key().unwrap());
+        let names = vec![format!("{}:2", key_name)];
+        assert!(context.read_desc_pub_keys_from_names(&names).is_err());
+
+        let nested_opt = DeriveKeyOptions {
+            derivation: DerivationOptions {
+                scheme: Scheme::Bip48Nested,
+                account: 1,
+            },
+            ..opt
+        };
+        let nested = context.derive_key(&nested_opt).unwrap();
+        assert_eq!(nested.origin_path().unwrap().to_string(), "m/48'/1'/1'/1'");
+        assert_eq!(context.read_all_desc_pub_keys(&key_name).unwrap().len(), 3);
+        let names = vec![format!("{}:1", key_name)];
+        assert!(
+            context.read_desc_pub_keys_from_names(&names).is_err(),
+            "ambiguous account"
+        );
+        let names = vec![
+            format!("{}:bip87:1", key_name),
+            format!("{}:bip48-nested:1", key_name),
+        ];
+        let keys = context.read_desc_pub_keys_from_names(&names).unwrap();
+        assert_eq!(keys[0], public.key().unwrap());
+        assert_eq!(keys[1], nested.key().unwrap());
+    }
+}
@@ -1,8 +1,13 @@
+use crate::offline::taproot::{is_taproot, TrMulti};
 use crate::*;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::util::bip32::{ChildNumber, DerivationPath};
 use bitcoin::Network;
-use miniscript::{DescriptorTrait, TranslatePk2};
+use miniscript::descriptor::{ShInner, Wsh, WshInner};
+use miniscript::{
+    Descriptor, DescriptorPublicKey, DescriptorTrait, ForEachKey, Terminal, TranslatePk1,
+    TranslatePk2,
+};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
@@ -21,6 +26,64 @@ impl DeriveAddressOptions {
     }
 }
 
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn poly_mod(mut c: u64, val: u64) -> u64 {
+    let c0 = c >> 35;
+    c = ((c & 0x7ffffffff) << 5) ^ val;
+    if c0 & 1 > 0 {
+        c ^= 0xf5dee51989
+    };
+    if c0 & 2 > 0 {
+        c ^= 0xa9fdca3312
+    };
+    if c0 & 4 > 0 {
+        c ^= 0x1bab10e32d
+    };
+    if c0 & 8 > 0 {
+        c ^= 0x3706b1677a
+    };
+    if c0 & 16 > 0 {
+        c ^= 0x644d626ffd
+    };
+    c
+}
+
+/// compute the descriptor checksum as defined in bitcoin core, needed for descriptors the node
+/// may not be able to parse (eg. taproot)
+pub fn descriptor_checksum(descriptor: &str) -> Result<String> {
+    let mut c = 1;
+    let mut cls = 0;
+    let mut clscount = 0;
+    for ch in descriptor.chars() {
+        let pos = INPUT_CHARSET
+            .find(ch)
+            .ok_or_else(|| Error::Generic(format!("invalid character in descriptor: {}", ch)))?
+            as u64;
+        c = poly_mod(c, pos & 31);
+        cls = cls * 3 + (pos >> 5);
+        clscount += 1;
+        if clscount == 3 {
+            c = poly_mod(c, cls);
+            cls = 0;
+            clscount = 0;
+        }
+    }
+    if clscount > 0 {
+        c = poly_mod(c, cls);
+    }
+    for _ in 0..8 {
+        c = poly_mod(c, 0);
+    }
+    c ^= 1;
+
+    let checksum = (0..8)
+        .map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char)
+        .collect();
+    Ok(checksum)
+}
+
 pub fn parse_descriptor_with_checksum(descriptor: &str) -> Result<ExtendedDescriptor> {
     // checksum not supported at the moment, stripping out
     // TODO check
@@ -30,20 +93,140 @@ pub fn parse_descriptor_with_checksum(descriptor: &str) -> Result<ExtendedDescri
     Ok(descriptor)
 }
 
+/// true if the bitcoin node is able to parse and derive the descriptor: `multi` and
+/// `sortedmulti`, while taproot and generic miniscript descriptors are derived locally
+pub fn node_can_derive(descriptor: &str) -> bool {
+    match parse_descriptor_with_checksum(descriptor) {
+        Ok(Descriptor::Wsh(wsh)) => wsh_is_multi(&wsh),
+        Ok(Descriptor::Sh(sh)) => match sh.as_inner() {
+            ShInner::Wsh(wsh) => wsh_is_multi(wsh),
+            _ => true,
+        },
+        Ok(_) => true,
+        Err(_) => false,
+    }
+}
+
+/// BIP389 multipath derivation step covering the receive and the change chain
+const MULTIPATH: &str = "<0;1>";
+
+/// true if keys in the descriptor use BIP389 multipath derivation `/<0;1>/*`
+pub fn is_multipath(descriptor: &str) -> bool {
+    descriptor.contains(MULTIPATH)
+}
+
+/// the single path descriptor of a multipath descriptor for the given `chain` (0 receive,
+/// 1 change), the checksum is recomputed
+pub fn single_path_descriptor(descriptor: &str, chain: u32) -> Result<String> {
+    let end = descriptor.find('#').unwrap_or(descriptor.len());
+    let single = descriptor[..end].replace(MULTIPATH, &chain.to_string());
+    Ok(format!("{}#{}", single, descriptor_checksum(&single)?))
+}
+
+/// the multipath descriptor `/<0;1>/*` of a receive descriptor with keys ending in `/0/*`
+pub fn multipath_descriptor(descriptor: &str) -> Result<String> {
+    if is_multipath(descriptor) {
+        return Ok(descriptor.to_string());
+    }
+    change_descriptor(descriptor)?; // checks every key is on the receive chain
+    let end = descriptor.find('#').unwrap_or(descriptor.len());
+    let multi = descriptor[..end].replace("/0/*", &format!("/{}/*", MULTIPATH));
+    Ok(format!("{}#{}", multi, descriptor_checksum(&multi)?))
+}
+
+/// the receive descriptor `/0/*`, single path descriptors are returned as they are
+pub fn receive_descriptor(descriptor: &str) -> Result<String> {
+    if is_multipath(descriptor) {
+        single_path_descriptor(descriptor, 0)
+    } else {
+        Ok(descriptor.to_string())
+    }
+}
+
+/// the change descriptor of a wallet descriptor, keys derivation ending in `/0/*` (receive chain)
+/// are replaced with `/1/*` (change chain)
+pub fn change_descriptor(descriptor: &str) -> Result<String> {
+    if is_multipath(descriptor) {
+        return single_path_descriptor(descriptor, 1);
+    }
+    if is_taproot(descriptor) {
+        let mut tr_multi: TrMulti = descriptor.parse()?;
+        tr_multi.keys = tr_multi
+            .keys
+            .iter()
+            .map(to_change_key)
+            .collect::<Result<_>>()?;
+        let tr_multi = tr_multi.to_string();
+        return Ok(format!("{}#{}", tr_multi, descriptor_checksum(&tr_multi)?));
+    }
+    let descriptor = parse_descriptor_with_checksum(descriptor)?;
+    let change: ExtendedDescriptor = descriptor.translate_pk1(to_change_key)?;
+    Ok(change.to_string()) // miniscript adds the checksum
+}
+
+fn to_change_key(key: &DescriptorPublicKey) -> Result<DescriptorPublicKey> {
+    if let DescriptorPublicKey::XPub(xpub) = key {
+        let mut path: Vec<ChildNumber> = xpub.derivation_path.clone().into();
+        if path.pop() == Some(ChildNumber::Normal { index: 0 }) {
+            path.push(ChildNumber::Normal { index: 1 });
+            let mut xpub = xpub.clone();
+            xpub.derivation_path = path.into();
+            return Ok(DescriptorPublicKey::XPub(xpub));
+        }
+    }
+    Err(Error::Generic(format!(
+        "key {} is not derived from the receive chain /0/*",
+        key
+    )))
+}
+
+/// the chain of the key, 0 for receive and 1 for change
+fn key_chain(key: &DescriptorPublicKey) -> u32 {
+    match key {
+        DescriptorPublicKey::XPub(xpub) => match xpub.derivation_path.as_ref().last() {
+            Some(ChildNumber::Normal { index }) => *index,
+            _ => 0,
+        },
+        _ => 0,
+    }
+}
+
+fn wsh_is_multi(wsh: &Wsh<DescriptorPublicKey>) -> bool {
+    match wsh.as_inner() {
+        WshInner::SortedMulti(_) => true,
+        WshInner::Ms(ms) => matches!(ms.node, Terminal::Multi(..)),
+    }
+}
+
 /// derive address from descriptor in the form "wsh(multi({n},{x}/{c}/*,{y}/{c}/*,...))#5wstxmwd"
+/// or "tr(NUMS,multi_a({n},{x}/{c}/*,{y}/{c}/*,...))#checksum", multipath descriptors derive the
+/// receive chain
 pub fn derive_address(network: Network, opt: &DeriveAddressOptions) -> Result<GetAddressOutput> {
     opt.validate()?;
-    let descriptor = parse_descriptor_with_checksum(&opt.descriptor)?;
 
     let secp = Secp256k1::verification_only();
-    //let context = DescriptorPublicKeyCtx::new(&secp, ChildNumber::from_normal_idx(opt.index)?);
-    let address = descriptor
-        .derive(opt.index)
-        .translate_pk2(|xpk| xpk.derive_public_key(&secp))
-        .unwrap()
-        .address(network)?;
+    let descriptor = receive_descriptor(&opt.descriptor)?;
+    let (address, chain) = if is_taproot(&descriptor) {
+        let descriptor: TrMulti = descriptor.parse()?;
+        let chain = descriptor.keys.first().map(key_chain).unwrap_or(0);
+        (descriptor.derive(&secp, opt.index)?.address(network), chain)
+    } else {
+        let descriptor = parse_descriptor_with_checksum(&descriptor)?;
+        let mut chain = 0;
+        descriptor.for_each_key(|k| {
+            chain = key_chain(k.as_key());
+            false
+        });
+        //let context = DescriptorPublicKeyCtx::new(&secp, ChildNumber::from_normal_idx(opt.index)?);
+        let address = descriptor
+            .derive(opt.index)
+            .translate_pk2(|xpk| xpk.derive_public_key(&secp))
+            .unwrap()
+            .address(network)?;
+        (address, chain)
+    };
 
-    let path = DerivationPath::from_str(&format!("m/0/{}", opt.index))?;
+    let path = DerivationPath::from_str(&format!("m/{}/{}", chain, opt.index))?;
 
     Ok(GetAddressOutput {
         address,
@@ -76,11 +259,100 @@ mod tests {
         );
 
         assert_eq!("m/0/0", derived_address.path.to_string());
+        assert!(node_can_derive(DESCRIPTOR));
         opts.index = 2147483648;
         let err = derive_address(Network::Testnet, &opts);
         assert_matches!(err, Err(Error::Bip32(InvalidChildNumber(2147483648))));
     }
 
+    #[test]
+    fn change_descriptor_test() {
+        let change = change_descriptor(DESCRIPTOR).unwrap();
+        assert!(!change.contains("/0/*"));
+        assert_eq!(change.matches("/1/*").count(), 2);
+        let (desc, checksum) = change.split_at(change.find('#').unwrap());
+        assert_eq!(format!("#{}", descriptor_checksum(desc).unwrap()), checksum);
+        let opts = DeriveAddressOptions {
+            descriptor: change.clone(),
+            index: 0,
+        };
+        let derived_address = derive_address(Network::Testnet, &opts).unwrap();
+        assert_ne!(
+            "tb1q5nrregep899vnvaa5vdpxcwg8794jqy38nu304kl4d7wm4e92yeqz4jfmk",
+            derived_address.address.to_string()
+        );
+        assert_eq!("m/1/0", derived_address.path.to_string());
+        assert!(change_descriptor(&change).is_err());
+
+        let k1 = "[a2ebe04e/48'/1'/0'/2']tpubDEXDRpvW2srXCSjAvC36zYkSE3jxT1wf7JXDo35Ln4NZpmaMNhq8o9coH9U9BQ5bAN4WDGxXV9d426iYKGorFF5wvv4Wv63cZsCotiXGGkD/0/*";
+        let desc = format!("tr({},multi_a(1,{}))", crate::offline::taproot::NUMS, k1);
+        let change = change_descriptor(&desc).unwrap();
+        assert!(change.contains("multi_a(1,[a2ebe04e/48'/1'/0'/2']tpub"));
+        assert!(change.contains("/1/*))#"));
+        let opts = DeriveAddressOptions {
+            descriptor: change,
+            index: 3,
+        };
+        let derived_address = derive_address(Network::Testnet, &opts).unwrap();
+        assert_eq!("m/1/3", derived_address.path.to_string());
+    }
+
+    #[test]
+    fn multipath_descriptor_test() {
+        let multipath = multipath_descriptor(DESCRIPTOR).unwrap();
+        assert!(is_multipath(&multipath));
+        assert_eq!(multipath.matches("/<0;1>/*").count(), 2);
+        assert_eq!(multipath_descriptor(&multipath).unwrap(), multipath);
+        assert_eq!(receive_descriptor(&multipath).unwrap(), DESCRIPTOR);
+        assert_eq!(receive_descriptor(DESCRIPTOR).unwrap(), DESCRIPTOR);
+        assert_eq!(
+            change_descriptor(&multipath).unwrap(),
+            change_descriptor(DESCRIPTOR).unwrap()
+        );
+        assert!(multipath_descriptor(&change_descriptor(DESCRIPTOR).unwrap()).is_err());
+
+        let opts = DeriveAddressOptions {
+            descriptor: multipath,
+            index: 0,
+        };
+        let derived_address = derive_address(Network::Testnet, &opts).unwrap();
+        assert_eq!(
+            "tb1q5nrregep899vnvaa5vdpxcwg8794jqy38nu304kl4d7wm4e92yeqz4jfmk",
+            derived_address.address.to_string()
+        );
+        assert_eq!("m/0/0", derived_address.path.to_string());
+    }
+
+    #[test]
+    fn descriptor_checksum_test() {
+        let (desc, checksum) = DESCRIPTOR.split_at(DESCRIPTOR.find('#').unwrap());
+        assert_eq!(format!("#{}", descriptor_checksum(desc).unwrap()), checksum);
+        assert!(descriptor_checksum("wsh(multi(1,€))").is_err());
+    }
+
+    #[test]
+    fn derive_taproot_address_test() {
+        let k1 = "[a2ebe04e/48'/1'/0'/2']tpubDEXDRpvW2srXCSjAvC36zYkSE3jxT1wf7JXDo35Ln4NZpmaMNhq8o9coH9U9BQ5bAN4WDGxXV9d426iYKGorFF5wvv4Wv63cZsCotiXGGkD/0/*";
+        let k2 = "[1f5e43d8/48'/1'/0'/2']tpubDFU4parcXvV8tBYt4rS4a8rGNF1DA32DCnRfhzVL6b3MSiDomV95rv9mb7W7jAPMTohyEYpbhVS8FbmTsuQsFRxDWPJX2ZFEeRPMFz3R1gh/0/*";
+        let desc = format!(
+            "tr({},multi_a(2,{},{}))",
+            crate::offline::taproot::NUMS,
+            k1,
+            k2
+        );
+        let opts = DeriveAddressOptions {
+            descriptor: desc,
+            index: 0,
+        };
+        let derived_address = derive_address(Network::Testnet, &opts).unwrap();
+        assert_eq!(
+            "tb1p26h6maj6l2er2mvu20hqc9adytylnkc09g390z3dcxd3eu23wcjqjtq06z",
+            derived_address.address.to_string()
+        );
+        assert_eq!("m/0/0", derived_address.path.to_string());
+        assert!(!node_can_derive(&opts.descriptor));
+    }
+
     #[test]
     fn descriptor_extract_keys_test() {
         let k1 = "[a2ebe04e/48'/1'/0'/2']tpubDEXDRpvW2srXCSjAvC36zYkSE3jxT1wf7JXDo35Ln4NZpmaMNhq8o9coH9U9BQ5bAN4WDGxXV9d426iYKGorFF5wvv4Wv63cZsCotiXGGkD/0/*";
@@ -91,6 +363,8 @@ mod tests {
                 kind: Kind::Wallet,
                 name: "azz".to_string(),
                 network: Network::Testnet,
+                account: None,
+                scheme: None,
             },
             descriptor: desc,
             created_at_height: 0,
@@ -1,4 +1,5 @@
 use crate::mnemonic::Mnemonic;
+use crate::offline::DerivationOptions;
 use crate::*;
 use bitcoin::Network;
 use num_bigint::BigUint;
@@ -26,6 +27,15 @@ pub struct DiceOptions {
     /// Value of the die launch, to be repeated multiple times
     #[structopt(short, required = true)]
     pub launches: Vec<u32>,
+
+    #[structopt(flatten)]
+    #[serde(flatten)]
+    pub derivation: DerivationOptions,
+
+    /// BIP39 passphrase, never persisted, in the CLI it's read from stdin with `--passphrase`
+    #[structopt(skip)]
+    #[serde(default, skip_serializing)]
+    pub passphrase: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -77,7 +87,9 @@ impl OfflineContext {
         opt.validate()?;
 
         let master_key =
-            calculate_key(&opt.launches, opt.faces as u32, self.network, &opt.key_name)?;
+            calculate_key(&opt.launches, opt.faces as u32, self.network, &opt.key_name)?
+                .with_passphrase(opt.passphrase.as_deref())?
+                .with_derivation(&opt.derivation)?;
 
         self.write_keys(&master_key)?;
 
@@ -212,6 +224,8 @@ mod tests {
             bits: Bits::_128,
             key_name: "a".to_string(),
             launches,
+            derivation: Default::default(),
+            passphrase: None,
         };
         let context = TestContext::default();
 
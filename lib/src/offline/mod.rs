@@ -1,4 +1,5 @@
 pub mod decrypt;
+pub mod derive_key;
 pub mod descriptor;
 pub mod dice;
 pub mod print;
@@ -6,3 +7,127 @@ pub mod random;
 pub mod restore;
 pub mod sign;
 pub mod sign_wallet;
+pub mod split;
+pub mod taproot;
+
+use crate::*;
+use bitcoin::util::bip32::{ChildNumber, DerivationPath};
+use bitcoin::Network;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+/// Derivation of the descriptor public key created for a master key
+#[derive(StructOpt, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DerivationOptions {
+    /// Derivation scheme, valid values are: bip48 (m/48'/coin'/account'/2'),
+    /// bip48-nested (m/48'/coin'/account'/1'), bip87 (m/87'/coin'/account')
+    #[structopt(long, default_value = "bip48")]
+    #[serde(default)]
+    pub scheme: Scheme,
+
+    /// Account number in the derivation path
+    #[structopt(long, default_value = "0")]
+    #[serde(default)]
+    pub account: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Scheme {
+    /// BIP48 with script type 2' native segwit
+    Bip48,
+    /// BIP48 with script type 1' nested segwit
+    Bip48Nested,
+    /// BIP87 without script type
+    Bip87,
+}
+
+// `#[default]` on enum variants requires rust 1.62, above the supported 1.56
+#[allow(clippy::derivable_impls)]
+impl Default for Scheme {
+    fn default() -> Self {
+        Scheme::Bip48
+    }
+}
+
+impl Display for Scheme {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Scheme::Bip48 => "bip48",
+            Scheme::Bip48Nested => "bip48-nested",
+            Scheme::Bip87 => "bip87",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for Scheme {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "bip48" => Ok(Scheme::Bip48),
+            "bip48-nested" => Ok(Scheme::Bip48Nested),
+            "bip87" => Ok(Scheme::Bip87),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("({}) valid values are: bip48, bip48-nested, bip87", s),
+            )),
+        }
+    }
+}
+
+impl DerivationOptions {
+    pub fn path(&self, network: Network) -> Result<DerivationPath> {
+        let coin = match network {
+            Network::Bitcoin => 0,
+            Network::Testnet => 1,
+            Network::Regtest => 2,
+            Network::Signet => 3,
+        };
+        let (purpose, script_type) = match self.scheme {
+            Scheme::Bip48 => (48, Some(2)),
+            Scheme::Bip48Nested => (48, Some(1)),
+            Scheme::Bip87 => (87, None),
+        };
+        let mut path = vec![
+            ChildNumber::from_hardened_idx(purpose)?,
+            ChildNumber::from_hardened_idx(coin)?,
+            ChildNumber::from_hardened_idx(self.account)?,
+        ];
+        if let Some(script_type) = script_type {
+            path.push(ChildNumber::from_hardened_idx(script_type)?);
+        }
+        Ok(path.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::offline::{DerivationOptions, Scheme};
+    use bitcoin::Network;
+
+    #[test]
+    fn test_derivation_path() {
+        let mut opt = DerivationOptions::default();
+        assert_eq!(
+            opt.path(Network::Bitcoin).unwrap().to_string(),
+            "m/48'/0'/0'/2'"
+        );
+        opt.account = 3;
+        opt.scheme = Scheme::Bip48Nested;
+        assert_eq!(
+            opt.path(Network::Testnet).unwrap().to_string(),
+            "m/48'/1'/3'/1'"
+        );
+        opt.scheme = Scheme::Bip87;
+        assert_eq!(
+            opt.path(Network::Testnet).unwrap().to_string(),
+            "m/87'/1'/3'"
+        );
+        opt.account = 1 << 31;
+        assert!(opt.path(Network::Testnet).is_err());
+    }
+}
@@ -2,18 +2,18 @@ use crate::list::ListOptions;
 use crate::offline::decrypt::decrypt;
 use crate::offline::descriptor::{derive_address, DeriveAddressOptions};
 use crate::offline::sign::MessageToSign;
+use crate::offline::taproot::*;
 use crate::*;
 use bitcoin::consensus::serialize;
-use bitcoin::secp256k1::{Secp256k1, Signature};
+use bitcoin::secp256k1::{schnorrsig, All, Message, Secp256k1, Signature};
 use bitcoin::util::bip32::{ChildNumber, DerivationPath, Fingerprint};
-use bitcoin::util::key;
 use bitcoin::{Address, Amount, Network, OutPoint, Script, SignedAmount};
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
-type HdKeypaths = BTreeMap<key::PublicKey, (Fingerprint, DerivationPath)>;
+type KeySources<'a> = Vec<&'a (Fingerprint, DerivationPath)>;
 
 /// Print details regarding a Partially Signed Bitcoin Transaction (PSBT) given as parameter.
 /// A `psbt_file` or a `psbt_base` should be specified.
@@ -94,7 +94,7 @@ pub fn pretty_print(
     let all_previous_known = previous_outputs.iter().all(Option::is_some);
     let mut balances = HashMap::new();
     let mut message_to_sign = MessageToSign::new(psbt);
-    let secp = Secp256k1::verification_only();
+    let secp = Secp256k1::new();
 
     let mut signatures: HashSet<Fingerprint>;
     let mut value: Option<Amount>;
@@ -106,6 +106,8 @@ pub fn pretty_print(
                 let addr = Address::from_script(&previous_output.script_pubkey, network)
                     .ok_or(Error::NonDefaultScript)?;
                 let keypaths = &psbt.inputs[i].bip32_derivation;
+                let tap_origins =
+                    tap_key_origins(&psbt.inputs[i].unknown, PSBT_IN_TAP_BIP32_DERIVATION)?;
                 signatures = psbt.inputs[i]
                     .partial_sigs
                     .iter()
@@ -131,7 +133,38 @@ pub fn pretty_print(
                     .filter_map(|(k, _)| keypaths.get(k).map(|v| v.0))
                     .collect();
 
-                wallet_if_any = wallet_with_path(keypaths, wallets, &addr);
+                let mut verify_schnorr = |leaf_hash: Option<&_>, key, signature: &[u8]| {
+                    let valid = message_to_sign
+                        .hash_taproot(i, leaf_hash)
+                        .ok()
+                        .and_then(|(_, message)| verify_schnorr(&secp, &message, &key, signature));
+                    if valid.is_none() {
+                        let msg = "Signatures: A signature in the psbt is not valid";
+                        result.info.push(msg.to_string());
+                    }
+                    valid.is_some()
+                };
+                for ((key, leaf_hash), signature) in tap_script_sigs(&psbt.inputs[i])? {
+                    if verify_schnorr(Some(&leaf_hash), key, &signature) {
+                        if let Some((_, (fingerprint, _))) = tap_origins.get(&key) {
+                            signatures.insert(*fingerprint);
+                        }
+                    }
+                }
+                if let Some(signature) = tap_key_sig(&psbt.inputs[i]) {
+                    let output_key =
+                        schnorrsig::PublicKey::from_slice(&previous_output.script_pubkey[2..])?;
+                    if verify_schnorr(None, output_key, signature) {
+                        let key_path_origin = tap_origins.values().find(|(l, _)| l.is_empty());
+                        if let Some((_, (fingerprint, _))) = key_path_origin {
+                            signatures.insert(*fingerprint);
+                        }
+                    }
+                }
+
+                let mut key_sources: KeySources = keypaths.values().collect();
+                key_sources.extend(tap_origins.values().map(|(_, source)| source));
+                wallet_if_any = wallet_with_path(key_sources, wallets, &addr);
                 if let Some((wallet, _)) = &wallet_if_any {
                     *balances.entry(wallet.clone()).or_insert(0i64) -= previous_output.value as i64
                 }
@@ -161,12 +194,20 @@ pub fn pretty_print(
         let addr =
             Address::from_script(&output.script_pubkey, network).ok_or(Error::NonDefaultScript)?;
         let keypaths = &psbt.outputs[i].bip32_derivation;
-        let wallet_if_any = wallet_with_path(keypaths, wallets, &addr);
+        let tap_origins = tap_key_origins(&psbt.outputs[i].unknown, PSBT_OUT_TAP_BIP32_DERIVATION)?;
+        let mut key_sources: KeySources = keypaths.values().collect();
+        key_sources.extend(tap_origins.values().map(|(_, source)| source));
+        let wallet_if_any = wallet_with_path(key_sources, wallets, &addr);
         if let Some((wallet, _)) = &wallet_if_any {
             *balances.entry(wallet.clone()).or_insert(0i64) += output.value as i64
         }
+        let change = wallet_if_any
+            .as_ref()
+            .map(|(_, path)| is_change(path))
+            .unwrap_or(false);
         let txout = entities::TxOut {
             address: addr.to_string(),
+            change,
             common: TxCommonInOut {
                 value: Amount::from_sat(output.value).to_string(),
                 wallet_with_path: wallet_if_any.map(|(w, p)| format!("[{}]{}", w, p)),
@@ -241,9 +282,16 @@ pub fn pretty_print(
     };
 
     let tx_vbytes = tx.get_weight() / 4;
-    let estimated_tx_vbytes = estimate_weight(psbt).ok().map(|e| e / 4);
+    let estimated_tx_vbytes = estimate_weight(psbt).ok().map(|e| (e + 3) / 4);
     let estimated_fee_rate = fee.and_then(|fee| estimated_tx_vbytes.map(|e| fee as f64 / e as f64));
 
+    result.replaces = get_psbt_replaces(psbt);
+    if let Some(txid) = result.replaces {
+        result
+            .info
+            .push(format!("Replaces the previous transaction {}", txid));
+    }
+
     result.size = Size {
         estimated: estimated_tx_vbytes,
         unsigned: tx_vbytes,
@@ -283,25 +331,46 @@ fn script_type(script: &Script) -> Option<usize> {
     SCRIPT_TYPE_FN.iter().position(|f| f(script))
 }
 
+/// verify a BIP340 signature, optionally followed by the sighash byte
+fn verify_schnorr(
+    secp: &Secp256k1<All>,
+    message: &Message,
+    key: &schnorrsig::PublicKey,
+    signature: &[u8],
+) -> Option<()> {
+    let signature = schnorrsig::Signature::from_slice(signature.get(..64)?).ok()?;
+    secp.schnorrsig_verify(&signature, message, key).ok()
+}
+
+/// true if the derivation path is on the change chain `/1/*`
+fn is_change(path: &DerivationPath) -> bool {
+    let path: &[ChildNumber] = path.as_ref();
+    path.len() >= 2 && path[path.len() - 2] == ChildNumber::Normal { index: 1 }
+}
+
 /// returns a wallet name and a derivation iif the address parameter is the same as the one derived from the wallet
 fn wallet_with_path(
-    hd_keypaths: &HdKeypaths,
+    key_sources: KeySources,
     wallets: &[Wallet],
     address: &Address,
 ) -> Option<(String, DerivationPath)> {
     for wallet in wallets {
-        for (_, (finger, path)) in hd_keypaths.iter() {
+        for (finger, path) in key_sources.iter() {
             if wallet.fingerprints().contains(finger) {
                 let path_vec: Vec<ChildNumber> = path.clone().into();
                 let len = path_vec.len();
                 if let ChildNumber::Normal { index } = path_vec.get(len - 2)? {
                     let descriptor = match index {
-                        0 => &wallet.descriptor,
+                        0 => wallet.descriptor.clone(),
+                        1 => match wallet.change_descriptor() {
+                            Ok(descriptor) => descriptor,
+                            Err(_) => continue,
+                        },
                         _ => return None,
                     };
                     if let ChildNumber::Normal { index } = path_vec.last()? {
                         let opts = DeriveAddressOptions {
-                            descriptor: descriptor.to_string(),
+                            descriptor,
                             index: *index,
                         };
                         if let Ok(derived) = derive_address(address.network, &opts) {
@@ -319,7 +388,7 @@ fn wallet_with_path(
 
 #[cfg(test)]
 mod tests {
-    use crate::offline::print::{biggest_dividing_pow, pretty_print, script_type};
+    use crate::offline::print::{biggest_dividing_pow, is_change, pretty_print, script_type};
     use crate::{psbt_from_base64, Psbt, Wallet};
     use bitcoin::Network;
 
@@ -354,6 +423,13 @@ mod tests {
         assert_eq!(script_type(&s), Some(4usize));
     }
 
+    #[test]
+    fn test_is_change() {
+        assert!(is_change(&"m/48'/1'/0'/2'/1/5".parse().unwrap()));
+        assert!(!is_change(&"m/48'/1'/0'/2'/0/5".parse().unwrap()));
+        assert!(!is_change(&"m/1".parse().unwrap()));
+    }
+
     #[test]
     fn test_pretty_print() {
         let (_, to_carol_psbt) = psbt_from_base64("cHNidP8BAH4CAAAAAQQYGYyRDjWA/D08BEjU3Q9P34Sv8q0mW9UV5niEqBZ4AQAAAAD+////AiDLAAAAAAAAF6kUaV+OwCj7iV87pOHOFXNLuZMc7tyHBwIAAAAAAAAiACAGYNwSo/z0dYfDuCUPL2Li/SSY10gjxu8hZ9pREpEaCwAAAAAM/AVmaXJtYQBuYW1lCHRvLWNhcm9sAAEAoQIAAAABG7mL63lJDPOLQybsXY8WZhK8QMjvz5D/qM6KBtZAYmQAAAAAIyIAIPynXT2ph1cCtzZ2E+fD0d6vmuZPc8BQvMyVxOjcK+c1/f///wJMiwYAAAAAABepFGdxKLPj9gk9IONcwMW/kz2S7YYIh6TOAAAAAAAAIgAg9ZFXIhxr0C/u7qGjb+y5bdnmVPnY3tH583t2S8HyPqp+hR0AAQErpM4AAAAAAAAiACD1kVciHGvQL+7uoaNv7Llt2eZU+dje0fnze3ZLwfI+qgEFR1IhApKznFtt8+fKlGOcjgKzwmEgy8O2et7atlNfdA5bb80uIQN9dFnXvgcdA4fmLWblwKJbuzazugS3dzc6PrlDq2fd4FKuIgYCkrOcW23z58qUY5yOArPCYSDLw7Z63tq2U190DltvzS4couvgTjAAAIABAACAAAAAgAIAAIAAAAAAAAAAACIGA310Wde+Bx0Dh+YtZuXAolu7NrO6BLd3Nzo+uUOrZ93gHB9eQ9gwAACAAQAAgAAAAIACAACAAAAAAAAAAAAAAAEBR1IhAuOCnowHNpvquGET8SUCHqm7lSymqSslu2U4B2VdZ9hAIQOo4hJeqVo5DnlJPz/2YUn3odyLWIHI1GBOEbzdokJRf1KuIgIC44KejAc2m+q4YRPxJQIeqbuVLKapKyW7ZTgHZV1n2EAcouvgTjAAAIABAACAAAAAgAIAAIAAAAAAAQAAACICA6jiEl6pWjkOeUk/P/ZhSfeh3ItYgcjUYE4RvN2iQlF/HB9eQ9gwAACAAQAAgAAAAIACAACAAAAAAAEAAAAA").unwrap();
@@ -367,8 +443,17 @@ mod tests {
         assert_eq!("Privacy: outputs have different precision https://en.bitcoin.it/wiki/Privacy#Round_numbers", result.info[1]);
 
         assert_eq!(result.fee.absolute, Some(381));
+        assert!(result.outputs[1].common.wallet_with_path.is_some());
+        assert!(!result.outputs.iter().any(|o| o.change));
 
         dbg!(result);
+
+        // same keys in a sortedmulti wallet, input witness script has keys already sorted
+        let wallet = Wallet::new("wsh(sortedmulti(2,[1f5e43d8/48'/1'/0'/2']tpubDFU4parcXvV8tBYt4rS4a8rGNF1DA32DCnRfhzVL6b3MSiDomV95rv9mb7W7jAPMTohyEYpbhVS8FbmTsuQsFRxDWPJX2ZFEeRPMFz3R1gh/0/*,[a2ebe04e/48'/1'/0'/2']tpubDEXDRpvW2srXCSjAvC36zYkSE3jxT1wf7JXDo35Ln4NZpmaMNhq8o9coH9U9BQ5bAN4WDGxXV9d426iYKGorFF5wvv4Wv63cZsCotiXGGkD/0/*))", Network::Testnet);
+        let name = wallet.id.name.clone();
+        let result = pretty_print(&to_carol_psbt, Network::Testnet, &[wallet]).unwrap();
+        assert_eq!(format!("{}: -0.00052381 BTC", name), result.balances);
+        assert!(result.inputs[0].common.wallet_with_path.is_some());
     }
 
     #[test]
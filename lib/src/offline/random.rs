@@ -1,4 +1,6 @@
-use crate::{mnemonic, MasterSecret, OfflineContext, Result};
+use crate::mnemonic::{Language, Mnemonic};
+use crate::offline::DerivationOptions;
+use crate::{MasterSecret, OfflineContext, Result};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use structopt::StructOpt;
@@ -10,13 +12,29 @@ pub struct RandomOptions {
     /// Name of the key
     #[structopt(short, long)]
     pub key_name: String,
+
+    /// Language of the generated mnemonic
+    #[structopt(long, default_value = "english")]
+    #[serde(default)]
+    pub language: Language,
+
+    #[structopt(flatten)]
+    #[serde(flatten)]
+    pub derivation: DerivationOptions,
+
+    /// BIP39 passphrase, never persisted, in the CLI it's read from stdin with `--passphrase`
+    #[structopt(skip)]
+    #[serde(default, skip_serializing)]
+    pub passphrase: Option<String>,
 }
 
 impl OfflineContext {
     pub fn create_key(&self, opt: &RandomOptions) -> Result<MasterSecret> {
         let sec = rand::thread_rng().gen::<[u8; 32]>();
-        let mnemonic = mnemonic::Mnemonic::new(&sec)?;
-        let master_key = MasterSecret::new(self.network, mnemonic, &opt.key_name)?;
+        let mnemonic = Mnemonic::new_in(&sec, opt.language)?;
+        let master_key = MasterSecret::new(self.network, mnemonic, &opt.key_name)?
+            .with_passphrase(opt.passphrase.as_deref())?
+            .with_derivation(&opt.derivation)?;
         self.write_keys(&master_key)?;
 
         Ok(master_key)
@@ -27,14 +45,22 @@ impl OfflineContext {
 mod tests {
     use crate::common::context::tests::TestContext;
     use crate::common::tests::rnd_string;
+    use crate::mnemonic::{Language, Mnemonic};
     use crate::offline::random::RandomOptions;
+    use crate::offline::{DerivationOptions, Scheme};
+    use crate::MasterSecret;
     use miniscript::descriptor::DescriptorSecretKey;
     use std::str::FromStr;
 
     impl RandomOptions {
         pub fn new_random() -> Self {
             let key_name = rnd_string();
-            RandomOptions { key_name }
+            RandomOptions {
+                key_name,
+                language: Default::default(),
+                derivation: Default::default(),
+                passphrase: None,
+            }
         }
     }
 
@@ -52,6 +78,39 @@ mod tests {
         assert_ne!(key_1, key_2);
     }
 
+    #[test]
+    fn test_random_derivation() {
+        let context = TestContext::default();
+        let mut rand_opts = RandomOptions::new_random();
+        rand_opts.derivation = DerivationOptions {
+            scheme: Scheme::Bip87,
+            account: 1,
+        };
+        let key = context.create_key(&rand_opts).unwrap();
+        let public = key.as_desc_pub_key().unwrap();
+        assert_eq!(public.origin_path().unwrap().to_string(), "m/87'/1'/1'");
+
+        let read: MasterSecret = context.read(&rand_opts.key_name).unwrap();
+        assert_eq!(read.derivation_path, key.derivation_path);
+        assert_eq!(read.as_desc_pub_key().unwrap(), public);
+    }
+
+    #[test]
+    fn test_random_language() {
+        let context = TestContext::default();
+        let mut rand_opts = RandomOptions::new_random();
+        rand_opts.language = Language::Japanese;
+        let key = context.create_key(&rand_opts).unwrap();
+        let mnemonic = key.mnemonic.as_ref().unwrap();
+        let words = Language::Japanese.words();
+        assert!(mnemonic.to_string().split(' ').all(|w| words.contains(&w)));
+
+        let read: MasterSecret = context.read(&rand_opts.key_name).unwrap();
+        assert_eq!(read.mnemonic.as_ref(), Some(mnemonic));
+        let parsed = Mnemonic::from_str(&mnemonic.to_string()).unwrap();
+        assert_eq!(parsed.to_seed(None), mnemonic.to_seed(None));
+    }
+
     #[test]
     fn test_descriptor_key() {
         let xpub = "[a15f432e/48'/1'/0']tpubDDoLq7YG6qr18Paph1uJ8F2ncVuSh2DjkixS6CX37nCiJusecE82JXvFmfZh8hp86Bm7sv7Pkprv5phMXn1r49TU6YrDidGmemFAL1PNWXi/0/*";
@@ -1,5 +1,8 @@
+use crate::codex32;
 use crate::mnemonic::Mnemonic;
-use crate::Result;
+use crate::offline::DerivationOptions;
+use crate::slip39::{self, Share};
+use crate::{Error, Result};
 use crate::{MasterSecret, OfflineContext};
 use bitcoin::util::bip32::ExtendedPrivKey;
 use serde::{Deserialize, Serialize};
@@ -19,14 +22,32 @@ pub struct RestoreOptions {
     #[structopt(short, long)]
     pub nature: Nature,
 
-    /// value of the secret component, could be xprv or seed in hex or bech32
+    /// value of the secret component, could be xprv or seed in hex or bech32, SLIP-39 and codex32
+    /// shares are separated by comma
     pub value: String,
+
+    #[structopt(flatten)]
+    #[serde(flatten)]
+    pub derivation: DerivationOptions,
+
+    /// BIP39 passphrase, never persisted, in the CLI it's read from stdin with `--passphrase`
+    #[structopt(skip)]
+    #[serde(default, skip_serializing)]
+    pub passphrase: Option<String>,
+
+    /// SLIP-39 passphrase decrypting the shares, never persisted, in the CLI it's read from stdin
+    /// with `--slip39-passphrase`. Shares created by `split` don't have one.
+    #[structopt(skip)]
+    #[serde(default, skip_serializing)]
+    pub slip39_passphrase: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub enum Nature {
     Xprv,
     Mnemonic,
+    Slip39,
+    Codex32,
 }
 
 impl FromStr for Nature {
@@ -36,9 +57,11 @@ impl FromStr for Nature {
         match s {
             "xprv" => Ok(Nature::Xprv),
             "mnemonic" => Ok(Nature::Mnemonic),
+            "slip39" => Ok(Nature::Slip39),
+            "codex32" => Ok(Nature::Codex32),
             _ => Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
-                format!("({}) valid values are: xprv, mnemonic", s),
+                format!("({}) valid values are: xprv, mnemonic, slip39, codex32", s),
             )),
         }
     }
@@ -46,16 +69,56 @@ impl FromStr for Nature {
 
 impl OfflineContext {
     pub fn restore(&self, opt: &RestoreOptions) -> Result<MasterSecret> {
+        match opt.nature {
+            Nature::Slip39 if opt.passphrase.is_some() => {
+                // SLIP-39 can't detect a wrong passphrase, using the BIP39 one given to `split`
+                // would silently restore a different key
+                return Err(Error::Generic(
+                    "SLIP-39 shares contain the seed already derived with the BIP39 passphrase, \
+                     use --slip39-passphrase for shares encrypted with a SLIP-39 passphrase"
+                        .into(),
+                ));
+            }
+            Nature::Slip39 => (),
+            _ if opt.slip39_passphrase.is_some() => {
+                return Err("The SLIP-39 passphrase applies only to slip39 shares".into());
+            }
+            _ => (),
+        }
         let master_key = match opt.nature {
             Nature::Xprv => {
                 let key = ExtendedPrivKey::from_str(&opt.value)?;
                 MasterSecret::from_xprv(self.network, key, &opt.key_name)?
+                    .with_passphrase(opt.passphrase.as_deref())?
             }
             Nature::Mnemonic => {
                 let mnemonic = Mnemonic::from_str(&opt.value)?;
                 MasterSecret::new(self.network, mnemonic, &opt.key_name)?
+                    .with_passphrase(opt.passphrase.as_deref())?
+            }
+            Nature::Slip39 => {
+                let shares = opt
+                    .value
+                    .split(',')
+                    .map(Share::from_str)
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                let passphrase = opt.slip39_passphrase.as_deref().unwrap_or("");
+                let seed = slip39::combine(&shares, passphrase.as_bytes())?;
+                let key = ExtendedPrivKey::new_master(self.network, &seed)?;
+                MasterSecret::from_xprv(self.network, key, &opt.key_name)?
+            }
+            Nature::Codex32 => {
+                let shares = opt
+                    .value
+                    .split(',')
+                    .map(codex32::Share::from_str)
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                let secret = codex32::combine(&shares)?;
+                MasterSecret::from_codex32(self.network, &secret, &opt.key_name)?
+                    .with_passphrase(opt.passphrase.as_deref())?
             }
         };
+        let master_key = master_key.with_derivation(&opt.derivation)?;
         self.write_keys(&master_key)?;
 
         Ok(master_key)
@@ -65,10 +128,12 @@ impl OfflineContext {
 #[cfg(test)]
 mod tests {
     use crate::common::context::tests::TestContext;
+    use crate::import_export::ExportOptions;
     use crate::list::ListOptions;
     use crate::offline::random::RandomOptions;
     use crate::offline::restore::{Nature, RestoreOptions};
-    use crate::Kind;
+    use crate::{Error, Kind, MasterSecret};
+    use bitcoin::util::bip32::ExtendedPrivKey;
     use bitcoin::Network;
 
     #[test]
@@ -82,6 +147,9 @@ mod tests {
             key_name: "restored".to_string(),
             nature: Nature::Xprv,
             value: key_orig.key.to_string(),
+            derivation: Default::default(),
+            passphrase: None,
+            slip39_passphrase: None,
         };
         let key_restored = context.restore(&restore_opts).unwrap();
         assert_eq!(key_orig.key, key_restored.key);
@@ -98,6 +166,7 @@ mod tests {
             key_name,
             nature: Nature::Mnemonic,
             value: key_orig.mnemonic.as_ref().unwrap().to_string(),
+            derivation: Default::default(),
         };
         let key_restored = context.restore(&restore_opts).unwrap();
         assert_eq!(key_orig.xprv(), key_restored.xprv());
@@ -107,6 +176,9 @@ mod tests {
             key_name: "err".to_string(),
             nature: Nature::Xprv,
             value: "X".to_string(),
+            derivation: Default::default(),
+            passphrase: None,
+            slip39_passphrase: None,
         };
         let result = context.restore(&restore_opts);
         assert!(result.is_err());
@@ -120,6 +192,9 @@ mod tests {
                 .xpub()
                 .unwrap()
                 .to_string(),
+            derivation: Default::default(),
+            passphrase: None,
+            slip39_passphrase: None,
         };
         let result = context.restore(&restore_opts);
         assert!(result.is_err());
@@ -130,9 +205,104 @@ mod tests {
             key_name: "bar".to_string(),
             nature: Nature::Xprv,
             value: key_orig.key.to_string(),
+            derivation: Default::default(),
+            passphrase: None,
+            slip39_passphrase: None,
         };
         let _ = regtest_context.restore(&restore_opts).unwrap();
         let list = regtest_context.list(&list_options).unwrap();
         assert!(list.master_secrets.iter().any(|a| a.id.name == key_name));
     }
+
+    #[test]
+    fn test_restore_passphrase() {
+        let context = TestContext::default();
+        let mut rand_opts = RandomOptions::new_random();
+        rand_opts.passphrase = Some("passphrase".to_string());
+        let key_orig = context.create_key(&rand_opts).unwrap();
+        assert!(key_orig.passphrase);
+
+        let stored: MasterSecret = context.read(&rand_opts.key_name).unwrap();
+        assert!(stored.passphrase);
+        assert_eq!(stored.fingerprint, key_orig.fingerprint);
+        assert_ne!(stored.key, key_orig.key, "passphrase key persisted");
+        assert_matches!(stored.unlock(None), Err(Error::PassphraseRequired));
+        assert_matches!(stored.unlock(Some("wrong")), Err(Error::WrongPassphrase));
+        assert_eq!(stored.unlock(Some("passphrase")).unwrap().key, key_orig.key);
+
+        let mut restore_opts = RestoreOptions {
+            key_name: "restored".to_string(),
+            nature: Nature::Mnemonic,
+            value: key_orig.mnemonic.as_ref().unwrap().to_string(),
+            derivation: Default::default(),
+            passphrase: Some("passphrase".to_string()),
+            slip39_passphrase: None,
+        };
+        let key_restored = context.restore(&restore_opts).unwrap();
+        assert_eq!(key_orig.key, key_restored.key);
+
+        restore_opts.key_name = "xprv".to_string();
+        restore_opts.nature = Nature::Xprv;
+        restore_opts.value = key_orig.key.to_string();
+        let result = context.restore(&restore_opts);
+        assert_matches!(result, Err(Error::PassphraseWithoutMnemonic));
+    }
+
+    #[test]
+    fn test_restore_codex32() {
+        let context = TestContext::default();
+        let rand_opts = RandomOptions::new_random();
+        let key_orig = context.create_key(&rand_opts).unwrap();
+        let export_opts = ExportOptions {
+            kind: Kind::MasterSecret,
+            name: rand_opts.key_name.clone(),
+            psbt_v2: false,
+        };
+        let exported = context.export(&export_opts).unwrap();
+        let codex32 = exported["codex32"].as_str().unwrap().to_string();
+        assert!(codex32.starts_with("ms10"));
+
+        let restore_opts = RestoreOptions {
+            key_name: "restored".to_string(),
+            nature: Nature::Codex32,
+            value: codex32.clone(),
+            derivation: Default::default(),
+            passphrase: None,
+            slip39_passphrase: None,
+        };
+        let key_restored = context.restore(&restore_opts).unwrap();
+        assert_eq!(key_orig.key, key_restored.key);
+        assert!(key_restored.mnemonic.is_none());
+        let export_opts = ExportOptions {
+            kind: Kind::MasterSecret,
+            name: "restored".to_string(),
+            psbt_v2: false,
+        };
+        let exported = context.export(&export_opts).unwrap();
+        assert_eq!(exported["codex32"], codex32);
+
+        let restore_opts = RestoreOptions {
+            key_name: "shares".to_string(),
+            nature: Nature::Codex32,
+            value: "MS12NAMEA320ZYXWVUTSRQPNMLKJHGFEDCAXRPP870HKKQRM,MS12NAMECACDEFGHJKLMNPQRSTUVWXYZ023FTR2GDZMPY6PN".to_string(),
+            derivation: Default::default(),
+            passphrase: None,
+            slip39_passphrase: None,
+        };
+        let key_restored = context.restore(&restore_opts).unwrap();
+        let seed = hex::decode("d1808e096b35b209ca12132b264662a5").unwrap();
+        let expected = ExtendedPrivKey::new_master(Network::Testnet, &seed).unwrap();
+        assert_eq!(key_restored.key, expected);
+
+        let mut rand_opts = RandomOptions::new_random();
+        rand_opts.passphrase = Some("passphrase".to_string());
+        context.create_key(&rand_opts).unwrap();
+        let export_opts = ExportOptions {
+            kind: Kind::MasterSecret,
+            name: rand_opts.key_name.clone(),
+            psbt_v2: false,
+        };
+        let exported = context.export(&export_opts).unwrap();
+        assert!(exported.get("codex32").is_none());
+    }
 }
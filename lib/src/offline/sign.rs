@@ -1,13 +1,15 @@
 //use crate::offline::decrypt::{decrypt, DecryptOptions, MaybeEncrypted};
 use crate::offline::print::pretty_print;
+use crate::offline::taproot::*;
 use crate::*;
 use bitcoin::blockdata::opcodes;
 use bitcoin::blockdata::script::Builder;
-use bitcoin::hashes::Hash;
-use bitcoin::secp256k1::{self, Message, Secp256k1, SignOnly};
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::secp256k1::{self, schnorrsig, All, Message, Secp256k1};
 use bitcoin::util::bip143::SigHashCache;
 use bitcoin::util::bip32::{DerivationPath, ExtendedPrivKey, ExtendedPubKey};
-use bitcoin::{Network, Script, SigHashType, Transaction};
+use bitcoin::util::taproot::{TapLeafHash, TapTweakHash};
+use bitcoin::{Network, Script, SigHashType, Transaction, TxOut};
 use log::{debug, info};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
@@ -33,9 +35,14 @@ pub struct SignOptions {
     #[structopt(short, long, default_value = "1000")]
     pub total_derivations: u32,
 
-    /// Allow any derivations (to avoid ramson attacks, by default only 2 levels are allowed, and the first level must be 0 or 1)
+    /// Allow any derivations (to avoid ramson attacks, by default only 2 levels after one of the key accounts are allowed, and the first level must be 0 or 1)
     #[structopt(long)]
     pub allow_any_derivations: bool,
+
+    /// BIP39 passphrase, never persisted, in the CLI it's read from stdin with `--passphrase`
+    #[structopt(skip)]
+    #[serde(default, skip_serializing)]
+    pub passphrase: Option<String>,
 }
 
 pub struct SignResult {
@@ -47,10 +54,11 @@ pub struct SignResult {
 struct PsbtSigner {
     pub psbt: BitcoinPsbt,
     xprv: ExtendedPrivKey,
-    secp: Secp256k1<SignOnly>,
+    secp: Secp256k1<All>,
     network: Network, // even if network is included in xprv, regtest is equal to testnet there, so we need this
     derivations: u32,
     allow_any_derivations: bool,
+    accounts: Vec<DerivationPath>,
 }
 
 /// extract field name in the PSBT extra field if present
@@ -93,8 +101,9 @@ impl PsbtSigner {
         network: Network,
         derivations: u32,
         allow_any_derivations: bool,
+        accounts: &[DerivationPath],
     ) -> Result<Self> {
-        let secp = Secp256k1::signing_only();
+        let secp = Secp256k1::new();
         check_compatibility(network, xprv.network)?;
 
         Ok(PsbtSigner {
@@ -104,6 +113,7 @@ impl PsbtSigner {
             derivations,
             network,
             allow_any_derivations,
+            accounts: accounts.to_vec(),
         })
     }
 
@@ -113,6 +123,13 @@ impl PsbtSigner {
 
         for (i, input) in self.psbt.inputs.clone().iter().enumerate() {
             debug!("sign input #{} {:?}", i, input);
+            if let Some(witness_utxo) = input.witness_utxo.as_ref() {
+                if is_v1_p2tr(&witness_utxo.script_pubkey) {
+                    // BIP341 signature commits to amounts, non_witness_utxo is not needed
+                    self.sign_taproot_input(i)?;
+                    continue;
+                }
+            }
             let is_segwit = input.witness_utxo.is_some();
             let non_witness_utxo = input
                 .non_witness_utxo
@@ -259,15 +276,7 @@ impl PsbtSigner {
             debug!("found key fingerprint {:?}", fing);
 
             if !self.allow_any_derivations {
-                //TODO recheck
-                let path_slice = child.as_ref();
-                if path_slice.len() != 6 {
-                    return Err(format!("{} only 6 derivation paths allowed", child).into());
-                } else if !(path_slice[4] == 0.into() || path_slice[4] == 1.into()) {
-                    return Err(
-                        format!("{} second-last derivation must be Soft 0 or 1", child).into(),
-                    );
-                }
+                check_derivation(child, &self.accounts)?;
             }
             let privkey = self.xprv.derive_priv(&self.secp, &child)?;
             let derived_pubkey =
@@ -285,15 +294,7 @@ impl PsbtSigner {
             signature.push(sighash.as_u32() as u8); // TODO how to properly do this?
             match input.partial_sigs.get(pubkey) {
                 Some(signature_already_there) => {
-                    if &signature == signature_already_there {
-                        return Err(
-                            "This transaction already contains a signature from this key matching the one generated by us (RFC6979 complaint)".into(),
-                        );
-                    } else {
-                        return Err(
-                            "This transaction already contains a signature from this key DIFFERENT from the one generated by us (wrong, or NOT RFC6979 complaint)".into(),
-                        );
-                    }
+                    return Err(already_signed(&signature, signature_already_there));
                 }
                 None => {
                     input.partial_sigs.insert(*pubkey, signature);
@@ -303,6 +304,110 @@ impl PsbtSigner {
         Ok(())
     }
 
+    fn sign_taproot_input(&mut self, input_index: usize) -> Result<()> {
+        debug!("sign_taproot_input #{}", input_index);
+        let psbt_clone = self.psbt.clone();
+        let mut message_to_sign = MessageToSign::new(&psbt_clone);
+        let input = &psbt_clone.inputs[input_index];
+        let my_fing = self.xprv.fingerprint(&self.secp);
+        let script_pubkey = &input
+            .witness_utxo
+            .as_ref()
+            .ok_or(Error::MissingWitnessUtxo)?
+            .script_pubkey;
+        let leaf_scripts: HashMap<TapLeafHash, (Vec<u8>, Script)> = tap_scripts(input)
+            .into_iter()
+            .filter(|(_, (_, leaf_version))| *leaf_version == TAPSCRIPT_LEAF_VERSION)
+            .map(|(control_block, (script, _))| (tap_leaf_hash(&script), (control_block, script)))
+            .collect();
+        let script_sigs = tap_script_sigs(input)?;
+
+        for (xonly, (key_leaf_hashes, (fing, child))) in
+            tap_key_origins(&input.unknown, PSBT_IN_TAP_BIP32_DERIVATION)?
+        {
+            if fing != my_fing {
+                continue;
+            }
+            debug!("found key fingerprint {:?}", fing);
+
+            if !self.allow_any_derivations {
+                check_derivation(&child, &self.accounts)?;
+            }
+            let privkey = self.xprv.derive_priv(&self.secp, &child)?;
+            let mut keypair =
+                schnorrsig::KeyPair::from_secret_key(&self.secp, privkey.private_key.key);
+            if schnorrsig::PublicKey::from_keypair(&self.secp, &keypair) != xonly {
+                return Err(
+                    "pubkey derived and expected differs even if fingerprint matches!".into(),
+                );
+            }
+
+            if key_leaf_hashes.is_empty() {
+                // key path spend, our key must be the internal key
+                if tap_internal_key(input)? != Some(xonly) {
+                    continue;
+                }
+                let mut engine = TapTweakHash::engine();
+                engine.input(&xonly.serialize());
+                if let Some(merkle_root) = tap_merkle_root(input) {
+                    engine.input(merkle_root);
+                }
+                let tweak = TapTweakHash::from_engine(engine);
+                keypair.tweak_add_assign(&self.secp, &tweak)?;
+                let output_key = schnorrsig::PublicKey::from_keypair(&self.secp, &keypair);
+                if output_key.serialize() != script_pubkey.as_bytes()[2..] {
+                    return Err("tweaked internal key doesn't match the script_pubkey".into());
+                }
+                let (sighash, msg) = message_to_sign.hash_taproot(input_index, None)?;
+                let signature = self.schnorr_sign(&msg, &keypair, sighash);
+                match tap_key_sig(input) {
+                    Some(already) => return Err(already_signed(&signature, already)),
+                    None => insert_tap_key_sig(&mut self.psbt.inputs[input_index], signature),
+                }
+            } else {
+                for leaf_hash in key_leaf_hashes.iter() {
+                    let (control_block, leaf_script) = leaf_scripts
+                        .get(leaf_hash)
+                        .ok_or_else(|| format!("missing tap leaf script for {}", leaf_hash))?;
+                    if !control_block_commits(
+                        &self.secp,
+                        control_block,
+                        leaf_script,
+                        script_pubkey,
+                    )? {
+                        return Err("tap leaf script doesn't match the script_pubkey".into());
+                    }
+                    if !script_has_key(leaf_script, &xonly) {
+                        return Err("key is not in the tap leaf script".into());
+                    }
+                    let (sighash, msg) =
+                        message_to_sign.hash_taproot(input_index, Some(leaf_hash))?;
+                    let signature = self.schnorr_sign(&msg, &keypair, sighash);
+                    match script_sigs.get(&(xonly, *leaf_hash)) {
+                        Some(already) => return Err(already_signed(&signature, already)),
+                        None => insert_tap_script_sig(
+                            &mut self.psbt.inputs[input_index],
+                            &xonly,
+                            leaf_hash,
+                            signature,
+                        ),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// deterministic (no aux rand) so that already present signatures can be compared
+    fn schnorr_sign(&self, msg: &Message, keypair: &schnorrsig::KeyPair, sighash: u8) -> Vec<u8> {
+        let signature = self.secp.schnorrsig_sign_no_aux_rand(msg, keypair);
+        let mut signature = signature[..].to_vec();
+        if sighash != SIGHASH_DEFAULT {
+            signature.push(sighash);
+        }
+        signature
+    }
+
     fn pretty_print(&self, wallets: &[Wallet]) -> Result<PsbtPrettyPrint> {
         pretty_print(&self.psbt, self.network, wallets)
     }
@@ -340,6 +445,61 @@ impl<'a> MessageToSign<'a> {
         let msg = Message::from_slice(&sig_hash.into_inner()[..])?;
         Ok((sig_hash_type, msg))
     }
+
+    /// BIP341 message, `leaf_hash` is given for script path spends
+    pub fn hash_taproot(
+        &mut self,
+        input_index: usize,
+        leaf_hash: Option<&TapLeafHash>,
+    ) -> Result<(u8, Message)> {
+        let tx = &self.psbt.global.unsigned_tx;
+        let mut prevouts: Vec<TxOut> = vec![];
+        for (input, txin) in self.psbt.inputs.iter().zip(tx.input.iter()) {
+            let prevout = match (&input.witness_utxo, &input.non_witness_utxo) {
+                (Some(witness_utxo), _) => witness_utxo.clone(),
+                (None, Some(prev_tx)) => prev_tx
+                    .output
+                    .get(txin.previous_output.vout as usize)
+                    .ok_or(Error::MissingTxout)?
+                    .clone(),
+                (None, None) => return Err(Error::MissingWitnessUtxo),
+            };
+            prevouts.push(prevout);
+        }
+        let input = &self.psbt.inputs[input_index];
+        let sig_hash_type = input
+            .sighash_type
+            .map(|s| s.as_u32() as u8)
+            .unwrap_or(SIGHASH_DEFAULT);
+        let sig_hash =
+            taproot_signature_hash(tx, input_index, &prevouts, sig_hash_type, leaf_hash)?;
+        let msg = Message::from_slice(&sig_hash.into_inner()[..])?;
+        Ok((sig_hash_type, msg))
+    }
+}
+
+/// `child` must be `<account>/<0 or 1>/<index>` where `<account>` is the origin path of one of
+/// the accounts of the key
+fn check_derivation(child: &DerivationPath, accounts: &[DerivationPath]) -> Result<()> {
+    let path_slice = child.as_ref();
+    let account = accounts
+        .iter()
+        .find(|a| path_slice.len() == a.as_ref().len() + 2 && path_slice.starts_with(a.as_ref()))
+        .ok_or_else(|| Error::Generic(format!("{} is not 2 levels under a key account", child)))?;
+    let chain = path_slice[account.as_ref().len()];
+    if !(chain == 0.into() || chain == 1.into()) {
+        Err(format!("{} second-last derivation must be Soft 0 or 1", child).into())
+    } else {
+        Ok(())
+    }
+}
+
+fn already_signed(signature: &[u8], signature_already_there: &[u8]) -> Error {
+    if signature == signature_already_there {
+        "This transaction already contains a signature from this key matching the one generated by us (RFC6979 complaint)".into()
+    } else {
+        "This transaction already contains a signature from this key DIFFERENT from the one generated by us (wrong, or NOT RFC6979 complaint)".into()
+    }
 }
 
 impl OfflineContext {
@@ -347,8 +507,13 @@ impl OfflineContext {
         debug!("sign::start");
         let secret: MasterSecret = self.read(&opt.key_name)?;
         debug!("read secret key {}", secret.id.name);
-        let public: DescriptorPublicKey = self.read(&opt.key_name)?;
-        debug!("read public key {}", public.id.name);
+        let secret = secret.unlock(opt.passphrase.as_deref())?;
+        let accounts = self
+            .read_all_desc_pub_keys(&opt.key_name)?
+            .iter()
+            .map(|public| public.origin_path())
+            .collect::<Result<Vec<_>>>()?;
+        debug!("read accounts {:?}", accounts);
         let wallet: Wallet = self.read(&opt.wallet_name)?;
         debug!("read wallet {}", wallet.id.name);
         let mut psbt: Psbt = self.read(&opt.psbt_name)?;
@@ -360,6 +525,7 @@ impl OfflineContext {
             self.network,
             opt.total_derivations,
             opt.allow_any_derivations,
+            &accounts,
         )?;
 
         debug!("{:?}", psbt_signer);
@@ -395,7 +561,7 @@ pub fn to_p2pkh(pubkey_hash: &[u8]) -> Script {
 #[cfg(test)]
 mod tests {
     use crate::offline::sign::*;
-    use crate::{psbt_from_base64, psbt_to_base64, BitcoinPsbt, Error, Psbt};
+    use crate::{psbt_from_base64, psbt_to_base64, BitcoinPsbt, Error, Psbt, Wallet};
     use bitcoin::consensus::deserialize;
     use bitcoin::Transaction;
     use flate2::write::ZlibEncoder;
@@ -407,7 +573,7 @@ mod tests {
         psbt_signed: &BitcoinPsbt,
         xprv: &ExtendedPrivKey,
     ) -> Result<()> {
-        let mut psbt_signer = PsbtSigner::new(psbt_to_sign, *xprv, xprv.network, 10, true)?;
+        let mut psbt_signer = PsbtSigner::new(psbt_to_sign, *xprv, xprv.network, 10, true, &[])?;
         psbt_signer.sign()?;
 
         assert_eq!(
@@ -577,4 +743,168 @@ mod tests {
             base64::encode(psbt_complete_bytes)
         );
     }
+
+    fn taproot_psbt(script_pubkey: Script) -> BitcoinPsbt {
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![bitcoin::TxIn {
+                previous_output: bitcoin::OutPoint::default(),
+                script_sig: Script::new(),
+                sequence: 0xfffffffd,
+                witness: vec![],
+            }],
+            output: vec![TxOut {
+                value: 99_000,
+                script_pubkey: script_pubkey.clone(),
+            }],
+        };
+        let mut psbt = BitcoinPsbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 100_000,
+            script_pubkey,
+        });
+        psbt
+    }
+
+    #[test]
+    fn test_sign_taproot_script_path() {
+        let secp = Secp256k1::new();
+        let bytes = include_bytes!("../../test_data/sign/psbt_testnet.1.key");
+        let key1: crate::MasterSecret = serde_json::from_slice(bytes).unwrap();
+        let bytes = include_bytes!("../../test_data/sign/psbt_testnet.2.key");
+        let key2: crate::MasterSecret = serde_json::from_slice(bytes).unwrap();
+        let keys = [&key1, &key2];
+        let accounts = [DerivationPath::from_str("m/48'/1'/0'/2'").unwrap()];
+
+        let desc_pub_keys: Vec<String> = keys
+            .iter()
+            .map(|k| k.as_desc_pub_key().unwrap().desc_pub_key)
+            .collect();
+        let descriptor = format!("tr({},multi_a(2,{}))", NUMS, desc_pub_keys.join(","));
+        let derived = descriptor
+            .parse::<TrMulti>()
+            .unwrap()
+            .derive(&secp, 5)
+            .unwrap();
+        let origins: Vec<_> = keys
+            .iter()
+            .map(|k| {
+                let path = DerivationPath::from_str("m/48'/1'/0'/2'/0/5").unwrap();
+                (k.key.fingerprint(&secp), path)
+            })
+            .collect();
+        let mut psbt = taproot_psbt(derived.script_pubkey());
+        derived.fill_input(&mut psbt.inputs[0], &origins);
+
+        let mut psbt_signer =
+            PsbtSigner::new(&psbt, key1.key, Network::Testnet, 10, false, &accounts).unwrap();
+        assert!(psbt_signer.sign().unwrap().signed);
+        let signed = psbt_signer.psbt.clone();
+        let sigs = tap_script_sigs(&signed.inputs[0]).unwrap();
+        assert_eq!(sigs.len(), 1);
+        let ((key, leaf_hash), signature) = sigs.iter().next().unwrap();
+        assert_eq!(key, &derived.keys[0]);
+        assert_eq!(leaf_hash, &derived.leaf_hash);
+        assert_eq!(signature.len(), 64); // SIGHASH_DEFAULT is not appended
+        let (_, msg) = MessageToSign::new(&signed)
+            .hash_taproot(0, Some(leaf_hash))
+            .unwrap();
+        let signature = schnorrsig::Signature::from_slice(signature).unwrap();
+        secp.schnorrsig_verify(&signature, &msg, key).unwrap();
+
+        // signing twice gives the same signature
+        let mut psbt_signer =
+            PsbtSigner::new(&signed, key1.key, Network::Testnet, 10, false, &accounts).unwrap();
+        let err = psbt_signer.sign().err().unwrap();
+        assert!(err.to_string().contains("matching"));
+
+        let mut psbt_signer =
+            PsbtSigner::new(&signed, key2.key, Network::Testnet, 10, false, &accounts).unwrap();
+        psbt_signer.sign().unwrap();
+        assert_eq!(
+            tap_script_sigs(&psbt_signer.psbt.inputs[0]).unwrap().len(),
+            2
+        );
+
+        let wallet = Wallet::new(&descriptor, Network::Testnet);
+        let print = psbt_signer.pretty_print(&[wallet]).unwrap();
+        assert_eq!(print.inputs[0].signatures.len(), 2);
+        assert!(print.inputs[0].common.wallet_with_path.is_some());
+
+        // derivations not allowed
+        let mut psbt = taproot_psbt(derived.script_pubkey());
+        let mut origins = origins;
+        origins[0].1 = DerivationPath::from_str("m/0/5").unwrap();
+        derived.fill_input(&mut psbt.inputs[0], &origins);
+        let mut psbt_signer =
+            PsbtSigner::new(&psbt, key1.key, Network::Testnet, 10, false, &accounts).unwrap();
+        assert!(psbt_signer.sign().is_err());
+    }
+
+    #[test]
+    fn test_check_derivation() {
+        let accounts = [
+            DerivationPath::from_str("m/48'/1'/0'/2'").unwrap(),
+            DerivationPath::from_str("m/87'/1'/1'").unwrap(),
+        ];
+        let check =
+            |path: &str| check_derivation(&DerivationPath::from_str(path).unwrap(), &accounts);
+        assert!(check("m/48'/1'/0'/2'/0/5").is_ok());
+        assert!(check("m/48'/1'/0'/2'/1/5").is_ok());
+        assert!(check("m/87'/1'/1'/0/5").is_ok());
+        assert!(check("m/87'/1'/1'/2/5").is_err());
+        assert!(check("m/87'/1'/2'/0/5").is_err());
+        assert!(check("m/48'/1'/0'/2'/0/5/1").is_err());
+        assert!(check("m/0/5").is_err());
+    }
+
+    #[test]
+    fn test_sign_taproot_key_path() {
+        let secp = Secp256k1::new();
+        let bytes = include_bytes!("../../test_data/sign/psbt_testnet.1.key");
+        let key: crate::MasterSecret = serde_json::from_slice(bytes).unwrap();
+        let path = DerivationPath::from_str("m/86'/1'/0'/0/0").unwrap();
+        let internal_key: schnorrsig::PublicKey =
+            ExtendedPubKey::from_private(&secp, &key.key.derive_priv(&secp, &path).unwrap())
+                .public_key
+                .key
+                .into();
+        let mut engine = TapTweakHash::engine();
+        engine.input(&internal_key.serialize());
+        let tweak = TapTweakHash::from_engine(engine);
+        let mut output_key = internal_key;
+        output_key.tweak_add_assign(&secp, &tweak).unwrap();
+        let script_pubkey = Script::new_witness_program(
+            bitcoin::bech32::u5::try_from_u8(1).unwrap(),
+            &output_key.serialize(),
+        );
+
+        let mut psbt = taproot_psbt(script_pubkey);
+        let input = &mut psbt.inputs[0];
+        insert_tap_key_origin(
+            &mut input.unknown,
+            PSBT_IN_TAP_BIP32_DERIVATION,
+            &internal_key,
+            &[],
+            &(key.key.fingerprint(&secp), path),
+        );
+        input.unknown.insert(
+            bitcoin::util::psbt::raw::Key {
+                type_value: PSBT_IN_TAP_INTERNAL_KEY,
+                key: vec![],
+            },
+            internal_key.serialize().to_vec(),
+        );
+
+        let mut psbt_signer =
+            PsbtSigner::new(&psbt, key.key, Network::Testnet, 10, true, &[]).unwrap();
+        assert!(psbt_signer.sign().unwrap().signed);
+        let signed = psbt_signer.psbt;
+        let signature = tap_key_sig(&signed.inputs[0]).unwrap();
+        let (_, msg) = MessageToSign::new(&signed).hash_taproot(0, None).unwrap();
+        let signature = schnorrsig::Signature::from_slice(signature).unwrap();
+        secp.schnorrsig_verify(&signature, &msg, &output_key)
+            .unwrap();
+    }
 }
@@ -3,14 +3,38 @@ use crate::online::WalletNameOptions;
 use crate::*;
 use bitcoin::secp256k1::recovery::{RecoverableSignature, RecoveryId};
 use bitcoin::secp256k1::{Message, Secp256k1, Signing, Verification};
-use bitcoin::util::bip32::ExtendedPubKey;
+use bitcoin::util::bip32::{DerivationPath, ExtendedPrivKey, ExtendedPubKey};
 use bitcoin::util::misc::signed_msg_hash;
 use bitcoin::{Address, Network, PrivateKey, PublicKey};
 use log::debug;
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
+use structopt::StructOpt;
 
 pub const WALLET_SIGN_DERIVATION: u32 = u32::MAX >> 1;
 
+#[derive(StructOpt, Debug, Serialize, Deserialize)]
+pub struct SignWalletOptions {
+    /// The name of the wallet to sign
+    #[structopt(long = "wallet-name")]
+    pub wallet_name: String,
+
+    /// BIP39 passphrase of the signing key, never persisted, in the CLI it's read from stdin with
+    /// `--passphrase`
+    #[structopt(skip)]
+    #[serde(default, skip_serializing)]
+    pub passphrase: Option<String>,
+}
+
+impl From<&str> for SignWalletOptions {
+    fn from(name: &str) -> Self {
+        SignWalletOptions {
+            wallet_name: name.to_string(),
+            passphrase: None,
+        }
+    }
+}
+
 impl OfflineContext {
     pub fn verify_wallet(&self, opt: &WalletNameOptions) -> Result<VerifyWalletResult> {
         let secp = Secp256k1::verification_only();
@@ -20,7 +44,7 @@ impl OfflineContext {
         verify_wallet_internal(&secp, &wallet, &signature, self.network)
     }
 
-    pub fn sign_wallet(&self, opt: &WalletNameOptions) -> Result<WalletSignature> {
+    pub fn sign_wallet(&self, opt: &SignWalletOptions) -> Result<WalletSignature> {
         let secp = Secp256k1::signing_only();
         let wallet: Wallet = self.read(&opt.wallet_name)?;
         let message = &wallet.descriptor;
@@ -40,13 +64,22 @@ impl OfflineContext {
         let list_opt = ListOptions { kind };
         debug!("sign_wallet list_opt {:?}", list_opt);
         let available_keys = self.list(&list_opt)?;
-        let master_private_key = find_key(&secp, &available_keys, &desc_pub_keys)?;
+        let origin_paths = wallet
+            .extract_desc_pub_keys()?
+            .into_iter()
+            .filter_map(|k| match k {
+                miniscript::DescriptorPublicKey::XPub(xpub) => xpub.origin.map(|(_, path)| path),
+                miniscript::DescriptorPublicKey::SinglePub(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let (master_private_key, key) = find_key(
+            &secp,
+            &available_keys,
+            &desc_pub_keys,
+            &origin_paths,
+            opt.passphrase.as_deref(),
+        )?;
         debug!("sign_wallet using {}", master_private_key.id.name);
-        let key = master_private_key.as_wallet_sign_prv_key(&secp)?;
-        let pub_key = master_private_key.as_wallet_sign_pub_key(&secp)?;
-        debug!("sign_wallet using {}", pub_key);
-        let match_pub = ExtendedPubKey::from_private(&secp, &key);
-        assert_eq!(pub_key, match_pub.public_key);
 
         let signature = sign_message_with_key(&secp, &key.private_key, message)?;
 
@@ -65,20 +98,45 @@ impl OfflineContext {
     }
 }
 
-fn find_key<'a, T: Signing>(
+/// returns the master key participating in the wallet and its wallet signing key, the signing key
+/// is derived from the primary account or from one of the `origin_paths` of the wallet keys.
+/// Keys created with a passphrase are considered only if unlocked by `passphrase`
+fn find_key<T: Signing>(
     secp: &Secp256k1<T>,
-    available_keys: &'a ListOutput,
+    available_keys: &ListOutput,
     desc_pub_keys: &[PublicKey],
-) -> Result<&'a MasterSecret> {
+    origin_paths: &[DerivationPath],
+    passphrase: Option<&str>,
+) -> Result<(MasterSecret, ExtendedPrivKey)> {
+    let mut locked = false;
     for key in available_keys.master_secrets.iter() {
-        let k = key.as_wallet_sign_pub_key(secp)?;
-        debug!("find_key key:{} -> sign_pub_key:{}", key.id.name, k);
-        if desc_pub_keys.contains(&k) {
-            debug!("find_key found pubkey {} of key {}", k, key.id.name);
-            return Ok(key);
+        let key = match key.unlock(passphrase) {
+            Ok(key) => key,
+            Err(e) => {
+                debug!("find_key skipping key:{} {:?}", key.id.name, e);
+                locked = true;
+                continue;
+            }
+        };
+        let primary = key.path();
+        for path in std::iter::once(&primary).chain(origin_paths.iter()) {
+            let prv = key.as_wallet_sign_prv_key_at(secp, path)?;
+            let k = ExtendedPubKey::from_private(secp, &prv).public_key;
+            debug!(
+                "find_key key:{} {} -> sign_pub_key:{}",
+                key.id.name, path, k
+            );
+            if desc_pub_keys.contains(&k) {
+                debug!("find_key found pubkey {} of key {}", k, key.id.name);
+                return Ok((key, prv));
+            }
         }
     }
-    Err("There is no private key participating in the wallet available".into())
+    if locked {
+        Err("There is no private key participating in the wallet available, keys created with a passphrase require the right one".into())
+    } else {
+        Err("There is no private key participating in the wallet available".into())
+    }
 }
 
 pub fn verify_wallet_internal<T: Verification>(
@@ -198,8 +256,10 @@ fn verify_message<T: Verification>(
 #[cfg(test)]
 mod tests {
     use crate::context::tests::TestContext;
+    use crate::offline::derive_key::DeriveKeyOptions;
     use crate::offline::random::RandomOptions;
-    use crate::offline::sign_wallet::{sign_message, verify_message};
+    use crate::offline::sign_wallet::{sign_message, verify_message, SignWalletOptions};
+    use crate::offline::DerivationOptions;
     use crate::online::WalletNameOptions;
     use crate::{Error, Wallet};
     use bitcoin::secp256k1::Secp256k1;
@@ -256,6 +316,7 @@ mod tests {
         let key = context.create_key(&RandomOptions::new_random()).unwrap();
         let wallet = Wallet::new_random(1, &vec![key]);
         let wallet_name_opt: WalletNameOptions = wallet.id.name.as_str().into();
+        let sign_opt: SignWalletOptions = wallet.id.name.as_str().into();
 
         // manually importing the wallet, because context.create_wallet needs the node, not available in unit tests
         context
@@ -264,7 +325,7 @@ mod tests {
 
         let err = context.verify_wallet(&wallet_name_opt);
         assert_matches!(err, Err(Error::FileNotFoundOrCorrupt(..)));
-        let mut signature = context.sign_wallet(&wallet_name_opt).unwrap();
+        let mut signature = context.sign_wallet(&sign_opt).unwrap();
         let result = context.verify_wallet(&wallet_name_opt).unwrap();
         assert!(result.verified, "valid signature did not verify");
 
@@ -275,10 +336,11 @@ mod tests {
         let key_2 = context.create_key(&RandomOptions::new_random()).unwrap();
         let wallet_2 = Wallet::new_random(1, &vec![key_2]);
         let wallet_2_name_opt: WalletNameOptions = wallet_2.id.name.as_str().into();
+        let sign_2_opt: SignWalletOptions = wallet_2.id.name.as_str().into();
         context
             .import_json(serde_json::to_value(wallet_2).unwrap())
             .unwrap();
-        let signature_2 = context.sign_wallet(&wallet_2_name_opt).unwrap();
+        let signature_2 = context.sign_wallet(&sign_2_opt).unwrap();
         let result_2 = context.verify_wallet(&wallet_2_name_opt).unwrap();
         assert!(result_2.verified, "valid signature did not verify");
 
@@ -289,4 +351,107 @@ mod tests {
         let err = context.verify_wallet(&wallet_name_opt);
         assert_matches!(err, Err(Error::WalletSignatureNotVerified));
     }
+
+    #[test]
+    fn test_sign_verify_sortedmulti() {
+        let context = TestContext::default();
+        let key_1 = context.create_key(&RandomOptions::new_random()).unwrap();
+        let key_2 = context.create_key(&RandomOptions::new_random()).unwrap();
+        let desc_pub_key = |k: &crate::MasterSecret| k.as_desc_pub_key().unwrap().desc_pub_key;
+        let descriptor = format!(
+            "wsh(sortedmulti(1,{},{}))",
+            desc_pub_key(&key_2),
+            desc_pub_key(&key_1)
+        );
+        let wallet = Wallet::new(&descriptor, Network::Testnet);
+        let wallet_name_opt: WalletNameOptions = wallet.id.name.as_str().into();
+        let sign_opt: SignWalletOptions = wallet.id.name.as_str().into();
+        assert_eq!(wallet.extract_desc_pub_keys().unwrap().len(), 2);
+        context
+            .import_json(serde_json::to_value(wallet).unwrap())
+            .unwrap();
+
+        context.sign_wallet(&sign_opt).unwrap();
+        let result = context.verify_wallet(&wallet_name_opt).unwrap();
+        assert!(result.verified, "valid signature did not verify");
+    }
+
+    #[test]
+    fn test_sign_verify_sh_wsh() {
+        let context = TestContext::default();
+        let key_1 = context.create_key(&RandomOptions::new_random()).unwrap();
+        let key_2 = context.create_key(&RandomOptions::new_random()).unwrap();
+        let desc_pub_key = |k: &crate::MasterSecret| k.as_desc_pub_key().unwrap().desc_pub_key;
+        let descriptor = format!(
+            "sh(wsh(sortedmulti(2,{},{})))",
+            desc_pub_key(&key_1),
+            desc_pub_key(&key_2)
+        );
+        let wallet = Wallet::new(&descriptor, Network::Testnet);
+        let wallet_name_opt: WalletNameOptions = wallet.id.name.as_str().into();
+        let sign_opt: SignWalletOptions = wallet.id.name.as_str().into();
+        assert_eq!(wallet.extract_desc_pub_keys().unwrap().len(), 2);
+        context
+            .import_json(serde_json::to_value(wallet).unwrap())
+            .unwrap();
+
+        context.sign_wallet(&sign_opt).unwrap();
+        let result = context.verify_wallet(&wallet_name_opt).unwrap();
+        assert!(result.verified, "valid signature did not verify");
+    }
+
+    #[test]
+    fn test_sign_verify_account() {
+        let context = TestContext::default();
+        let rand_opts = RandomOptions::new_random();
+        let key_1 = context.create_key(&rand_opts).unwrap();
+        let key_2 = context.create_key(&RandomOptions::new_random()).unwrap();
+        let opt = DeriveKeyOptions {
+            key_name: rand_opts.key_name.clone(),
+            derivation: DerivationOptions {
+                scheme: Default::default(),
+                account: 1,
+            },
+            passphrase: None,
+        };
+        let account = context.derive_key(&opt).unwrap();
+        assert_ne!(account, key_1.as_desc_pub_key().unwrap());
+        let descriptor = format!(
+            "wsh(sortedmulti(2,{},{}))",
+            account.desc_pub_key,
+            key_2.as_desc_pub_key().unwrap().desc_pub_key
+        );
+        let wallet = Wallet::new(&descriptor, Network::Testnet);
+        let wallet_name_opt: WalletNameOptions = wallet.id.name.as_str().into();
+        let sign_opt: SignWalletOptions = wallet.id.name.as_str().into();
+        context
+            .import_json(serde_json::to_value(wallet).unwrap())
+            .unwrap();
+
+        context.sign_wallet(&sign_opt).unwrap();
+        let result = context.verify_wallet(&wallet_name_opt).unwrap();
+        assert!(result.verified, "valid signature did not verify");
+    }
+
+    #[test]
+    fn test_sign_verify_passphrase() {
+        let context = TestContext::default();
+        let mut rand_opts = RandomOptions::new_random();
+        rand_opts.passphrase = Some("passphrase".to_string());
+        let key = context.create_key(&rand_opts).unwrap();
+        let wallet = Wallet::new_random(1, &[key]);
+        let wallet_name_opt: WalletNameOptions = wallet.id.name.as_str().into();
+        let mut sign_opt: SignWalletOptions = wallet.id.name.as_str().into();
+        context
+            .import_json(serde_json::to_value(wallet).unwrap())
+            .unwrap();
+
+        assert!(context.sign_wallet(&sign_opt).is_err());
+        sign_opt.passphrase = Some("wrong".to_string());
+        assert!(context.sign_wallet(&sign_opt).is_err());
+        sign_opt.passphrase = Some("passphrase".to_string());
+        context.sign_wallet(&sign_opt).unwrap();
+        let result = context.verify_wallet(&wallet_name_opt).unwrap();
+        assert!(result.verified, "valid signature did not verify");
+    }
 }
@@ -0,0 +1,149 @@
+use crate::slip39::{self, GroupSpec};
+use crate::*;
+use bitcoin::util::bip32::ExtendedPrivKey;
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+/// Split the seed of a key in SLIP-39 shares
+#[derive(StructOpt, Debug, Serialize, Deserialize)]
+#[structopt(name = "split")]
+pub struct SplitOptions {
+    /// Name of the key
+    #[structopt(short, long)]
+    pub key_name: String,
+
+    /// Number of groups needed to restore the key
+    #[structopt(long, default_value = "1")]
+    pub group_threshold: u8,
+
+    /// Group of shares as `M-of-N`, to be repeated for every group
+    #[structopt(long = "group", required = true)]
+    pub groups: Vec<GroupSpec>,
+
+    /// SLIP-39 iteration exponent, the encryption of the seed uses 10000*2^e iterations
+    #[structopt(long, default_value = "1")]
+    pub iteration_exponent: u8,
+
+    /// BIP39 passphrase, never persisted, in the CLI it's read from stdin with `--passphrase`
+    #[structopt(skip)]
+    #[serde(default, skip_serializing)]
+    pub passphrase: Option<String>,
+}
+
+impl OfflineContext {
+    /// the BIP32 seed of the key is split, so that the key can be restored with any SLIP-39
+    /// implementation, shares are not protected by a SLIP-39 passphrase
+    pub fn split(&self, opt: &SplitOptions) -> Result<SplitOutput> {
+        let secret: MasterSecret = self.read(&opt.key_name)?;
+        let secret = secret.unlock(opt.passphrase.as_deref())?;
+        let mnemonic = secret
+            .mnemonic
+            .as_ref()
+            .ok_or_else(|| Error::Generic("Only keys with a mnemonic can be split".into()))?;
+        let seed = mnemonic.to_seed(opt.passphrase.as_deref());
+        if ExtendedPrivKey::new_master(self.network, &seed.0)? != secret.key {
+            return Err(Error::Generic("The seed doesn't match the key".into()));
+        }
+
+        let groups = slip39::split(
+            &seed.0,
+            b"",
+            opt.group_threshold,
+            &opt.groups,
+            opt.iteration_exponent,
+        )?;
+        let groups = groups
+            .iter()
+            .map(|group| group.iter().map(|share| share.to_string()).collect())
+            .collect();
+
+        Ok(SplitOutput {
+            group_threshold: opt.group_threshold,
+            groups,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::context::tests::TestContext;
+    use crate::offline::random::RandomOptions;
+    use crate::offline::restore::{Nature, RestoreOptions};
+    use crate::offline::split::SplitOptions;
+    use crate::slip39;
+    use crate::Error;
+    use bitcoin::util::bip32::ExtendedPrivKey;
+    use bitcoin::Network;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_split_restore() {
+        let context = TestContext::default();
+        let mut rand_opts = RandomOptions::new_random();
+        rand_opts.passphrase = Some("passphrase".to_string());
+        let key = context.create_key(&rand_opts).unwrap();
+
+        let mut opt = SplitOptions {
+            key_name: rand_opts.key_name.clone(),
+            group_threshold: 1,
+            groups: vec![FromStr::from_str("2-of-3").unwrap()],
+            iteration_exponent: 0,
+            passphrase: None,
+        };
+        assert!(matches!(
+            context.split(&opt),
+            Err(Error::PassphraseRequired)
+        ));
+        opt.passphrase = rand_opts.passphrase.clone();
+        let output = context.split(&opt).unwrap();
+        assert_eq!(output.groups.len(), 1);
+        assert_eq!(output.groups[0].len(), 3);
+        assert_eq!(output.groups[0][0].split(' ').count(), 59);
+
+        let mut restore_opts = RestoreOptions {
+            key_name: "restored".to_string(),
+            nature: Nature::Slip39,
+            value: format!("{},{}", output.groups[0][2], output.groups[0][0]),
+            derivation: Default::default(),
+            passphrase: rand_opts.passphrase.clone(),
+            slip39_passphrase: None,
+        };
+        // the passphrase given to split must not be used to restore, it's already in the seed
+        assert!(context.restore(&restore_opts).is_err());
+        restore_opts.passphrase = None;
+        let restored = context.restore(&restore_opts).unwrap();
+        assert_eq!(restored.key, key.key);
+        assert_eq!(
+            restored.as_desc_pub_key().unwrap().desc_pub_key,
+            key.as_desc_pub_key().unwrap().desc_pub_key
+        );
+
+        restore_opts.key_name = "not-enough".to_string();
+        restore_opts.value = output.groups[0][1].clone();
+        assert!(context.restore(&restore_opts).is_err());
+    }
+
+    #[test]
+    fn test_restore_slip39_passphrase() {
+        let context = TestContext::default();
+        let seed = [7u8; 16];
+        let groups = vec![FromStr::from_str("1-of-1").unwrap()];
+        let shares = slip39::split(&seed, b"slip39", 1, &groups, 0).unwrap();
+        let expected = ExtendedPrivKey::new_master(Network::Testnet, &seed).unwrap();
+
+        let mut restore_opts = RestoreOptions {
+            key_name: "slip39".to_string(),
+            nature: Nature::Slip39,
+            value: shares[0][0].to_string(),
+            derivation: Default::default(),
+            passphrase: None,
+            slip39_passphrase: Some("slip39".to_string()),
+        };
+        assert_eq!(context.restore(&restore_opts).unwrap().key, expected);
+
+        restore_opts.key_name = "mnemonic".to_string();
+        restore_opts.nature = Nature::Mnemonic;
+        restore_opts.value = crate::mnemonic::Mnemonic::new(&seed).unwrap().to_string();
+        assert!(context.restore(&restore_opts).is_err());
+    }
+}
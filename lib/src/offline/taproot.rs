@@ -0,0 +1,741 @@
+use crate::*;
+use bitcoin::blockdata::opcodes;
+use bitcoin::blockdata::script::{Builder, Instruction};
+use bitcoin::consensus::encode::VarInt;
+use bitcoin::consensus::{deserialize_partial, serialize, Encodable};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::{schnorrsig, Secp256k1, Verification};
+use bitcoin::util::address::Payload;
+use bitcoin::util::bip32::{ChildNumber, DerivationPath, Fingerprint};
+use bitcoin::util::psbt::{raw, Input};
+use bitcoin::util::taproot::{TapBranchHash, TapLeafHash, TapSighashHash, TapTweakHash};
+use bitcoin::{bech32, Address, Network, Script, Transaction, TxOut};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// x-only public key with unknown discrete logarithm (BIP341), used as internal key so that
+/// the key path spend is not possible and the wallet is a pure script path multisig
+pub const NUMS: &str = "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+/// leaf version of tapscript (BIP342)
+pub const TAPSCRIPT_LEAF_VERSION: u8 = 0xc0;
+
+/// OP_CHECKSIGADD (BIP342), in rust-bitcoin 0.27 it's still named after the OP_SUCCESS it replaced
+pub const OP_CHECKSIGADD: opcodes::All = opcodes::all::OP_RETURN_186;
+
+/// A taproot multisig descriptor in the form "tr(NUMS,multi_a({n},{x}/{c}/*,{y}/{c}/*,...))",
+/// also `sortedmulti_a` is supported.
+/// miniscript in use does not support taproot so parsing is done here.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrMulti {
+    pub required_sigs: usize,
+    pub sorted: bool,
+    pub keys: Vec<miniscript::DescriptorPublicKey>,
+}
+
+/// A `TrMulti` derived at a specific index
+#[derive(Debug, Clone, PartialEq)]
+pub struct DerivedTrMulti {
+    pub internal_key: schnorrsig::PublicKey,
+    pub keys: Vec<schnorrsig::PublicKey>,
+    pub leaf_script: Script,
+    pub leaf_hash: TapLeafHash,
+    pub output_key: schnorrsig::PublicKey,
+    pub output_key_parity: bool,
+}
+
+pub fn is_taproot(descriptor: &str) -> bool {
+    descriptor.starts_with("tr(")
+}
+
+pub fn nums() -> schnorrsig::PublicKey {
+    schnorrsig::PublicKey::from_str(NUMS).expect("NUMS is a valid x-only key")
+}
+
+impl FromStr for TrMulti {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let end = s.find('#').unwrap_or(s.len());
+        let inner = s[..end]
+            .strip_prefix("tr(")
+            .and_then(|s| s.strip_suffix(')'))
+            .ok_or_else(|| Error::Generic(format!("{} is not a tr() descriptor", s)))?;
+        let mut parts = inner.splitn(2, ',');
+        let internal = parts.next().unwrap_or_default();
+        if internal != NUMS {
+            return Err("only the NUMS internal key is supported in tr() descriptors".into());
+        }
+        let script = parts.next().unwrap_or_default();
+        let (sorted, args) = match script.strip_prefix("sortedmulti_a(") {
+            Some(args) => (true, args),
+            None => (false, script.strip_prefix("multi_a(").unwrap_or_default()),
+        };
+        let args = args
+            .strip_suffix(')')
+            .ok_or("tr() script must be multi_a or sortedmulti_a")?;
+        let mut args = args.split(',');
+        let required_sigs: usize = args
+            .next()
+            .unwrap_or_default()
+            .parse()
+            .map_err(|_| Error::Generic("invalid required signatures".into()))?;
+        let mut keys = vec![];
+        for k in args {
+            keys.push(k.parse()?);
+        }
+        if required_sigs == 0 || required_sigs > keys.len() {
+            return Err("required signatures must be between 1 and the number of keys".into());
+        }
+        Ok(TrMulti {
+            required_sigs,
+            sorted,
+            keys,
+        })
+    }
+}
+
+impl fmt::Display for TrMulti {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: Vec<String> = self.keys.iter().map(|k| k.to_string()).collect();
+        let multi = if self.sorted {
+            "sortedmulti_a"
+        } else {
+            "multi_a"
+        };
+        write!(
+            f,
+            "tr({},{}({},{}))",
+            NUMS,
+            multi,
+            self.required_sigs,
+            keys.join(",")
+        )
+    }
+}
+
+impl TrMulti {
+    pub fn derive<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        index: u32,
+    ) -> Result<DerivedTrMulti> {
+        let mut keys = vec![];
+        for key in self.keys.iter() {
+            let public_key = key.clone().derive(index).derive_public_key(secp)?;
+            keys.push(schnorrsig::PublicKey::from(public_key.key));
+        }
+        if self.sorted {
+            keys.sort_by_key(|k| k.serialize());
+        }
+        let leaf_script = multi_a_script(self.required_sigs, &keys);
+        let leaf_hash = tap_leaf_hash(&leaf_script);
+
+        let internal_key = nums();
+        let mut engine = TapTweakHash::engine();
+        engine.input(&internal_key.serialize());
+        engine.input(&leaf_hash);
+        let tweak = TapTweakHash::from_engine(engine);
+
+        let mut output_key = internal_key;
+        let output_key_parity = output_key.tweak_add_assign(secp, &tweak)?;
+
+        Ok(DerivedTrMulti {
+            internal_key,
+            keys,
+            leaf_script,
+            leaf_hash,
+            output_key,
+            output_key_parity,
+        })
+    }
+}
+
+impl DerivedTrMulti {
+    pub fn script_pubkey(&self) -> Script {
+        Script::new_witness_program(v1(), &self.output_key.serialize())
+    }
+
+    pub fn address(&self, network: Network) -> Address {
+        Address {
+            payload: Payload::WitnessProgram {
+                version: v1(),
+                program: self.output_key.serialize().to_vec(),
+            },
+            network,
+        }
+    }
+
+    /// control block for spending the only leaf of the tree
+    pub fn control_block(&self) -> Vec<u8> {
+        let mut result = vec![TAPSCRIPT_LEAF_VERSION | self.output_key_parity as u8];
+        result.extend(&self.internal_key.serialize());
+        result
+    }
+}
+
+fn v1() -> bech32::u5 {
+    bech32::u5::try_from_u8(1).expect("1 is a valid u5")
+}
+
+/// `<key_1> OP_CHECKSIG <key_2> OP_CHECKSIGADD ... <key_n> OP_CHECKSIGADD <k> OP_NUMEQUAL`
+pub fn multi_a_script(required_sigs: usize, keys: &[schnorrsig::PublicKey]) -> Script {
+    let mut builder = Builder::new();
+    for (i, key) in keys.iter().enumerate() {
+        builder = builder.push_slice(&key.serialize());
+        builder = if i == 0 {
+            builder.push_opcode(opcodes::all::OP_CHECKSIG)
+        } else {
+            builder.push_opcode(OP_CHECKSIGADD)
+        };
+    }
+    builder
+        .push_int(required_sigs as i64)
+        .push_opcode(opcodes::all::OP_NUMEQUAL)
+        .into_script()
+}
+
+pub fn tap_leaf_hash(script: &Script) -> TapLeafHash {
+    let mut engine = TapLeafHash::engine();
+    engine.input(&[TAPSCRIPT_LEAF_VERSION]);
+    engine.input(&serialize(script)); // compact size prefixed
+    TapLeafHash::from_engine(engine)
+}
+
+/// taproot sighash type committing to everything, not appended to signatures
+pub const SIGHASH_DEFAULT: u8 = 0x00;
+
+/// BIP371 PSBT input types, rust-bitcoin 0.27 keeps them in the `unknown` map
+pub const PSBT_IN_TAP_KEY_SIG: u8 = 0x13;
+pub const PSBT_IN_TAP_SCRIPT_SIG: u8 = 0x14;
+pub const PSBT_IN_TAP_LEAF_SCRIPT: u8 = 0x15;
+pub const PSBT_IN_TAP_BIP32_DERIVATION: u8 = 0x16;
+pub const PSBT_IN_TAP_INTERNAL_KEY: u8 = 0x17;
+pub const PSBT_IN_TAP_MERKLE_ROOT: u8 = 0x18;
+/// BIP371 PSBT output types
+pub const PSBT_OUT_TAP_INTERNAL_KEY: u8 = 0x05;
+pub const PSBT_OUT_TAP_BIP32_DERIVATION: u8 = 0x07;
+
+pub type UnknownMap = BTreeMap<raw::Key, Vec<u8>>;
+pub type KeySource = (Fingerprint, DerivationPath);
+pub type TapKeyOrigins = BTreeMap<schnorrsig::PublicKey, (Vec<TapLeafHash>, KeySource)>;
+
+fn raw_key(type_value: u8, key: &[u8]) -> raw::Key {
+    raw::Key {
+        type_value,
+        key: key.to_vec(),
+    }
+}
+
+fn x_only(bytes: &[u8]) -> Result<schnorrsig::PublicKey> {
+    Ok(schnorrsig::PublicKey::from_slice(bytes)?)
+}
+
+/// `PSBT_IN_TAP_BIP32_DERIVATION` or `PSBT_OUT_TAP_BIP32_DERIVATION` depending on `type_value`
+pub fn tap_key_origins(unknown: &UnknownMap, type_value: u8) -> Result<TapKeyOrigins> {
+    let mut result = BTreeMap::new();
+    for (key, value) in unknown.iter().filter(|(k, _)| k.type_value == type_value) {
+        let (n, mut pos): (VarInt, usize) = deserialize_partial(value)?;
+        let mut leaf_hashes = vec![];
+        for _ in 0..n.0 {
+            let bytes = value.get(pos..pos + 32).ok_or(Error::MissingKey)?;
+            leaf_hashes.push(TapLeafHash::from_slice(bytes).map_err(|e| e.to_string())?);
+            pos += 32;
+        }
+        let fingerprint = Fingerprint::from(value.get(pos..pos + 4).ok_or(Error::MissingKey)?);
+        let chunks = value[pos + 4..].chunks_exact(4);
+        if !chunks.remainder().is_empty() {
+            return Err("taproot derivation path is not a multiple of 4 bytes".into());
+        }
+        let path = chunks
+            .map(|c| {
+                let mut buf = [0u8; 4];
+                buf.copy_from_slice(c);
+                ChildNumber::from(u32::from_le_bytes(buf))
+            })
+            .collect::<Vec<_>>();
+        result.insert(x_only(&key.key)?, (leaf_hashes, (fingerprint, path.into())));
+    }
+    Ok(result)
+}
+
+pub fn insert_tap_key_origin(
+    unknown: &mut UnknownMap,
+    type_value: u8,
+    key: &schnorrsig::PublicKey,
+    leaf_hashes: &[TapLeafHash],
+    key_source: &KeySource,
+) {
+    let mut value = serialize(&VarInt(leaf_hashes.len() as u64));
+    for leaf_hash in leaf_hashes {
+        value.extend(&leaf_hash[..]);
+    }
+    value.extend(key_source.0.as_bytes());
+    for child in key_source.1.as_ref() {
+        value.extend(&u32::from(*child).to_le_bytes());
+    }
+    unknown.insert(raw_key(type_value, &key.serialize()), value);
+}
+
+/// scripts in the input, mapped by control block
+pub fn tap_scripts(input: &Input) -> BTreeMap<Vec<u8>, (Script, u8)> {
+    input
+        .unknown
+        .iter()
+        .filter(|(k, v)| k.type_value == PSBT_IN_TAP_LEAF_SCRIPT && !v.is_empty())
+        .map(|(k, v)| {
+            let (script, leaf_version) = v.split_at(v.len() - 1);
+            (k.key.clone(), (script.to_vec().into(), leaf_version[0]))
+        })
+        .collect()
+}
+
+pub fn tap_internal_key(input: &Input) -> Result<Option<schnorrsig::PublicKey>> {
+    match input.unknown.get(&raw_key(PSBT_IN_TAP_INTERNAL_KEY, &[])) {
+        Some(value) => Ok(Some(x_only(value)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn tap_merkle_root(input: &Input) -> Option<&[u8]> {
+    input
+        .unknown
+        .get(&raw_key(PSBT_IN_TAP_MERKLE_ROOT, &[]))
+        .map(|v| &v[..])
+}
+
+pub fn tap_key_sig(input: &Input) -> Option<&Vec<u8>> {
+    input.unknown.get(&raw_key(PSBT_IN_TAP_KEY_SIG, &[]))
+}
+
+/// script path signatures in the input, mapped by x-only key and leaf hash
+pub fn tap_script_sigs(
+    input: &Input,
+) -> Result<BTreeMap<(schnorrsig::PublicKey, TapLeafHash), Vec<u8>>> {
+    let mut result = BTreeMap::new();
+    for (key, value) in input
+        .unknown
+        .iter()
+        .filter(|(k, _)| k.type_value == PSBT_IN_TAP_SCRIPT_SIG && k.key.len() == 64)
+    {
+        let leaf_hash = TapLeafHash::from_slice(&key.key[32..]).map_err(|e| e.to_string())?;
+        result.insert((x_only(&key.key[..32])?, leaf_hash), value.clone());
+    }
+    Ok(result)
+}
+
+pub fn insert_tap_key_sig(input: &mut Input, signature: Vec<u8>) {
+    input
+        .unknown
+        .insert(raw_key(PSBT_IN_TAP_KEY_SIG, &[]), signature);
+}
+
+pub fn insert_tap_script_sig(
+    input: &mut Input,
+    key: &schnorrsig::PublicKey,
+    leaf_hash: &TapLeafHash,
+    signature: Vec<u8>,
+) {
+    let mut raw = key.serialize().to_vec();
+    raw.extend(&leaf_hash[..]);
+    input
+        .unknown
+        .insert(raw_key(PSBT_IN_TAP_SCRIPT_SIG, &raw), signature);
+}
+
+impl DerivedTrMulti {
+    /// fill BIP371 fields of an input spending this output, `origins` are the key sources
+    /// of `self.keys` in the same order
+    pub fn fill_input(&self, input: &mut Input, origins: &[KeySource]) {
+        let mut value = self.leaf_script.to_bytes();
+        value.push(TAPSCRIPT_LEAF_VERSION);
+        input.unknown.insert(
+            raw_key(PSBT_IN_TAP_LEAF_SCRIPT, &self.control_block()),
+            value,
+        );
+        input.unknown.insert(
+            raw_key(PSBT_IN_TAP_INTERNAL_KEY, &[]),
+            self.internal_key.serialize().to_vec(),
+        );
+        input.unknown.insert(
+            raw_key(PSBT_IN_TAP_MERKLE_ROOT, &[]),
+            self.leaf_hash.to_vec(),
+        );
+        for (key, origin) in self.keys.iter().zip(origins) {
+            insert_tap_key_origin(
+                &mut input.unknown,
+                PSBT_IN_TAP_BIP32_DERIVATION,
+                key,
+                &[self.leaf_hash],
+                origin,
+            );
+        }
+    }
+}
+
+pub fn is_v1_p2tr(script: &Script) -> bool {
+    let bytes = script.as_bytes();
+    bytes.len() == 34 && bytes[0] == opcodes::all::OP_PUSHNUM_1.into_u8() && bytes[1] == 32
+}
+
+/// true if `leaf_script` with the internal key and merkle path in `control_block` commits to the
+/// taproot output `script_pubkey` (BIP341 script path validation)
+pub fn control_block_commits<C: Verification>(
+    secp: &Secp256k1<C>,
+    control_block: &[u8],
+    leaf_script: &Script,
+    script_pubkey: &Script,
+) -> Result<bool> {
+    if !is_v1_p2tr(script_pubkey) || control_block.len() < 33 {
+        return Ok(false);
+    }
+    let path = control_block[33..].chunks_exact(32);
+    if !path.remainder().is_empty() {
+        return Ok(false);
+    }
+    let mut engine = TapLeafHash::engine();
+    engine.input(&[control_block[0] & 0xfe]);
+    engine.input(&serialize(leaf_script));
+    let mut node = TapLeafHash::from_engine(engine).into_inner();
+    for sibling in path {
+        let mut engine = TapBranchHash::engine();
+        if node[..] <= sibling[..] {
+            engine.input(&node);
+            engine.input(sibling);
+        } else {
+            engine.input(sibling);
+            engine.input(&node);
+        }
+        node = TapBranchHash::from_engine(engine).into_inner();
+    }
+    let internal_key = x_only(&control_block[1..33])?;
+    let mut engine = TapTweakHash::engine();
+    engine.input(&internal_key.serialize());
+    engine.input(&node);
+    let tweak = TapTweakHash::from_engine(engine);
+    let mut output_key = internal_key;
+    let parity = output_key.tweak_add_assign(secp, &tweak)?;
+    Ok(output_key.serialize() == script_pubkey.as_bytes()[2..]
+        && parity == (control_block[0] & 1 == 1))
+}
+
+/// true if `key` is pushed in `script`
+pub fn script_has_key(script: &Script, key: &schnorrsig::PublicKey) -> bool {
+    let key = key.serialize();
+    script
+        .instructions()
+        .any(|i| matches!(i, Ok(Instruction::PushBytes(bytes)) if bytes == &key[..]))
+}
+
+/// Compute the BIP341 signature hash of the input at `input_index`, `prevouts` are all the
+/// outputs spent by the transaction. `leaf_hash` is given for script path spends.
+pub fn taproot_signature_hash(
+    tx: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+    sighash_type: u8,
+    leaf_hash: Option<&TapLeafHash>,
+) -> Result<TapSighashHash> {
+    let anyone_can_pay = sighash_type & 0x80 != 0;
+    let output_type = sighash_type & 0x7f;
+    if output_type > 3 || sighash_type == 0x80 {
+        return Err(format!("invalid taproot sighash type {}", sighash_type).into());
+    }
+    if prevouts.len() != tx.input.len() || input_index >= tx.input.len() {
+        return Err("prevouts must match transaction inputs".into());
+    }
+    let mut engine = TapSighashHash::engine();
+    engine.input(&[0u8, sighash_type]); // epoch and hash type
+    tx.version.consensus_encode(&mut engine)?;
+    tx.lock_time.consensus_encode(&mut engine)?;
+
+    if !anyone_can_pay {
+        let mut prevouts_engine = sha256::Hash::engine();
+        let mut amounts_engine = sha256::Hash::engine();
+        let mut script_pubkeys_engine = sha256::Hash::engine();
+        let mut sequences_engine = sha256::Hash::engine();
+        for (input, prevout) in tx.input.iter().zip(prevouts) {
+            input
+                .previous_output
+                .consensus_encode(&mut prevouts_engine)?;
+            prevout.value.consensus_encode(&mut amounts_engine)?;
+            prevout
+                .script_pubkey
+                .consensus_encode(&mut script_pubkeys_engine)?;
+            input.sequence.consensus_encode(&mut sequences_engine)?;
+        }
+        engine.input(&sha256::Hash::from_engine(prevouts_engine));
+        engine.input(&sha256::Hash::from_engine(amounts_engine));
+        engine.input(&sha256::Hash::from_engine(script_pubkeys_engine));
+        engine.input(&sha256::Hash::from_engine(sequences_engine));
+    }
+    if output_type != 2 && output_type != 3 {
+        // not NONE nor SINGLE
+        let mut outputs_engine = sha256::Hash::engine();
+        for output in tx.output.iter() {
+            output.consensus_encode(&mut outputs_engine)?;
+        }
+        engine.input(&sha256::Hash::from_engine(outputs_engine));
+    }
+
+    let spend_type = if leaf_hash.is_some() { 2u8 } else { 0u8 }; // no annex
+    engine.input(&[spend_type]);
+    if anyone_can_pay {
+        let input = &tx.input[input_index];
+        input.previous_output.consensus_encode(&mut engine)?;
+        prevouts[input_index].consensus_encode(&mut engine)?;
+        input.sequence.consensus_encode(&mut engine)?;
+    } else {
+        (input_index as u32).consensus_encode(&mut engine)?;
+    }
+    if output_type == 3 {
+        let output = tx
+            .output
+            .get(input_index)
+            .ok_or("SIGHASH_SINGLE without corresponding output")?;
+        let mut output_engine = sha256::Hash::engine();
+        output.consensus_encode(&mut output_engine)?;
+        engine.input(&sha256::Hash::from_engine(output_engine));
+    }
+    if let Some(leaf_hash) = leaf_hash {
+        engine.input(&leaf_hash[..]);
+        engine.input(&[0u8]); // key version
+        engine.input(&u32::MAX.to_le_bytes()); // no OP_CODESEPARATOR executed
+    }
+    Ok(TapSighashHash::from_engine(engine))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::offline::taproot::*;
+    use bitcoin::consensus::deserialize;
+
+    const K1: &str = "[a2ebe04e/48'/1'/0'/2']tpubDEXDRpvW2srXCSjAvC36zYkSE3jxT1wf7JXDo35Ln4NZpmaMNhq8o9coH9U9BQ5bAN4WDGxXV9d426iYKGorFF5wvv4Wv63cZsCotiXGGkD/0/*";
+    const K2: &str = "[1f5e43d8/48'/1'/0'/2']tpubDFU4parcXvV8tBYt4rS4a8rGNF1DA32DCnRfhzVL6b3MSiDomV95rv9mb7W7jAPMTohyEYpbhVS8FbmTsuQsFRxDWPJX2ZFEeRPMFz3R1gh/0/*";
+
+    #[test]
+    fn test_tr_multi_roundtrip() {
+        let desc = format!("tr({},multi_a(2,{},{}))", NUMS, K1, K2);
+        let tr: TrMulti = desc.parse().unwrap();
+        assert_eq!(tr.required_sigs, 2);
+        assert!(!tr.sorted);
+        assert_eq!(tr.to_string(), desc);
+        let with_checksum = format!("{}#abcdefgh", desc);
+        assert_eq!(tr, with_checksum.parse().unwrap());
+
+        let sorted = format!("tr({},sortedmulti_a(1,{},{}))", NUMS, K1, K2);
+        let tr: TrMulti = sorted.parse().unwrap();
+        assert!(tr.sorted);
+        assert_eq!(tr.to_string(), sorted);
+
+        assert!(format!("tr({},multi_a(3,{},{}))", NUMS, K1, K2)
+            .parse::<TrMulti>()
+            .is_err());
+        assert!(format!("tr({},multi_a(1,{}))", K2, K1)
+            .parse::<TrMulti>()
+            .is_err());
+        assert!(format!("wsh(multi(1,{}))", K1).parse::<TrMulti>().is_err());
+    }
+
+    #[test]
+    fn test_control_block_commits() {
+        let secp = Secp256k1::verification_only();
+        let desc = format!("tr({},multi_a(2,{},{}))", NUMS, K1, K2);
+        let tr: TrMulti = desc.parse().unwrap();
+        let derived = tr.derive(&secp, 0).unwrap();
+        let other = tr.derive(&secp, 1).unwrap();
+        let control_block = derived.control_block();
+        let script_pubkey = derived.script_pubkey();
+        let leaf = &derived.leaf_script;
+
+        assert!(control_block_commits(&secp, &control_block, leaf, &script_pubkey).unwrap());
+        let other_spk = other.script_pubkey();
+        assert!(!control_block_commits(&secp, &control_block, leaf, &other_spk).unwrap());
+        let other_leaf = &other.leaf_script;
+        assert!(!control_block_commits(&secp, &control_block, other_leaf, &script_pubkey).unwrap());
+        let mut wrong_parity = control_block.clone();
+        wrong_parity[0] ^= 1;
+        assert!(!control_block_commits(&secp, &wrong_parity, leaf, &script_pubkey).unwrap());
+        let truncated = &control_block[..32];
+        assert!(!control_block_commits(&secp, truncated, leaf, &script_pubkey).unwrap());
+
+        assert!(script_has_key(leaf, &derived.keys[0]));
+        assert!(!script_has_key(leaf, &other.keys[0]));
+    }
+
+    #[test]
+    fn test_tr_multi_derive() {
+        let secp = Secp256k1::verification_only();
+        let desc = format!("tr({},multi_a(2,{},{}))", NUMS, K1, K2);
+        let tr: TrMulti = desc.parse().unwrap();
+        let derived = tr.derive(&secp, 0).unwrap();
+        assert_eq!(derived.keys.len(), 2);
+        assert!(derived.output_key.tweak_add_check(
+            &secp,
+            &derived.internal_key,
+            derived.output_key_parity,
+            {
+                let mut engine = TapTweakHash::engine();
+                engine.input(&derived.internal_key.serialize());
+                engine.input(&derived.leaf_hash);
+                TapTweakHash::from_engine(engine).into_inner()
+            }
+        ));
+        assert_eq!(derived.control_block().len(), 33);
+        assert_eq!(
+            derived.address(Network::Testnet).script_pubkey(),
+            derived.script_pubkey()
+        );
+
+        let sorted = format!("tr({},sortedmulti_a(2,{},{}))", NUMS, K2, K1);
+        let derived_sorted = sorted.parse::<TrMulti>().unwrap().derive(&secp, 0).unwrap();
+        let mut keys = derived.keys.clone();
+        keys.sort_by_key(|k| k.serialize());
+        assert_eq!(derived_sorted.keys, keys);
+    }
+
+    #[test]
+    fn test_taproot_signature_hash() {
+        // keyPathSpending test vectors from BIP341
+        let tx: Transaction = deserialize(&hex::decode("02000000097de20cbff686da83a54981d2b9bab3586f4ca7e48f57f5b55963115f3b334e9c010000000000000000d7b7cab57b1393ace2d064f4d4a2cb8af6def61273e127517d44759b6dafdd990000000000fffffffff8e1f583384333689228c5d28eac13366be082dc57441760d957275419a418420000000000fffffffff0689180aa63b30cb162a73c6d2a38b7eeda2a83ece74310fda0843ad604853b0100000000feffffffaa5202bdf6d8ccd2ee0f0202afbbb7461d9264a25e5bfd3c5a52ee1239e0ba6c0000000000feffffff956149bdc66faa968eb2be2d2faa29718acbfe3941215893a2a3446d32acd050000000000000000000e664b9773b88c09c32cb70a2a3e4da0ced63b7ba3b22f848531bbb1d5d5f4c94010000000000000000e9aa6b8e6c9de67619e6a3924ae25696bb7b694bb677a632a74ef7eadfd4eabf0000000000ffffffffa778eb6a263dc090464cd125c466b5a99667720b1c110468831d058aa1b82af10100000000ffffffff0200ca9a3b000000001976a91406afd46bcdfd22ef94ac122aa11f241244a37ecc88ac807840cb0000000020ac9a87f5594be208f8532db38cff670c450ed2fea8fcdefcc9a663f78bab962b0065cd1d").unwrap()).unwrap();
+        let prevouts: Vec<TxOut> = vec![
+            (
+                "512053a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343",
+                420000000,
+            ),
+            (
+                "5120147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3",
+                462000000,
+            ),
+            (
+                "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac",
+                294000000,
+            ),
+            (
+                "5120e4d810fd50586274face62b8a807eb9719cef49c04177cc6b76a9a4251d5450e",
+                504000000,
+            ),
+            (
+                "512091b64d5324723a985170e4dc5a0f84c041804f2cd12660fa5dec09fc21783605",
+                630000000,
+            ),
+            ("00147dd65592d0ab2fe0d0257d571abf032cd9db93dc", 378000000),
+            (
+                "512075169f4001aa68f15bbed28b218df1d0a62cbbcf1188c6665110c293c907b831",
+                672000000,
+            ),
+            (
+                "5120712447206d7a5238acc7ff53fbe94a3b64539ad291c7cdbc490b7577e4b17df5",
+                546000000,
+            ),
+            (
+                "512077e30a5522dd9f894c3f8b8bd4c4b2cf82ca7da8a3ea6a239655c39c050ab220",
+                588000000,
+            ),
+        ]
+        .into_iter()
+        .map(|(script, value)| TxOut {
+            value,
+            script_pubkey: hex::decode(script).unwrap().into(),
+        })
+        .collect();
+        let expected = [
+            (
+                0,
+                3,
+                "2514a6272f85cfa0f45eb907fcb0d121b808ed37c6ea160a5a9046ed5526d555",
+            ),
+            (
+                1,
+                131,
+                "325a644af47e8a5a2591cda0ab0723978537318f10e6a63d4eed783b96a71a4d",
+            ),
+            (
+                3,
+                1,
+                "bf013ea93474aa67815b1b6cc441d23b64fa310911d991e713cd34c7f5d46669",
+            ),
+            (
+                4,
+                0,
+                "4f900a0bae3f1446fd48490c2958b5a023228f01661cda3496a11da502a7f7ef",
+            ),
+            (
+                6,
+                2,
+                "15f25c298eb5cdc7eb1d638dd2d45c97c4c59dcaec6679cfc16ad84f30876b85",
+            ),
+            (
+                7,
+                130,
+                "cd292de50313804dabe4685e83f923d2969577191a3e1d2882220dca88cbeb10",
+            ),
+            (
+                8,
+                129,
+                "cccb739eca6c13a8a89e6e5cd317ffe55669bbda23f2fd37b0f18755e008edd2",
+            ),
+        ];
+        for (index, sighash_type, sighash) in expected.iter() {
+            let hash = taproot_signature_hash(&tx, *index, &prevouts, *sighash_type, None).unwrap();
+            assert_eq!(hex::encode(hash.into_inner()), *sighash);
+        }
+
+        assert!(taproot_signature_hash(&tx, 0, &prevouts, 0x80, None).is_err());
+        assert!(taproot_signature_hash(&tx, 0, &prevouts[1..], 0, None).is_err());
+
+        // script path, from rust-bitcoin test vectors
+        let tx: Transaction = deserialize(&hex::decode("020000000189fc651483f9296b906455dd939813bf086b1bbe7c77635e157c8e14ae29062195010000004445b5c7044561320000000000160014331414dbdada7fb578f700f38fb69995fc9b5ab958020000000000001976a914268db0a8104cc6d8afd91233cc8b3d1ace8ac3ef88ac580200000000000017a914ec00dcb368d6a693e11986d265f659d2f59e8be2875802000000000000160014c715799a49a0bae3956df9c17cb4440a673ac0df6f010000").unwrap()).unwrap();
+        let prevouts: Vec<TxOut> = deserialize(&hex::decode("011bec34000000000022512028055142ea437db73382e991861446040b61dd2185c4891d7daf6893d79f7182").unwrap()).unwrap();
+        let script: Script =
+            hex::decode("20cc4e1107aea1d170c5ff5b6817e1303010049724fb3caa7941792ea9d29b3e2bacab")
+                .unwrap()
+                .into();
+        let leaf_hash = tap_leaf_hash(&script);
+        let hash = taproot_signature_hash(&tx, 0, &prevouts, 1, Some(&leaf_hash)).unwrap();
+        assert_eq!(
+            hex::encode(hash.into_inner()),
+            "d66de5274a60400c7b08c86ba6b7f198f40660079edf53aca89d2a9501317f2e"
+        );
+    }
+
+    #[test]
+    fn test_tap_key_origins_roundtrip() {
+        let secp = Secp256k1::verification_only();
+        let desc = format!("tr({},multi_a(2,{},{}))", NUMS, K1, K2);
+        let derived = desc.parse::<TrMulti>().unwrap().derive(&secp, 3).unwrap();
+        let origins: Vec<KeySource> = ["a2ebe04e", "1f5e43d8"]
+            .iter()
+            .map(|f| {
+                let path = DerivationPath::from_str("m/48'/1'/0'/2'/0/3").unwrap();
+                (Fingerprint::from_str(f).unwrap(), path)
+            })
+            .collect();
+        let mut input = Input::default();
+        derived.fill_input(&mut input, &origins);
+
+        let result = tap_key_origins(&input.unknown, PSBT_IN_TAP_BIP32_DERIVATION).unwrap();
+        assert_eq!(result.len(), 2);
+        for (key, origin) in derived.keys.iter().zip(origins.iter()) {
+            assert_eq!(
+                result.get(key).unwrap(),
+                &(vec![derived.leaf_hash], origin.clone())
+            );
+        }
+        assert_eq!(tap_internal_key(&input).unwrap(), Some(nums()));
+        assert_eq!(tap_merkle_root(&input).unwrap(), &derived.leaf_hash[..]);
+        let scripts = tap_scripts(&input);
+        assert_eq!(
+            scripts.get(&derived.control_block()).unwrap(),
+            &(derived.leaf_script.clone(), TAPSCRIPT_LEAF_VERSION)
+        );
+        assert!(tap_script_sigs(&input).unwrap().is_empty());
+
+        // a truncated derivation path from an untrusted PSBT must not panic
+        for value in input.unknown.values_mut() {
+            value.pop();
+        }
+        assert!(tap_key_origins(&input.unknown, PSBT_IN_TAP_BIP32_DERIVATION).is_err());
+    }
+}
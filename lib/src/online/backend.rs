@@ -0,0 +1,343 @@
+use crate::offline::descriptor::node_can_derive;
+use crate::online::electrum::ElectrumBackend;
+use crate::*;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Address, Amount, OutPoint, Script, Transaction, Txid};
+use bitcoincore_rpc::bitcoincore_rpc_json::GetTransactionResultDetailCategory;
+use bitcoincore_rpc::{Client, RpcApi};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::str::FromStr;
+
+/// default blocks target of the fee estimation
+pub const FEE_TARGET: u16 = 6;
+
+/// fee rate in sat/vB used when the backend cannot estimate
+pub const FALLBACK_FEE_RATE: f64 = 1.0;
+
+/// minimum fee rate relayed by default by the nodes, in sat/vB
+pub const MIN_RELAY_FEE_RATE: f64 = 1.0;
+
+/// The source of chain data used by the online commands
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// bitcoin core RPC, the wallet is a watch-only wallet in the node
+    Bitcoind,
+    /// electrum protocol server, the wallet scripts are derived locally
+    Electrum,
+}
+
+// `#[default]` on enum variants requires rust 1.62
+#[allow(clippy::derivable_impls)]
+impl Default for BackendKind {
+    fn default() -> Self {
+        BackendKind::Bitcoind
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "bitcoind" => Ok(BackendKind::Bitcoind),
+            "electrum" => Ok(BackendKind::Electrum),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("({}) valid values are: bitcoind, electrum", s),
+            )),
+        }
+    }
+}
+
+/// An unspent output of the wallet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub amount: Amount,
+    pub script_pubkey: Script,
+    pub confirmations: u32,
+    /// chain and index of the script in the wallet descriptor, when known by the backend
+    pub derivation: Option<(u32, u32)>,
+}
+
+/// A transaction of the wallet with its effect on the wallet balance
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalletTx {
+    pub txid: Txid,
+    /// received minus spent by the wallet, including the fee if paid by the wallet
+    pub net: i64,
+    /// known if all the inputs are of the wallet
+    pub fee: Option<u64>,
+    pub confirmations: u32,
+    pub height: Option<u32>,
+    /// timestamp of the block
+    pub time: Option<u64>,
+}
+
+/// Chain operations needed by the online commands of a wallet
+pub trait ChainBackend {
+    /// confirmed and pending balance of the wallet
+    fn balance(&self) -> Result<BalanceOutput>;
+
+    /// unspent outputs of the wallet with at least `min_conf` confirmations
+    fn list_unspent(&self, min_conf: u32) -> Result<Vec<Utxo>>;
+
+    /// the wallet transaction with id `txid`
+    fn transaction(&self, txid: &Txid) -> Result<Transaction>;
+
+    /// fee paid by the unconfirmed transaction `tx`, computed from its previous outputs
+    fn unconfirmed_fee(&self, tx: &Transaction) -> Result<u64> {
+        let mut input_value = 0u64;
+        for input in tx.input.iter() {
+            let outpoint = input.previous_output;
+            let prev_tx = self.transaction(&outpoint.txid)?;
+            input_value += prev_tx
+                .output
+                .get(outpoint.vout as usize)
+                .ok_or_else(|| Error::Generic(format!("missing prevout {}", outpoint)))?
+                .value;
+        }
+        let output_value: u64 = tx.output.iter().map(|o| o.value).sum();
+        Ok(input_value.saturating_sub(output_value))
+    }
+
+    /// estimated fee rate in sat/vB to confirm within `conf_target` blocks
+    fn fee_rate(&self, conf_target: u16) -> Result<f64>;
+
+    /// all the transactions of the wallet, in no particular order
+    fn history(&self) -> Result<Vec<WalletTx>>;
+
+    /// for every script in `scripts`, true if it received funds
+    fn received(&self, scripts: &[Script]) -> Result<Vec<bool>>;
+
+    /// true if the wallet already sent to `address`, receiving on it doesn't count
+    fn is_address_reused(&self, address: &Address) -> Result<bool>;
+
+    /// true if payments to the wallet `address` are detected by the backend
+    fn is_watched(&self, _address: &Address) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// combine the signed `psbts` and extract the final transaction
+    fn finalize(&self, psbts: &[String]) -> Result<Transaction> {
+        finalize_psbts(psbts)
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid>;
+
+    /// scan the chain for wallet transactions from the `start_from` height, returns the last
+    /// height scanned
+    fn rescan(&self, start_from: usize) -> Result<usize>;
+
+    /// the address at `index` of `descriptor` derived by the backend, if able to, it's used to
+    /// cross-check the locally derived one
+    fn derive_address(&self, _descriptor: &str, _index: u32) -> Result<Option<Address>> {
+        Ok(None)
+    }
+}
+
+/// chain and index of the first key of the descriptor `desc` returned by the node for a wallet
+/// output, like `wsh(multi(2,[a2ebe04e/48'/1'/0'/2'/0/5]03..,..))`
+pub fn origin_derivation(desc: &str) -> Option<(u32, u32)> {
+    let start = desc.find('[')?;
+    let end = start + desc[start..].find(']')?;
+    let mut steps = desc[start + 1..end].rsplit('/');
+    let index = steps.next()?.parse().ok()?;
+    let chain = steps.next()?.parse().ok()?;
+    Some((chain, index))
+}
+
+/// combine and finalize `psbts` without a node, wallet inputs must be miniscript
+pub fn finalize_psbts(psbts: &[String]) -> Result<Transaction> {
+    let mut iter = psbts.iter();
+    let (_, mut combined) = psbt_from_base64(iter.next().ok_or(Error::NeedAtLeastOne)?)?;
+    for psbt in iter {
+        combined.merge(psbt_from_base64(psbt)?.1)?;
+    }
+    let secp = Secp256k1::verification_only();
+    miniscript::psbt::finalize(&mut combined, &secp)?;
+    Ok(miniscript::psbt::extract(&combined, &secp)?)
+}
+
+impl ChainBackend for Client {
+    fn balance(&self) -> Result<BalanceOutput> {
+        let balances: Balances = self.call("getbalances", &[])?;
+        let balance = balances.watchonly.unwrap_or(balances.mine);
+        let am = balance.immature + balance.untrusted_pending;
+        let pending = match am.as_sat() {
+            0 => None,
+            _ => Some(am.into()),
+        };
+        let confirmed: BalanceSatBtc = balance.trusted.into();
+        Ok(BalanceOutput { confirmed, pending })
+    }
+
+    fn list_unspent(&self, min_conf: u32) -> Result<Vec<Utxo>> {
+        let list = RpcApi::list_unspent(self, Some(min_conf as usize), None, None, None, None)?;
+        Ok(list
+            .iter()
+            .map(|utxo| Utxo {
+                outpoint: OutPoint::new(utxo.txid, utxo.vout),
+                amount: utxo.amount,
+                script_pubkey: utxo.script_pub_key.clone(),
+                confirmations: utxo.confirmations,
+                derivation: utxo.descriptor.as_deref().and_then(origin_derivation),
+            })
+            .collect())
+    }
+
+    fn transaction(&self, txid: &Txid) -> Result<Transaction> {
+        Ok(self.get_transaction(txid, Some(true))?.transaction()?)
+    }
+
+    fn unconfirmed_fee(&self, tx: &Transaction) -> Result<u64> {
+        // previous transactions of incoming payments aren't in the wallet, the mempool knows
+        Ok(self.get_mempool_entry(&tx.txid())?.fees.base.as_sat())
+    }
+
+    fn fee_rate(&self, conf_target: u16) -> Result<f64> {
+        let estimate = self.estimate_smart_fee(conf_target, None)?;
+        match estimate.fee_rate {
+            Some(btc_per_kvb) => Ok(btc_per_kvb.as_sat() as f64 / 1000.0),
+            None => {
+                info!(
+                    "node cannot estimate fee, using {} sat/vB",
+                    FALLBACK_FEE_RATE
+                );
+                Ok(FALLBACK_FEE_RATE)
+            }
+        }
+    }
+
+    fn history(&self) -> Result<Vec<WalletTx>> {
+        let list = self.list_since_block(None, None, Some(true), None)?;
+        let mut txs: Vec<WalletTx> = vec![];
+        for entry in list.transactions {
+            if entry.info.confirmations < 0 {
+                // conflicted
+                continue;
+            }
+            // an entry for every output, merged in one per transaction
+            let position = match txs.iter().position(|tx| tx.txid == entry.info.txid) {
+                Some(position) => position,
+                None => {
+                    txs.push(WalletTx {
+                        txid: entry.info.txid,
+                        net: 0,
+                        fee: None,
+                        confirmations: entry.info.confirmations as u32,
+                        height: entry.info.blockheight,
+                        time: entry.info.blocktime,
+                    });
+                    txs.len() - 1
+                }
+            };
+            let tx = &mut txs[position];
+            tx.net += entry.detail.amount.as_sat();
+            if let Some(fee) = entry.detail.fee {
+                tx.fee = Some(fee.as_sat().unsigned_abs());
+            }
+        }
+        for tx in txs.iter_mut() {
+            // amounts sent don't include the fee
+            tx.net -= tx.fee.unwrap_or(0) as i64;
+        }
+        Ok(txs)
+    }
+
+    fn received(&self, scripts: &[Script]) -> Result<Vec<bool>> {
+        let list = self.list_received_by_address(None, Some(0), Some(false), Some(true))?;
+        let received: Vec<Script> = list.iter().map(|r| r.address.script_pubkey()).collect();
+        Ok(scripts.iter().map(|s| received.contains(s)).collect())
+    }
+
+    fn is_watched(&self, address: &Address) -> Result<bool> {
+        let info = self.get_address_info(address)?;
+        Ok(info.is_mine.unwrap_or(false) || info.is_watchonly.unwrap_or(false))
+    }
+
+    fn is_address_reused(&self, address: &Address) -> Result<bool> {
+        let transactions = self
+            .list_since_block(None, None, Some(true), None)?
+            .transactions;
+        Ok(transactions.iter().any(|tx| {
+            tx.detail.address.as_ref() == Some(address)
+                && tx.detail.category == GetTransactionResultDetailCategory::Send
+        }))
+    }
+
+    fn finalize(&self, psbts: &[String]) -> Result<Transaction> {
+        let combined = self.combine_psbt(psbts)?;
+        debug!("combined {:?}", combined);
+
+        let finalized = self.finalize_psbt(&combined, Some(true))?;
+        debug!("finalized {:?}", finalized);
+
+        let bytes = finalized.hex.ok_or(Error::MissingHex)?;
+        Ok(bitcoin::consensus::deserialize(&bytes)?)
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        Ok(self.send_raw_transaction(tx)?)
+    }
+
+    fn rescan(&self, start_from: usize) -> Result<usize> {
+        let (_a, b) = self.rescan_blockchain(Some(start_from), None)?;
+        b.ok_or(Error::MissingRescanUpTo)
+    }
+
+    fn derive_address(&self, descriptor: &str, index: u32) -> Result<Option<Address>> {
+        // the node cannot derive taproot or miniscript descriptors, skipping the cross-check
+        if !node_can_derive(descriptor) {
+            return Ok(None);
+        }
+        let addresses = self.derive_addresses(descriptor, Some([index, index]))?;
+        Ok(Some(
+            addresses.first().ok_or(Error::MissingAddress)?.clone(),
+        ))
+    }
+}
+
+impl OnlineContext {
+    /// the chain backend of the connected daemon for the wallet `wallet_name`
+    pub fn make_backend(&self, wallet_name: &str) -> Result<Box<dyn ChainBackend>> {
+        let opts = self.read_daemon_opts()?;
+        match opts.backend {
+            BackendKind::Bitcoind => Ok(Box::new(self.make_client(wallet_name)?)),
+            BackendKind::Electrum => {
+                let wallet: Wallet = self.read(wallet_name)?;
+                let indexes: WalletIndexes = self.read(wallet_name)?;
+                Ok(Box::new(ElectrumBackend::new(
+                    &opts,
+                    self.network,
+                    wallet,
+                    indexes,
+                )?))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::online::backend::origin_derivation;
+
+    #[test]
+    fn test_origin_derivation() {
+        let desc = "wsh(multi(2,[a2ebe04e/48'/1'/0'/2'/1/1500]02e4f6b3c1b1d3e8e1f9c0b5e0f3d7a8a1b6c2d9e0f1a2b3c4d5e6f7a8b9c0d1e2,[1f5e43d8/48h/1h/0h/2h/1/1500]03b1))#abcdefgh";
+        assert_eq!(origin_derivation(desc), Some((1, 1500)));
+        let desc = "wpkh([a2ebe04e/84'/1'/0'/0/7]02e4)";
+        assert_eq!(origin_derivation(desc), Some((0, 7)));
+        assert_eq!(
+            origin_derivation(
+                "addr(tb1q5nrregep899vnvaa5vdpxcwg8794jqy38nu304kl4d7wm4e92yeqz4jfmk)"
+            ),
+            None
+        );
+        assert_eq!(origin_derivation("wpkh([a2ebe04e]02e4)"), None);
+        assert_eq!(origin_derivation("wpkh([a2ebe04e/0'/1']02e4)"), None);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::online::backend::{Utxo, MIN_RELAY_FEE_RATE};
+use crate::online::fund_psbt::{
+    fill_input, fill_non_witness_utxos, fill_output, wallet_coins, wallet_script, wallet_scripts,
+    SEQUENCE_RBF,
+};
+use crate::*;
+use bitcoin::{Amount, Script, Transaction, TxOut, Txid};
+use log::debug;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct BumpFeeOptions {
+    /// The name of the wallet which created the transaction
+    #[structopt(long = "wallet-name")]
+    pub wallet_name: String,
+
+    /// The id of the unconfirmed transaction to replace
+    #[structopt(long)]
+    pub txid: Txid,
+
+    /// Fee rate of the replacement in satoshi per virtual byte
+    #[structopt(long)]
+    pub fee_rate: f64,
+}
+
+/// build the PSBT replacing `tx`, spending the same inputs at `fee_rate`, the fee increase is
+/// taken from the change output. `prevouts` are the outputs spent by the inputs of `tx`,
+/// `original` is the stored PSBT which created `tx`, if any
+pub fn bump_fee_psbt(
+    wallet: &Wallet,
+    indexes: &WalletIndexes,
+    tx: &Transaction,
+    prevouts: &[TxOut],
+    original: Option<&BitcoinPsbt>,
+    fee_rate: f64,
+) -> Result<BitcoinPsbt> {
+    let txid = tx.txid();
+    if !tx.input.iter().any(|input| input.sequence < 0xFFFF_FFFE) {
+        return Err(format!("transaction {} doesn't signal replaceability", txid).into());
+    }
+    let utxos: Vec<Utxo> = tx
+        .input
+        .iter()
+        .zip(prevouts)
+        .map(|(input, prevout)| Utxo {
+            outpoint: input.previous_output,
+            amount: Amount::from_sat(prevout.value),
+            script_pubkey: prevout.script_pubkey.clone(),
+            confirmations: 0,
+            derivation: None,
+        })
+        .collect();
+    let coins = wallet_coins(wallet, indexes, &utxos)?;
+    if coins.len() != tx.input.len() {
+        return Err(format!("transaction {} spends coins not of the wallet", txid).into());
+    }
+    let scripts = wallet_scripts(wallet, indexes, wallet.id.network)?;
+    let is_change = |script: &Script| {
+        scripts
+            .iter()
+            .find(|(s, chain, _)| *chain == 1 && s == script)
+            .map(|(_, chain, index)| (*chain, *index))
+    };
+    let change = match tx
+        .output
+        .iter()
+        .enumerate()
+        .find_map(|(vout, output)| is_change(&output.script_pubkey).map(|c| (vout, c)))
+    {
+        Some(change) => Some(change),
+        // when the node doesn't watch the change chain, the change is on the receive chain and
+        // it's recognized by the derivation filled in the PSBT which created the transaction
+        None => match original {
+            Some(original) => stored_change(wallet, tx, original)?,
+            None => None,
+        },
+    };
+    let (vout, (change_chain, change_index)) = change
+        .ok_or_else(|| Error::Generic(format!("transaction {} has no change output", txid)))?;
+
+    let mut replacement = tx.clone();
+    for input in replacement.input.iter_mut() {
+        input.script_sig = Script::new();
+        input.witness = vec![];
+        input.sequence = SEQUENCE_RBF;
+    }
+    let mut psbt = BitcoinPsbt::from_unsigned_tx(replacement)?;
+    for (input, coin) in psbt.inputs.iter_mut().zip(coins.iter()) {
+        fill_input(wallet, coin, input)?;
+    }
+    fill_output(wallet, change_chain, change_index, &mut psbt.outputs[vout])?;
+    set_psbt_replaces(&mut psbt, &txid);
+
+    // the original transaction is signed, its weight is the one of the replacement
+    let weight = estimate_weight(&psbt).unwrap_or_else(|_| tx.get_weight());
+    let vsize = ((weight + 3) / 4) as f64;
+    let input_value: u64 = prevouts.iter().map(|o| o.value).sum();
+    let output_value: u64 = tx.output.iter().map(|o| o.value).sum();
+    let original_fee = input_value.saturating_sub(output_value);
+    // BIP125 requires the replacement to pay for its own relay on top of the original fee
+    let min_fee = original_fee + (vsize * MIN_RELAY_FEE_RATE).ceil() as u64;
+    let fee = ((vsize * fee_rate).ceil() as u64).max(min_fee);
+    debug!("original fee {} new fee {}", original_fee, fee);
+
+    let change = &mut psbt.global.unsigned_tx.output[vout];
+    let dust = change.script_pubkey.dust_value().as_sat();
+    let increase = fee - original_fee;
+    change.value = change
+        .value
+        .checked_sub(increase)
+        .filter(|value| *value > dust)
+        .ok_or(Error::InsufficientFunds {
+            available: change.value,
+            needed: increase + dust,
+        })?;
+    Ok(psbt)
+}
+
+/// the output of `tx` with its chain and index, which the stored PSBT `original` marks as change
+fn stored_change(
+    wallet: &Wallet,
+    tx: &Transaction,
+    original: &BitcoinPsbt,
+) -> Result<Option<(usize, (u32, u32))>> {
+    for (vout, (output, psbt_output)) in tx.output.iter().zip(original.outputs.iter()).enumerate() {
+        let path = match psbt_output.bip32_derivation.values().next() {
+            Some((_, path)) => path,
+            None => continue,
+        };
+        let mut steps = path.as_ref().iter().rev().map(|c| u32::from(*c));
+        if let (Some(index), Some(chain)) = (steps.next(), steps.next()) {
+            if chain < 2 && wallet_script(wallet, chain, index)? == output.script_pubkey {
+                return Ok(Some((vout, (chain, index))));
+            }
+        }
+    }
+    Ok(None)
+}
+
+impl OnlineContext {
+    pub fn bump_fee(&self, opt: &BumpFeeOptions) -> Result<BumpFeeOutput> {
+        if opt.fee_rate < MIN_RELAY_FEE_RATE {
+            return Err(format!(
+                "fee rate must be at least {} sat/vB (--fee-rate)",
+                MIN_RELAY_FEE_RATE
+            )
+            .into());
+        }
+        let backend = self.make_backend(&opt.wallet_name)?;
+        let wallet: Wallet = self.read(&opt.wallet_name)?;
+        let indexes: WalletIndexes = self.read(&opt.wallet_name)?;
+
+        let confirmed = backend
+            .history()?
+            .iter()
+            .any(|tx| tx.txid == opt.txid && tx.confirmations > 0);
+        if confirmed {
+            return Err(format!("transaction {} is already confirmed", opt.txid).into());
+        }
+
+        let tx = backend.transaction(&opt.txid)?;
+        let prevouts = tx
+            .input
+            .iter()
+            .map(|input| {
+                let outpoint = input.previous_output;
+                backend
+                    .transaction(&outpoint.txid)?
+                    .output
+                    .get(outpoint.vout as usize)
+                    .cloned()
+                    .ok_or_else(|| Error::Generic(format!("missing prevout {}", outpoint)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let original = self.stored_psbt(&opt.txid)?;
+        let mut psbt = bump_fee_psbt(
+            &wallet,
+            &indexes,
+            &tx,
+            &prevouts,
+            original.as_ref(),
+            opt.fee_rate,
+        )?;
+        fill_non_witness_utxos(&mut psbt, backend.as_ref())?;
+
+        let psbt_name = self.psbt_name_after(&opt.txid, "bump")?;
+        set_psbt_name(&mut psbt, &psbt_name);
+        let psbt_name = self.save_psbt(&mut psbt)?;
+
+        let input_value: u64 = prevouts.iter().map(|o| o.value).sum();
+        let output_value = |tx: &Transaction| tx.output.iter().map(|o| o.value).sum::<u64>();
+        Ok(BumpFeeOutput {
+            funded_psbt: (&psbt, self.network).into(),
+            psbt_name,
+            replaced_txid: opt.txid,
+            original_fee: input_value - output_value(&tx),
+            fee: input_value - output_value(&psbt.global.unsigned_tx),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::offline::print::pretty_print;
+    use crate::online::bump_fee::bump_fee_psbt;
+    use crate::online::create_wallet::DEFAULT_IMPORT_RANGE;
+    use crate::online::fund_psbt::tests::{coin, DESCRIPTOR};
+    use crate::online::fund_psbt::{fund_psbt, FundRequest};
+    use crate::{get_psbt_replaces, Error, Identifier, Kind, Wallet, WalletIndexes};
+    use bitcoin::Network;
+
+    #[test]
+    fn test_bump_fee() {
+        let wallet = Wallet::new(DESCRIPTOR, Network::Testnet);
+        let indexes = WalletIndexes {
+            id: Identifier::new(Network::Testnet, Kind::WalletIndexes, "bump"),
+            main: 10,
+            change: 1,
+            imported: DEFAULT_IMPORT_RANGE,
+        };
+        let coins = vec![coin(&wallet, 0, 10_000, 0), coin(&wallet, 1, 50_000, 1)];
+        let mut req = FundRequest {
+            wallet: &wallet,
+            coins: &coins,
+            selected: &[],
+            outputs: vec![coin(&wallet, 9, 40_000, 9).txout],
+            change_chain: 1,
+            change_index: 0,
+            fee_rate: 1.0,
+            replaceable: false,
+            subtract_fee_from: None,
+        };
+        let prevouts = vec![coins[1].txout.clone()];
+        let tx = fund_psbt(&req).unwrap().global.unsigned_tx;
+        assert_matches!(
+            bump_fee_psbt(&wallet, &indexes, &tx, &prevouts, None, 5.0),
+            Err(Error::Generic(_))
+        );
+
+        req.replaceable = true;
+        let tx = fund_psbt(&req).unwrap().global.unsigned_tx;
+        let psbt = bump_fee_psbt(&wallet, &indexes, &tx, &prevouts, None, 5.0).unwrap();
+        let replacement = &psbt.global.unsigned_tx;
+        assert_eq!(replacement.input, tx.input);
+        assert_eq!(replacement.output[0], tx.output[0]);
+        assert!(replacement.output[1].value < tx.output[1].value);
+        assert_eq!(get_psbt_replaces(&psbt), Some(tx.txid()));
+        assert_eq!(psbt.outputs[1].bip32_derivation.len(), 2);
+        let print = pretty_print(&psbt, Network::Testnet, &[]).unwrap();
+        assert_eq!(print.replaces, Some(tx.txid()));
+        assert_eq!(print.fee.rate, Some(5.0));
+
+        // the replacement pays at least the original fee plus its relay fee
+        let psbt = bump_fee_psbt(&wallet, &indexes, &tx, &prevouts, None, 1.0).unwrap();
+        let print = pretty_print(&psbt, Network::Testnet, &[]).unwrap();
+        assert_eq!(print.fee.rate, Some(2.0));
+
+        assert_matches!(
+            bump_fee_psbt(&wallet, &indexes, &tx, &prevouts, None, 1000.0),
+            Err(Error::InsufficientFunds { .. })
+        );
+    }
+
+    #[test]
+    fn test_bump_fee_change_on_receive_chain() {
+        // wallets whose change chain isn't watched by the node send the change to the receive
+        // chain
+        let wallet = Wallet::new(DESCRIPTOR, Network::Testnet);
+        let indexes = WalletIndexes {
+            id: Identifier::new(Network::Testnet, Kind::WalletIndexes, "bump-receive"),
+            main: 12,
+            change: 0,
+            imported: DEFAULT_IMPORT_RANGE,
+        };
+        let coins = vec![coin(&wallet, 0, 50_000, 1)];
+        let req = FundRequest {
+            wallet: &wallet,
+            coins: &coins,
+            selected: &[],
+            outputs: vec![coin(&wallet, 9, 40_000, 9).txout],
+            change_chain: 0,
+            change_index: 11,
+            fee_rate: 1.0,
+            replaceable: true,
+            subtract_fee_from: None,
+        };
+        let prevouts = vec![coins[0].txout.clone()];
+        let original = fund_psbt(&req).unwrap();
+        let tx = original.global.unsigned_tx.clone();
+        assert_matches!(
+            bump_fee_psbt(&wallet, &indexes, &tx, &prevouts, None, 5.0),
+            Err(Error::Generic(_))
+        );
+
+        let psbt = bump_fee_psbt(&wallet, &indexes, &tx, &prevouts, Some(&original), 5.0).unwrap();
+        let replacement = &psbt.global.unsigned_tx;
+        assert_eq!(replacement.output[0], tx.output[0]);
+        assert!(replacement.output[1].value < tx.output[1].value);
+        assert_eq!(
+            psbt.outputs[1].bip32_derivation,
+            original.outputs[1].bip32_derivation
+        );
+        assert!(psbt.outputs[0].bip32_derivation.is_empty());
+    }
+}
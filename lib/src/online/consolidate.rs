@@ -0,0 +1,168 @@
+use crate::online::backend::{Utxo, FEE_TARGET, MIN_RELAY_FEE_RATE};
+use crate::online::create_tx::CreateTxOptions;
+use crate::*;
+use bitcoin::{Address, Amount, OutPoint};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ConsolidateOptions {
+    /// The name of the wallet to consolidate
+    #[structopt(long = "wallet-name")]
+    pub wallet_name: String,
+
+    /// Don't consolidate if the estimated fee rate in sat/vB is higher
+    #[structopt(long)]
+    pub max_fee_rate: f64,
+
+    /// Don't consolidate less than this number of coins
+    #[structopt(long, default_value = "2")]
+    pub min_inputs: usize,
+
+    /// Only coins with an amount in satoshi up to this are consolidated, larger ones are left
+    /// alone. If not specified every confirmed coin is consolidated
+    #[structopt(long)]
+    pub max_amount: Option<u64>,
+
+    /// Name of the PSBT
+    #[structopt(short, long)]
+    pub psbt_name: String,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct SweepOptions {
+    /// The name of the wallet to sweep
+    #[structopt(long = "wallet-name")]
+    pub wallet_name: String,
+
+    /// The address receiving the whole confirmed balance
+    #[structopt(long)]
+    pub address: Address,
+
+    /// Fee rate in satoshi per virtual byte, if not specified it is estimated by the backend
+    #[structopt(long)]
+    pub fee_rate: Option<f64>,
+
+    /// Name of the PSBT
+    #[structopt(short, long)]
+    pub psbt_name: String,
+}
+
+/// the confirmed coins to consolidate with amount up to `max_amount` if given, smallest first,
+/// at least `min_inputs`
+fn consolidation_coins(
+    utxos: &[Utxo],
+    min_inputs: usize,
+    max_amount: Option<Amount>,
+) -> Result<Vec<OutPoint>> {
+    let mut utxos: Vec<_> = utxos
+        .iter()
+        .filter(|u| u.confirmations > 0 && u.amount <= max_amount.unwrap_or(u.amount))
+        .collect();
+    if utxos.len() < min_inputs.max(2) {
+        return Err(format!(
+            "{} confirmed coins (up to --max-amount if given), at least {} needed to consolidate (--min-inputs)",
+            utxos.len(),
+            min_inputs.max(2)
+        )
+        .into());
+    }
+    utxos.sort_by_key(|u| u.amount);
+    Ok(utxos.iter().map(|u| u.outpoint).collect())
+}
+
+impl OnlineContext {
+    /// gather the confirmed coins of the wallet, not frozen, in one output to a new wallet address
+    pub fn consolidate(&self, opt: &ConsolidateOptions) -> Result<CreateTxOutput> {
+        let backend = self.make_backend(&opt.wallet_name)?;
+        let fee_rate = backend.fee_rate(FEE_TARGET)?.max(MIN_RELAY_FEE_RATE);
+        if fee_rate > opt.max_fee_rate {
+            return Err(format!(
+                "estimated fee rate {} sat/vB is higher than {} (--max-fee-rate)",
+                fee_rate, opt.max_fee_rate
+            )
+            .into());
+        }
+        let labels = self.read_labels(&opt.wallet_name)?;
+        let mut utxos = backend.list_unspent(1)?;
+        utxos.retain(|u| !labels.is_frozen(&u.outpoint));
+        let max_amount = opt.max_amount.map(Amount::from_sat);
+        let coins = consolidation_coins(&utxos, opt.min_inputs, max_amount)?;
+
+        // the change chain is used so that receiving addresses are only the given out ones
+        let address = self
+            .change_address(&opt.wallet_name, backend.as_ref())?
+            .1
+            .address;
+
+        let create_tx_opts = CreateTxOptions {
+            wallet_name: opt.wallet_name.to_string(),
+            recipients: vec![],
+            coins,
+            psbt_name: opt.psbt_name.to_string(),
+            fee_rate: Some(fee_rate),
+            conf_target: None,
+            replaceable: true,
+            subtract_fee_from: None,
+            send_all: Some(address),
+        };
+        self.create_tx(&create_tx_opts)
+    }
+
+    /// send the whole confirmed balance of the wallet to an address
+    pub fn sweep(&self, opt: &SweepOptions) -> Result<CreateTxOutput> {
+        let create_tx_opts = CreateTxOptions {
+            wallet_name: opt.wallet_name.to_string(),
+            recipients: vec![],
+            coins: vec![],
+            psbt_name: opt.psbt_name.to_string(),
+            fee_rate: opt.fee_rate,
+            conf_target: None,
+            replaceable: true,
+            subtract_fee_from: None,
+            send_all: Some(opt.address.clone()),
+        };
+        self.create_tx(&create_tx_opts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::online::backend::Utxo;
+    use crate::online::consolidate::consolidation_coins;
+    use bitcoin::{Amount, OutPoint, Script, Txid};
+
+    #[test]
+    fn test_consolidation_coins() {
+        let utxo = |vout, amount, confirmations| Utxo {
+            outpoint: OutPoint::new(Txid::default(), vout),
+            amount: Amount::from_sat(amount),
+            script_pubkey: Script::new(),
+            confirmations,
+            derivation: None,
+        };
+        let max = Some(Amount::from_sat(10_000));
+        let utxos = vec![utxo(0, 5_000, 1), utxo(1, 1_000, 3), utxo(2, 700, 0)];
+        let coins = consolidation_coins(&utxos, 2, max).unwrap();
+        assert_eq!(coins, vec![utxos[1].outpoint, utxos[0].outpoint]);
+        assert!(consolidation_coins(&utxos, 3, max).is_err());
+        assert!(consolidation_coins(&utxos[..1], 0, max).is_err());
+
+        let utxos = vec![
+            utxo(0, 5_000, 1),
+            utxo(1, 1_000_000, 3),
+            utxo(2, 10_000, 2),
+            utxo(3, 700, 1),
+        ];
+        let coins = consolidation_coins(&utxos, 2, max).unwrap();
+        assert_eq!(
+            coins,
+            vec![utxos[3].outpoint, utxos[0].outpoint, utxos[2].outpoint],
+            "large coin selected"
+        );
+        assert!(consolidation_coins(&utxos, 2, Some(Amount::from_sat(1_000))).is_err());
+
+        let coins = consolidation_coins(&utxos, 4, None).unwrap();
+        assert_eq!(coins.len(), 4, "every confirmed coin without --max-amount");
+        assert_eq!(coins[3], utxos[1].outpoint);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::online::backend::{Utxo, MIN_RELAY_FEE_RATE};
+use crate::online::fund_psbt::{
+    fill_non_witness_utxos, fund_psbt, wallet_coins, FundRequest, WalletCoin,
+};
+use crate::*;
+use bitcoin::{OutPoint, Txid};
+use log::debug;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct CpfpOptions {
+    /// The name of the wallet receiving outputs of the transaction
+    #[structopt(long = "wallet-name")]
+    pub wallet_name: String,
+
+    /// The id of the unconfirmed transaction to accelerate
+    #[structopt(long)]
+    pub txid: Txid,
+
+    /// Fee rate of the package, parent and child, in satoshi per virtual byte
+    #[structopt(long)]
+    pub fee_rate: f64,
+}
+
+/// the fee of the child PSBT built by `req`, which must have an output
+fn fund_child(req: &FundRequest, total: u64) -> Result<(BitcoinPsbt, u64)> {
+    let psbt = fund_psbt(req)?;
+    let output_value: u64 = psbt.global.unsigned_tx.output.iter().map(|o| o.value).sum();
+    if output_value == 0 {
+        // the whole value would be spent in fee
+        return Err(Error::InsufficientFunds {
+            available: total,
+            needed: total + 1,
+        });
+    }
+    Ok((psbt, total - output_value))
+}
+
+/// build the PSBT spending `coins`, outputs of the unconfirmed parent, to the change address at
+/// `change` (chain, index) so that parent and child together pay `fee_rate`, returns also the
+/// child vsize
+pub fn cpfp_psbt(
+    wallet: &Wallet,
+    coins: &[WalletCoin],
+    change: (u32, u32),
+    parent_vsize: u64,
+    parent_fee: u64,
+    fee_rate: f64,
+) -> Result<(BitcoinPsbt, u64)> {
+    let selected: Vec<OutPoint> = coins.iter().map(|c| c.outpoint).collect();
+    let total: u64 = coins.iter().map(|c| c.txout.value).sum();
+    let mut req = FundRequest {
+        wallet,
+        coins,
+        selected: &selected,
+        outputs: vec![],
+        change_chain: change.0,
+        change_index: change.1,
+        fee_rate: 1.0,
+        replaceable: false,
+        subtract_fee_from: None,
+    };
+    // at 1 sat/vB the fee is the child vsize
+    let (_, child_vsize) = fund_child(&req, total)?;
+
+    let package_fee = ((parent_vsize + child_vsize) as f64 * fee_rate).ceil() as u64;
+    let min_fee = (child_vsize as f64 * MIN_RELAY_FEE_RATE).ceil() as u64;
+    let child_fee = package_fee.saturating_sub(parent_fee).max(min_fee);
+    debug!(
+        "parent vsize {} fee {}, child vsize {} fee {}",
+        parent_vsize, parent_fee, child_vsize, child_fee
+    );
+    req.fee_rate = child_fee as f64 / child_vsize as f64;
+    let (psbt, _) = fund_child(&req, total)?;
+    Ok((psbt, child_vsize))
+}
+
+impl OnlineContext {
+    pub fn cpfp(&self, opt: &CpfpOptions) -> Result<CpfpOutput> {
+        if opt.fee_rate < MIN_RELAY_FEE_RATE {
+            return Err(format!(
+                "fee rate must be at least {} sat/vB (--fee-rate)",
+                MIN_RELAY_FEE_RATE
+            )
+            .into());
+        }
+        let backend = self.make_backend(&opt.wallet_name)?;
+        let utxos: Vec<Utxo> = backend
+            .list_unspent(0)?
+            .into_iter()
+            .filter(|utxo| utxo.outpoint.txid == opt.txid)
+            .collect();
+        if utxos.is_empty() {
+            return Err(format!("no unspent outputs of the wallet in {}", opt.txid).into());
+        }
+        if utxos.iter().any(|utxo| utxo.confirmations > 0) {
+            return Err(format!("transaction {} is already confirmed", opt.txid).into());
+        }
+
+        let (change_chain, change) = self.change_address(&opt.wallet_name, backend.as_ref())?;
+        let change_index = change.path.as_ref().last().map_or(0, |c| u32::from(*c));
+        let wallet: Wallet = self.read(&opt.wallet_name)?;
+        let indexes: WalletIndexes = self.read(&opt.wallet_name)?;
+        let coins = wallet_coins(&wallet, &indexes, &utxos)?;
+
+        let parent = backend.transaction(&opt.txid)?;
+        let parent_vsize = ((parent.get_weight() + 3) / 4) as u64;
+        let parent_fee = backend.unconfirmed_fee(&parent)?;
+
+        let (mut psbt, child_vsize) = cpfp_psbt(
+            &wallet,
+            &coins,
+            (change_chain, change_index),
+            parent_vsize,
+            parent_fee,
+            opt.fee_rate,
+        )?;
+        fill_non_witness_utxos(&mut psbt, backend.as_ref())?;
+
+        let psbt_name = self.psbt_name_after(&opt.txid, "cpfp")?;
+        set_psbt_name(&mut psbt, &psbt_name);
+        let psbt_name = self.save_psbt(&mut psbt)?;
+
+        let total: u64 = coins.iter().map(|c| c.txout.value).sum();
+        let output_value: u64 = psbt.global.unsigned_tx.output.iter().map(|o| o.value).sum();
+        let child_fee = total - output_value;
+        let package_fee_rate =
+            (parent_fee + child_fee) as f64 / (parent_vsize + child_vsize) as f64;
+        Ok(CpfpOutput {
+            psbt_name,
+            funded_psbt: (&psbt, self.network).into(),
+            parent_txid: opt.txid,
+            parent_vsize,
+            parent_fee,
+            child_vsize,
+            child_fee,
+            package_fee_rate,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::online::cpfp::cpfp_psbt;
+    use crate::online::fund_psbt::tests::{coin, DESCRIPTOR};
+    use crate::{Error, Wallet};
+    use bitcoin::Network;
+
+    #[test]
+    fn test_cpfp() {
+        let wallet = Wallet::new(DESCRIPTOR, Network::Testnet);
+        let coins = vec![coin(&wallet, 1, 20_000, 3)];
+        let (psbt, child_vsize) = cpfp_psbt(&wallet, &coins, (1, 0), 200, 200, 5.0).unwrap();
+        let tx = &psbt.global.unsigned_tx;
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(psbt.outputs[0].bip32_derivation.len(), 2);
+        let child_fee = 20_000 - tx.output[0].value;
+        let package_rate = (200 + child_fee) as f64 / (200 + child_vsize) as f64;
+        assert!((5.0..5.1).contains(&package_rate), "{}", package_rate);
+
+        // parent paying more than the target, the child pays its own relay fee
+        let (psbt, child_vsize) = cpfp_psbt(&wallet, &coins, (1, 0), 200, 10_000, 5.0).unwrap();
+        let child_fee = 20_000 - psbt.global.unsigned_tx.output[0].value;
+        assert_eq!(child_fee, child_vsize);
+
+        assert_matches!(
+            cpfp_psbt(&wallet, &coins, (1, 0), 200, 200, 500.0),
+            Err(Error::InsufficientFunds { .. })
+        );
+    }
+}
@@ -1,12 +1,10 @@
-use crate::online::get_address::GetAddressOptions;
+use crate::online::backend::{FEE_TARGET, MIN_RELAY_FEE_RATE};
+use crate::online::fund_psbt::{fill_non_witness_utxos, fund_psbt, wallet_coins, FundRequest};
+use crate::online::get_address::CHANGE_NOT_WATCHED;
 use crate::*;
-use bitcoin::{Address, Amount, OutPoint};
-use bitcoincore_rpc::bitcoincore_rpc_json::{
-    CreateRawTransactionInput, GetTransactionResultDetailCategory, WalletCreateFundedPsbtOptions,
-};
-use bitcoincore_rpc::RpcApi;
-use log::{debug, info};
-use std::collections::{HashMap, HashSet};
+use bitcoin::{Address, Amount, OutPoint, TxOut};
+use log::debug;
+use std::collections::HashSet;
 use std::str::FromStr;
 use structopt::StructOpt;
 
@@ -22,13 +20,35 @@ pub struct CreateTxOptions {
     #[structopt(long, long = "recipient")]
     pub recipients: Vec<AddressAmount>,
 
-    /// Coin to spend, specified as txid:vout see list-coins, if not specified the node will choose coins
+    /// Coin to spend, specified as txid:vout see list-coins, if not specified coins are selected
+    /// among the confirmed ones not frozen
     #[structopt(long, long = "coin")]
     pub coins: Vec<OutPoint>,
 
     /// Name of the PSBT
     #[structopt(short, long)]
     pub psbt_name: String,
+
+    /// Fee rate in satoshi per virtual byte, if not specified it is estimated by the backend
+    #[structopt(long)]
+    pub fee_rate: Option<f64>,
+
+    /// Blocks target of the fee estimation, ignored if the fee rate is specified
+    #[structopt(long)]
+    pub conf_target: Option<u16>,
+
+    /// Signal the transaction as replaceable (BIP125), allowing to bump the fee later
+    #[structopt(long)]
+    pub replaceable: bool,
+
+    /// Index of the recipient paying the fee, which receives the amount minus the fee
+    #[structopt(long)]
+    pub subtract_fee_from: Option<usize>,
+
+    /// Send all the confirmed coins not frozen, or the ones specified with --coin, to this address without
+    /// change, the fee is subtracted from the amount. Excludes --recipient
+    #[structopt(long)]
+    pub send_all: Option<Address>,
 }
 
 #[derive(StructOpt, Debug)]
@@ -39,31 +59,45 @@ pub struct AddressAmount {
 
 impl CreateTxOptions {
     fn validate(&self) -> Result<()> {
-        if self.recipients.is_empty() {
-            return Err("At least one recipient is mandatory (--recipient)".into());
+        if self.send_all.is_some() {
+            if !self.recipients.is_empty() || self.subtract_fee_from.is_some() {
+                return Err("--send-all excludes --recipient and --subtract-fee-from".into());
+            }
+        } else if self.recipients.is_empty() {
+            return Err("At least one recipient is mandatory (--recipient or --send-all)".into());
+        }
+        if self.fee_rate.is_some() && self.conf_target.is_some() {
+            return Err("--fee-rate and --conf-target are mutually exclusive".into());
+        }
+        if let Some(fee_rate) = self.fee_rate {
+            if fee_rate < MIN_RELAY_FEE_RATE {
+                return Err(format!(
+                    "fee rate must be at least {} sat/vB (--fee-rate)",
+                    MIN_RELAY_FEE_RATE
+                )
+                .into());
+            }
+        }
+        if let Some(index) = self.subtract_fee_from {
+            if index >= self.recipients.len() {
+                return Err(
+                    format!("no recipient at index {} (--subtract-fee-from)", index).into(),
+                );
+            }
         }
 
         Ok(())
     }
 
-    fn recipients_as_outputs(&self) -> HashMap<String, Amount> {
+    fn recipients_as_outputs(&self) -> Vec<TxOut> {
         self.recipients
             .iter()
-            .map(|r| (r.address.to_string(), r.amount))
+            .map(|r| TxOut {
+                value: r.amount.as_sat(),
+                script_pubkey: r.address.script_pubkey(),
+            })
             .collect()
     }
-
-    fn coins_as_inputs(&self) -> Vec<CreateRawTransactionInput> {
-        let mut vec = vec![];
-        for coin in self.coins.iter() {
-            vec.push(CreateRawTransactionInput {
-                txid: coin.txid,
-                vout: coin.vout,
-                sequence: None,
-            });
-        }
-        vec
-    }
 }
 
 impl FromStr for AddressAmount {
@@ -89,61 +123,93 @@ impl FromStr for AddressAmount {
 impl OnlineContext {
     pub fn create_tx(&self, opt: &CreateTxOptions) -> Result<CreateTxOutput> {
         opt.validate()?;
-        let client = self.make_client(&opt.wallet_name)?;
-        let outputs = opt.recipients_as_outputs();
-        debug!("{:?}", outputs);
-        let inputs = opt.coins_as_inputs();
-        debug!("{:?}", inputs);
+        let backend = self.make_backend(&opt.wallet_name)?;
 
-        let get_addr_opts = GetAddressOptions {
-            wallet_name: opt.wallet_name.to_string(),
-            index: None,
-            verify_wallet: false,
+        let mut warning = None;
+        let (change_chain, change_index) = if opt.send_all.is_some() {
+            // there is no change, the current index is given only to fill the request
+            (1, self.read::<WalletIndexes>(&opt.wallet_name)?.change)
+        } else {
+            let (chain, change) = self.change_address(&opt.wallet_name, backend.as_ref())?;
+            if chain == 0 {
+                warning = Some(CHANGE_NOT_WATCHED.to_string());
+            }
+            let index = change.path.as_ref().last().map_or(0, |c| u32::from(*c));
+            (chain, index)
         };
 
-        let change_address = self.get_address(&get_addr_opts)?.address;
-
-        let options = WalletCreateFundedPsbtOptions {
-            include_watching: Some(true),
-            change_address: Some(change_address),
-            ..Default::default()
+        let wallet: Wallet = self.read(&opt.wallet_name)?;
+        let indexes: WalletIndexes = self.read(&opt.wallet_name)?;
+        // unconfirmed coins are spent only if explicitly chosen
+        let min_conf = if opt.coins.is_empty() { 1 } else { 0 };
+        let utxos = backend.list_unspent(min_conf)?;
+        let mut coins = wallet_coins(&wallet, &indexes, &utxos)?;
+        if let Some(coin) = opt
+            .coins
+            .iter()
+            .find(|outpoint| !coins.iter().any(|c| c.outpoint == **outpoint))
+        {
+            return Err(format!("coin {} is not an unspent coin of the wallet", coin).into());
+        }
+        let labels = self.read_labels(&opt.wallet_name)?;
+        coins.retain(|c| opt.coins.contains(&c.outpoint) || !labels.is_frozen(&c.outpoint));
+        let fee_rate = match opt.fee_rate {
+            Some(fee_rate) => fee_rate,
+            None => backend.fee_rate(opt.conf_target.unwrap_or(FEE_TARGET))?,
         };
 
-        let result =
-            client.wallet_create_funded_psbt(&inputs, &outputs, None, Some(options), Some(true));
-        info!("wallet_create_funded_psbt {:#?}", result);
-
-        // decreasing auto-incremented change index if error or change not used
-        let funded_psbt = result?;
-        /*let funded_psbt = match result {
-            Ok(value) => {
-                if value.change_position == -1 {
-                    self.context.decrease_index()?;
+        let (outputs, selected, subtract_fee_from) = match &opt.send_all {
+            Some(address) => {
+                let selected: Vec<OutPoint> = if opt.coins.is_empty() {
+                    coins.iter().map(|c| c.outpoint).collect()
+                } else {
+                    opt.coins.clone()
+                };
+                if selected.is_empty() {
+                    return Err("no confirmed coins to send".into());
                 }
-                value
+                let total = coins
+                    .iter()
+                    .filter(|c| selected.contains(&c.outpoint))
+                    .map(|c| c.txout.value)
+                    .sum();
+                let output = TxOut {
+                    value: total,
+                    script_pubkey: address.script_pubkey(),
+                };
+                (vec![output], selected, Some(0))
             }
-            Err(e) => {
-                self.context.decrease_index()?;
-                return Err(format!("error creating psbt ({:?})", e).into());
-            }
-        };*/
+            None => (
+                opt.recipients_as_outputs(),
+                opt.coins.clone(),
+                opt.subtract_fee_from,
+            ),
+        };
+        debug!("{:?}", outputs);
 
-        let mut psbt = psbt_from_rpc(&funded_psbt, &opt.psbt_name)?;
+        let req = FundRequest {
+            wallet: &wallet,
+            coins: &coins,
+            selected: &selected,
+            outputs,
+            change_chain,
+            change_index,
+            fee_rate,
+            replaceable: opt.replaceable,
+            subtract_fee_from,
+        };
+        let mut psbt = fund_psbt(&req)?;
+        fill_non_witness_utxos(&mut psbt, backend.as_ref())?;
+        set_psbt_name(&mut psbt, &opt.psbt_name);
 
         let psbt_name = self.save_psbt(&mut psbt)?;
 
         // detect address reuse
-        let transactions = client
-            .list_transactions(None, Some(1000), None, Some(true))
-            .unwrap();
         let mut address_reused = HashSet::new();
-        for recipient in opt.recipients.iter() {
-            for tx in transactions.iter() {
-                if tx.detail.address.as_ref() == Some(&recipient.address)
-                    && tx.detail.category == GetTransactionResultDetailCategory::Send
-                {
-                    address_reused.insert(recipient.address.clone());
-                }
+        let addresses = opt.recipients.iter().map(|r| &r.address);
+        for address in addresses.chain(opt.send_all.iter()) {
+            if backend.is_address_reused(address)? {
+                address_reused.insert(address.clone());
             }
         }
 
@@ -151,8 +217,54 @@ impl OnlineContext {
             funded_psbt: (&psbt, self.network).into(),
             psbt_name,
             address_reused,
+            warning,
         };
 
         Ok(create_tx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::online::create_tx::CreateTxOptions;
+    use structopt::StructOpt;
+
+    const ADDRESS: &str = "tb1q5nrregep899vnvaa5vdpxcwg8794jqy38nu304kl4d7wm4e92yeqz4jfmk";
+
+    fn parse(args: &[&str]) -> CreateTxOptions {
+        let base = ["create-tx", "--wallet-name", "w", "--psbt-name", "p"];
+        CreateTxOptions::from_iter_safe(base.iter().chain(args)).unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        let recipient = format!("{}:1000", ADDRESS);
+        assert!(parse(&[]).validate().is_err());
+        assert!(parse(&["--recipient", &recipient]).validate().is_ok());
+        assert!(parse(&["--send-all", ADDRESS]).validate().is_ok());
+        assert!(parse(&["--send-all", ADDRESS, "--recipient", &recipient])
+            .validate()
+            .is_err());
+        assert!(parse(&["--send-all", ADDRESS, "--subtract-fee-from", "0"])
+            .validate()
+            .is_err());
+        assert!(
+            parse(&["--recipient", &recipient, "--subtract-fee-from", "1"])
+                .validate()
+                .is_err()
+        );
+        assert!(parse(&["--recipient", &recipient, "--fee-rate", "0.5"])
+            .validate()
+            .is_err());
+        assert!(parse(&[
+            "--recipient",
+            &recipient,
+            "--fee-rate",
+            "2",
+            "--conf-target",
+            "3"
+        ])
+        .validate()
+        .is_err());
+    }
+}
@@ -1,10 +1,27 @@
+use crate::offline::descriptor::{
+    change_descriptor, derive_address, descriptor_checksum, multipath_descriptor, node_can_derive,
+    DeriveAddressOptions,
+};
+use crate::offline::taproot::NUMS;
+use crate::online::backend::BackendKind;
+use crate::online::electrum::ElectrumClient;
 use crate::*;
+use bitcoin::Network;
 use bitcoincore_rpc::bitcoincore_rpc_json::ImportMultiOptions;
 use bitcoincore_rpc::bitcoincore_rpc_json::ImportMultiRequest;
+use bitcoincore_rpc::bitcoincore_rpc_json::ImportMultiRequestScriptPubkey;
 use bitcoincore_rpc::bitcoincore_rpc_json::ImportMultiRescanSince;
-use bitcoincore_rpc::RpcApi;
+use bitcoincore_rpc::bitcoincore_rpc_json::ImportMultiResult;
+use bitcoincore_rpc::{Client, RpcApi};
 use log::debug;
 use log::info;
+use miniscript::policy::Concrete;
+use miniscript::{Descriptor, DescriptorPublicKey, Segwitv0};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io;
+use std::str::FromStr;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -14,15 +31,18 @@ pub struct CreateWalletOptions {
     pub wallet_name: String,
 
     /// number of signatures required
-    #[structopt(short)]
-    pub required_sigs: u8,
+    #[structopt(short, required_unless = "policy")]
+    pub required_sigs: Option<u8>,
 
     /// DescriptorPubKey that are composing the wallet, given as String (xprv...).
-    /// Could be an Extended Public Keys (xpub) but it could also contain origin path and fingerprint and path
+    /// Could be an Extended Public Keys (xpub) but it could also contain origin path and fingerprint and path,
+    /// multipath keys ending in `/<0;1>/*` are accepted
     #[structopt(long)]
     pub desc_pub_keys: Vec<String>, // DescriptorPubKey
 
-    /// Key name that are composing the wallet, must be found in firma datadir
+    /// Key name that are composing the wallet, must be found in firma datadir, use
+    /// `name:scheme:account` (or `name:account` if unambiguous) for an additional account derived
+    /// with `derive-key`
     #[structopt(long = "key-name")]
     pub key_names: Vec<String>,
 
@@ -30,27 +50,92 @@ pub struct CreateWalletOptions {
     /// useful for testing locally, to avoid removing the wallets
     #[structopt(long)]
     pub allow_wallet_already_exists: bool,
+
+    /// Script type of the wallet, valid values are: wsh, sh-wsh, tr
+    /// `sh-wsh` nests the segwit script in a p2sh for compatibility with older senders,
+    /// `tr` creates a taproot wallet with an unspendable internal key and a `multi_a` leaf
+    #[structopt(long, default_value = "wsh")]
+    pub script_type: ScriptType,
+
+    /// Use `sortedmulti` (`sortedmulti_a` for taproot) so that the order of the keys given doesn't
+    /// matter, keys are also sorted in the descriptor. Use `--sortedmulti false` for `multi`
+    #[structopt(long, parse(try_from_str), default_value = "true")]
+    pub sortedmulti: bool,
+
+    /// Miniscript policy compiled to a `wsh` descriptor, keys in the policy are key names found
+    /// in firma datadir, eg. "or(thresh(2,pk(A),pk(B),pk(C)),and(pk(D),older(52560)))"
+    #[structopt(long, conflicts_with_all = &["required-sigs", "desc-pub-keys", "key-names"])]
+    pub policy: Option<String>,
+
+    /// Store the wallet as a BIP389 multipath descriptor `/<0;1>/*` covering receive and change
+    /// chains. Use `--multipath false` to store the receive descriptor `/0/*`
+    #[structopt(long, parse(try_from_str), default_value = "true")]
+    pub multipath: bool,
+
+    /// Last derivation index of the receive and change chains imported in the bitcoin node,
+    /// `get-address` extends it when addresses given out approach the end of the range
+    #[structopt(long, default_value = "1000")]
+    pub import_range: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum ScriptType {
+    Wsh,
+    ShWsh,
+    Tr,
+}
+
+impl FromStr for ScriptType {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "wsh" => Ok(ScriptType::Wsh),
+            "sh-wsh" => Ok(ScriptType::ShWsh),
+            "tr" => Ok(ScriptType::Tr),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("({}) valid values are: wsh, sh-wsh, tr", s),
+            )),
+        }
+    }
 }
 
 impl CreateWalletOptions {
     fn desc_pub_keys(&self) -> Result<Vec<miniscript::DescriptorPublicKey>> {
         let mut result = vec![];
         for s in self.desc_pub_keys.iter() {
-            let k: miniscript::DescriptorPublicKey = s.parse()?;
+            // multipath keys are taken on the receive chain, the wallet covers both chains anyway
+            let k: miniscript::DescriptorPublicKey = s.replace("/<0;1>/", "/0/").parse()?;
             result.push(k);
         }
         Ok(result)
     }
     fn validate(&self, context: &Context) -> Result<()> {
-        if self.required_sigs == 0 {
+        if self.policy.is_some() {
+            if self.required_sigs.is_some()
+                || !self.key_names.is_empty()
+                || !self.desc_pub_keys.is_empty()
+            {
+                return Err("policy wallets take keys from the policy".into());
+            }
+            if self.script_type != ScriptType::Wsh {
+                return Err("policy wallets support only wsh script type".into());
+            }
+            return Ok(());
+        }
+        let required_sigs = self
+            .required_sigs
+            .ok_or("required signatures must be given")?;
+        if required_sigs == 0 {
             return Err("required signatures cannot be 0".into());
         }
 
-        if self.required_sigs > 15 {
+        if required_sigs > 15 {
             return Err("required signatures cannot be greater than 15".into());
         }
 
-        if self.required_sigs > (self.desc_pub_keys.len() + self.key_names.len()) as u8 {
+        if required_sigs > (self.desc_pub_keys.len() + self.key_names.len()) as u8 {
             //TODO check overflow
             return Err("required signatures cannot be greater than the number of xpubs".into());
         }
@@ -85,7 +170,64 @@ impl OnlineContext {
         debug!("create_wallet {:?}", opt);
         opt.validate(self)?;
 
-        // create the wallet in the bitcoin node  (should not already exist unless forced)
+        let descriptor = match (&opt.policy, opt.required_sigs) {
+            (Some(policy), _) => {
+                let policy: Concrete<String> = policy.parse()?;
+                let names = policy_key_names(&policy);
+                let desc_pub_keys = self.read_desc_pub_keys_from_names(&names)?;
+                compile_policy(&policy, &desc_pub_keys)?
+            }
+            (None, Some(required_sigs)) => {
+                let mut desc_pub_keys = self.read_desc_pub_keys_from_names(&opt.key_names)?;
+                desc_pub_keys.extend(opt.desc_pub_keys()?);
+                create_descriptor(
+                    opt.script_type,
+                    opt.sortedmulti,
+                    required_sigs,
+                    &desc_pub_keys,
+                )
+            }
+            (None, None) => return Err("required signatures must be given".into()),
+        };
+
+        let daemon_opts = self.read_daemon_opts()?;
+        let (descriptor, height) = match daemon_opts.backend {
+            BackendKind::Bitcoind => self.import_in_node(opt, &descriptor)?,
+            BackendKind::Electrum => {
+                // scripts are derived locally, there is no wallet to create in the server
+                let client =
+                    ElectrumClient::new(&daemon_opts.url, self.network, daemon_opts.insecure)?;
+                (with_checksum(&descriptor)?, client.tip_height()?)
+            }
+        };
+        let descriptor = if opt.multipath {
+            multipath_descriptor(&descriptor)?
+        } else {
+            descriptor
+        };
+
+        let wallet = Wallet {
+            id: Identifier::new(self.network, Kind::Wallet, &opt.wallet_name),
+            descriptor,
+            created_at_height: height,
+        };
+        let indexes = WalletIndexes {
+            id: Identifier::new(self.network, Kind::WalletIndexes, &opt.wallet_name),
+            main: 0u32,
+            change: 0u32,
+            imported: opt.import_range,
+        };
+
+        self.write(&wallet)?;
+        self.write(&indexes)?;
+
+        Ok(wallet)
+    }
+
+    /// create the watch-only wallet in the bitcoin node (should not already exist unless forced)
+    /// and import the receive and change descriptors, returns the descriptor with the checksum
+    /// and the current height
+    fn import_in_node(&self, opt: &CreateWalletOptions, descriptor: &str) -> Result<(String, u64)> {
         match self.make_client(&opt.wallet_name) {
             Ok(_) => {
                 if !opt.allow_wallet_already_exists {
@@ -95,69 +237,263 @@ impl OnlineContext {
                 }
             }
             Err(Error::WalletNotExistsInNode(_)) => {
-                self.read_daemon_opts()?
-                    .make_client(None, self.network)?
-                    .create_wallet(&opt.wallet_name, Some(true), None, None, None)?;
+                let client = self.read_daemon_opts()?.make_client(None, self.network)?;
+                if client.version()? >= DESCRIPTOR_WALLET_VERSION {
+                    // watch-only, blank, descriptor wallet, the library call misses the last param
+                    let params: Vec<Value> = vec![
+                        opt.wallet_name.clone().into(),
+                        true.into(),
+                        true.into(),
+                        "".into(),
+                        false.into(),
+                        true.into(),
+                    ];
+                    let _: Value = client.call("createwallet", &params)?;
+                } else {
+                    // legacy watch-only wallet, descriptors are imported with `importmulti`
+                    client.create_wallet(&opt.wallet_name, Some(true), None, None, None)?;
+                }
             }
             Err(e) => return Err(e),
         };
         let client = self.make_client(&opt.wallet_name)?;
 
-        let mut desc_pub_keys = self.read_desc_pub_keys_from_names(&opt.key_names)?;
-        desc_pub_keys.extend(opt.desc_pub_keys()?);
+        let range = (0, opt.import_range);
+        let descriptor = import_descriptor(&client, self.network, descriptor, false, range)?;
+        let change_descriptor = change_descriptor(&descriptor)?;
+        import_descriptor(&client, self.network, &change_descriptor, true, range)?;
+        let height = client.get_blockchain_info()?.blocks;
+        Ok((descriptor, height))
+    }
+}
+
+/// last index of the wallet chains imported in the node when not specified
+pub const DEFAULT_IMPORT_RANGE: u32 = 1000;
 
-        let descriptor = create_descriptor(opt.required_sigs, &desc_pub_keys);
-        let descriptor = client.get_descriptor_info(&descriptor)?.descriptor; // adds checksum
+/// first node version supporting descriptor wallets and `importdescriptors`
+const DESCRIPTOR_WALLET_VERSION: usize = 210000;
+
+#[derive(Deserialize)]
+struct ImportDescriptorsResult {
+    success: bool,
+    error: Option<Value>,
+}
+
+/// true if the node wallet is a descriptor wallet, false for legacy ones
+pub(crate) fn is_descriptor_wallet(client: &Client) -> Result<bool> {
+    let info: Value = client.call("getwalletinfo", &[])?;
+    Ok(info
+        .get("descriptors")
+        .and_then(Value::as_bool)
+        .unwrap_or(false))
+}
+
+/// import the descriptor in the node as watch-only for the indexes in `range` (inclusive),
+/// `internal` descriptors are used for change, returns the descriptor with the checksum
+pub(crate) fn import_descriptor(
+    client: &Client,
+    network: Network,
+    descriptor: &str,
+    internal: bool,
+    range: (u32, u32),
+) -> Result<String> {
+    if is_descriptor_wallet(client)? {
+        import_descriptors(client, network, descriptor, internal, range)
+    } else {
+        import_multi(client, network, descriptor, internal, range)
+    }
+}
+
+/// import in a descriptor wallet with `importdescriptors`, ranged descriptors are imported as
+/// active so that the node derives the addresses itself
+fn import_descriptors(
+    client: &Client,
+    network: Network,
+    descriptor: &str,
+    internal: bool,
+    range: (u32, u32),
+) -> Result<String> {
+    let (descriptor, requests) = if node_can_derive(descriptor) {
+        let descriptor = client.get_descriptor_info(descriptor)?.descriptor; // adds checksum
+
+        // the node requires the new range to include the one already imported
+        let request = json!({
+            "desc": descriptor,
+            "active": true,
+            "internal": internal,
+            "range": [0, range.1],
+            "timestamp": "now",
+        });
+        (descriptor, vec![request])
+    } else {
+        let descriptor = with_checksum(descriptor)?;
+        let mut requests = vec![];
+        for index in range.0..=range.1 {
+            let opts = DeriveAddressOptions {
+                descriptor: descriptor.clone(),
+                index,
+            };
+            let address = derive_address(network, &opts)?.address;
+            let desc = with_checksum(&format!("addr({})", address))?;
+            requests.push(json!({ "desc": desc, "timestamp": "now" }));
+        }
+        (descriptor, requests)
+    };
+
+    let results: Vec<ImportDescriptorsResult> =
+        client.call("importdescriptors", &[Value::Array(requests)])?;
+    info!("importdescriptors results {}", results.len());
+    if let Some(error) = results.into_iter().find(|r| !r.success) {
+        let message = error.error.unwrap_or(Value::Null).to_string();
+        return Err(format!("importdescriptors failed: {}", message).into());
+    }
+    Ok(descriptor)
+}
+
+/// import in a legacy wallet with `importmulti`
+fn import_multi(
+    client: &Client,
+    network: Network,
+    descriptor: &str,
+    internal: bool,
+    range: (u32, u32),
+) -> Result<String> {
+    let multi_options = ImportMultiOptions {
+        rescan: Some(false),
+    };
+
+    if node_can_derive(descriptor) {
+        let descriptor = client.get_descriptor_info(descriptor)?.descriptor; // adds checksum
 
         let multi_request = ImportMultiRequest {
-            range: Some((0, 1000)), //TODO should be a parameter
+            range: Some((range.0 as usize, range.1 as usize)),
             timestamp: ImportMultiRescanSince::Now,
             keypool: Some(true),
             watchonly: Some(true),
             descriptor: Some(&descriptor),
-            internal: Some(false),
+            internal: Some(internal),
             ..Default::default()
         };
 
-        let multi_options = ImportMultiOptions {
-            rescan: Some(false),
-        };
-
-        let import_multi_result = client.import_multi(&[multi_request], Some(&multi_options));
-        info!("import_multi_result {:?}", import_multi_result);
+        let results = client.import_multi(&[multi_request], Some(&multi_options))?;
+        info!("import_multi_result {:?}", results);
+        check_import_multi(results)?;
+        Ok(descriptor)
+    } else {
+        // the node cannot parse taproot or miniscript descriptors, checksum is computed
+        // locally and addresses are imported one by one as watch-only scripts
+        let descriptor = with_checksum(descriptor)?;
+        let mut addresses = vec![];
+        for index in range.0..=range.1 {
+            let opts = DeriveAddressOptions {
+                descriptor: descriptor.clone(),
+                index,
+            };
+            addresses.push(derive_address(network, &opts)?.address);
+        }
+        let multi_requests: Vec<_> = addresses
+            .iter()
+            .map(|address| ImportMultiRequest {
+                timestamp: ImportMultiRescanSince::Now,
+                script_pubkey: Some(ImportMultiRequestScriptPubkey::Address(address)),
+                watchonly: Some(true),
+                internal: Some(internal),
+                ..Default::default()
+            })
+            .collect();
 
-        let height = client.get_blockchain_info()?.blocks;
+        let results = client.import_multi(&multi_requests, Some(&multi_options))?;
+        info!("import_multi_result {}", results.len());
+        check_import_multi(results)?;
+        Ok(descriptor)
+    }
+}
 
-        let wallet = Wallet {
-            id: Identifier::new(self.network, Kind::Wallet, &opt.wallet_name),
-            descriptor,
-            created_at_height: height,
-        };
-        let indexes = WalletIndexes {
-            id: Identifier::new(self.network, Kind::WalletIndexes, &opt.wallet_name),
-            main: 0u32,
-        };
+/// error if any of the `importmulti` requests failed, the node returns them per request
+fn check_import_multi(results: Vec<ImportMultiResult>) -> Result<()> {
+    match results.into_iter().find(|r| !r.success) {
+        Some(failed) => {
+            let message = failed.error.map(|e| e.message).unwrap_or_default();
+            Err(format!("importmulti failed: {}", message).into())
+        }
+        None => Ok(()),
+    }
+}
 
-        self.write(&wallet)?;
-        self.write(&indexes)?;
+/// the descriptor with the checksum, computed locally if missing
+fn with_checksum(descriptor: &str) -> Result<String> {
+    Ok(match descriptor.find('#') {
+        Some(_) => descriptor.to_string(),
+        None => format!("{}#{}", descriptor, descriptor_checksum(descriptor)?),
+    })
+}
 
-        Ok(wallet)
+/// key names in the policy, in order of appearance without duplicates
+fn policy_key_names(policy: &Concrete<String>) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for name in policy.keys() {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
     }
+    names
+}
+
+/// compile the policy to a wsh descriptor, `desc_pub_keys` are the keys of the names returned by
+/// `policy_key_names` in the same order
+fn compile_policy(
+    policy: &Concrete<String>,
+    desc_pub_keys: &[DescriptorPublicKey],
+) -> Result<String> {
+    let keys: HashMap<_, _> = policy_key_names(policy)
+        .into_iter()
+        .zip(desc_pub_keys.iter())
+        .collect();
+    let policy = policy.translate_pk(|name| {
+        keys.get(name)
+            .map(|k| (*k).clone())
+            .ok_or(Error::MissingKey)
+    })?;
+    let miniscript = policy.compile::<Segwitv0>()?;
+    Ok(Descriptor::new_wsh(miniscript)?.to_string())
 }
 
 fn create_descriptor(
+    script_type: ScriptType,
+    sortedmulti: bool,
     required_sigs: u8,
     desc_pub_keys: &[miniscript::DescriptorPublicKey],
 ) -> String {
-    let keys: Vec<String> = desc_pub_keys.iter().map(|d| d.to_string()).collect();
-    let descriptor = format!("wsh(multi({},{}))", required_sigs, keys.join(","));
+    let mut keys: Vec<String> = desc_pub_keys.iter().map(|d| d.to_string()).collect();
+    if sortedmulti {
+        // keys order doesn't change the addresses, sorting them to have the same descriptor
+        keys.sort();
+    }
+    let multi = match (script_type, sortedmulti) {
+        (ScriptType::Wsh, false) | (ScriptType::ShWsh, false) => "multi",
+        (ScriptType::Wsh, true) | (ScriptType::ShWsh, true) => "sortedmulti",
+        (ScriptType::Tr, false) => "multi_a",
+        (ScriptType::Tr, true) => "sortedmulti_a",
+    };
+    let descriptor = match script_type {
+        ScriptType::Wsh => format!("wsh({}({},{}))", multi, required_sigs, keys.join(",")),
+        ScriptType::ShWsh => format!("sh(wsh({}({},{})))", multi, required_sigs, keys.join(",")),
+        ScriptType::Tr => format!(
+            "tr({},{}({},{}))",
+            NUMS,
+            multi,
+            required_sigs,
+            keys.join(",")
+        ),
+    };
     descriptor
 }
 
 #[cfg(test)]
 mod tests {
     use crate::common::tests::rnd_string;
-    use crate::online::create_wallet::{create_descriptor, CreateWalletOptions};
+    use crate::offline::descriptor::{derive_address, node_can_derive, DeriveAddressOptions};
+    use crate::online::create_wallet::*;
     use crate::{Identifier, Kind, MasterSecret, Wallet};
     use bitcoin::Network;
 
@@ -165,10 +501,15 @@ mod tests {
         pub fn new_random(required_sigs: u8, key_names: Vec<String>) -> Self {
             CreateWalletOptions {
                 wallet_name: rnd_string(),
-                required_sigs,
+                required_sigs: Some(required_sigs),
                 desc_pub_keys: vec![],
                 key_names,
                 allow_wallet_already_exists: false,
+                script_type: ScriptType::Wsh,
+                sortedmulti: true,
+                policy: None,
+                multipath: true,
+                import_range: DEFAULT_IMPORT_RANGE,
             }
         }
     }
@@ -198,10 +539,93 @@ mod tests {
                     kind: Kind::Wallet,
                     name: rnd_string(),
                     network: Network::Testnet,
+                    account: None,
+                    scheme: None,
                 },
-                descriptor: create_descriptor(required_sig, &desc_pub_keys),
+                descriptor: create_descriptor(ScriptType::Wsh, false, required_sig, &desc_pub_keys),
                 created_at_height: 0,
             }
         }
     }
+
+    #[test]
+    fn test_create_descriptor() {
+        let k1: miniscript::DescriptorPublicKey = "[a2ebe04e/48'/1'/0'/2']tpubDEXDRpvW2srXCSjAvC36zYkSE3jxT1wf7JXDo35Ln4NZpmaMNhq8o9coH9U9BQ5bAN4WDGxXV9d426iYKGorFF5wvv4Wv63cZsCotiXGGkD/0/*".parse().unwrap();
+        let k2: miniscript::DescriptorPublicKey = "[1f5e43d8/48'/1'/0'/2']tpubDFU4parcXvV8tBYt4rS4a8rGNF1DA32DCnRfhzVL6b3MSiDomV95rv9mb7W7jAPMTohyEYpbhVS8FbmTsuQsFRxDWPJX2ZFEeRPMFz3R1gh/0/*".parse().unwrap();
+        let keys = vec![k1.clone(), k2.clone()];
+        let reversed = vec![k2, k1];
+
+        let multi = create_descriptor(ScriptType::Wsh, false, 2, &keys);
+        assert!(multi.starts_with("wsh(multi(2,[a2ebe04e"));
+        assert_ne!(
+            multi,
+            create_descriptor(ScriptType::Wsh, false, 2, &reversed)
+        );
+
+        let sorted = create_descriptor(ScriptType::Wsh, true, 2, &keys);
+        assert!(sorted.starts_with("wsh(sortedmulti(2,[1f5e43d8"));
+        assert_eq!(
+            sorted,
+            create_descriptor(ScriptType::Wsh, true, 2, &reversed)
+        );
+        sorted
+            .parse::<miniscript::Descriptor<miniscript::DescriptorPublicKey>>()
+            .unwrap();
+
+        let sorted_tr = create_descriptor(ScriptType::Tr, true, 2, &keys);
+        assert!(sorted_tr.contains("sortedmulti_a(2,[1f5e43d8"));
+        assert_eq!(
+            sorted_tr,
+            create_descriptor(ScriptType::Tr, true, 2, &reversed)
+        );
+    }
+
+    #[test]
+    fn test_create_descriptor_sh_wsh() {
+        let k1: miniscript::DescriptorPublicKey = "[a2ebe04e/48'/1'/0'/1']tpubDEXDRpvW2srXCSjAvC36zYkSE3jxT1wf7JXDo35Ln4NZpmaMNhq8o9coH9U9BQ5bAN4WDGxXV9d426iYKGorFF5wvv4Wv63cZsCotiXGGkD/0/*".parse().unwrap();
+        let k2: miniscript::DescriptorPublicKey = "[1f5e43d8/48'/1'/0'/1']tpubDFU4parcXvV8tBYt4rS4a8rGNF1DA32DCnRfhzVL6b3MSiDomV95rv9mb7W7jAPMTohyEYpbhVS8FbmTsuQsFRxDWPJX2ZFEeRPMFz3R1gh/0/*".parse().unwrap();
+        let keys = vec![k1.clone(), k2.clone()];
+
+        let descriptor = create_descriptor(ScriptType::ShWsh, true, 2, &keys);
+        assert!(descriptor.starts_with("sh(wsh(sortedmulti(2,[1f5e43d8"));
+        assert!(node_can_derive(&descriptor));
+        let opts = DeriveAddressOptions {
+            descriptor: descriptor.clone(),
+            index: 0,
+        };
+        let address = derive_address(Network::Testnet, &opts).unwrap().address;
+        assert!(address.script_pubkey().is_p2sh());
+
+        let wallet = Wallet::new(&descriptor, Network::Testnet);
+        assert_eq!(wallet.extract_desc_pub_keys().unwrap(), vec![k2, k1]);
+    }
+
+    #[test]
+    fn test_compile_policy() {
+        let policy = "or(thresh(2,pk(A),pk(B),pk(C)),and(pk(D),older(52560)))";
+        let policy: Concrete<String> = policy.parse().unwrap();
+        assert_eq!(policy_key_names(&policy), vec!["A", "B", "C", "D"]);
+        let xpubs = [
+            "[a2ebe04e/48'/1'/0'/2']tpubDEXDRpvW2srXCSjAvC36zYkSE3jxT1wf7JXDo35Ln4NZpmaMNhq8o9coH9U9BQ5bAN4WDGxXV9d426iYKGorFF5wvv4Wv63cZsCotiXGGkD",
+            "[1f5e43d8/48'/1'/0'/2']tpubDFU4parcXvV8tBYt4rS4a8rGNF1DA32DCnRfhzVL6b3MSiDomV95rv9mb7W7jAPMTohyEYpbhVS8FbmTsuQsFRxDWPJX2ZFEeRPMFz3R1gh",
+        ];
+        let keys: Vec<DescriptorPublicKey> = (0..4)
+            .map(|i| format!("{}/{}/*", xpubs[i % 2], i).parse().unwrap())
+            .collect();
+
+        let descriptor = compile_policy(&policy, &keys).unwrap();
+        assert!(descriptor.starts_with("wsh("));
+        assert!(descriptor.contains("older(52560)"));
+        assert!(!node_can_derive(&descriptor));
+        let opts = DeriveAddressOptions {
+            descriptor: descriptor.clone(),
+            index: 0,
+        };
+        derive_address(Network::Testnet, &opts).unwrap();
+
+        let wallet = Wallet::new(&descriptor, Network::Testnet);
+        assert_eq!(wallet.extract_desc_pub_keys().unwrap().len(), 4);
+
+        assert!(compile_policy(&policy, &keys[..3]).is_err());
+    }
 }
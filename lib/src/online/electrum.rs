@@ -0,0 +1,659 @@
+use crate::online::backend::{ChainBackend, Utxo, WalletTx, FALLBACK_FEE_RATE};
+use crate::online::fund_psbt::{wallet_scripts, WalletCoin};
+use crate::*;
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::{Address, Amount, BlockHeader, Network, OutPoint, Script, Transaction, TxOut, Txid};
+use log::{debug, info};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+
+/// A client of the electrum protocol over plain TCP
+pub struct ElectrumClient {
+    stream: RefCell<BufReader<TcpStream>>,
+    id: RefCell<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListUnspentEntry {
+    tx_hash: Txid,
+    tx_pos: u32,
+    height: i64,
+    value: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryEntry {
+    tx_hash: Txid,
+    height: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct HeaderNotification {
+    height: u64,
+}
+
+impl ElectrumClient {
+    /// connect to the server at `url` in the form `host:port` or `tcp://host:port` and check it is
+    /// on `network`. The connection isn't encrypted, a server not on the loopback interface
+    /// requires `insecure`
+    pub fn new(url: &str, network: Network, insecure: bool) -> Result<Self> {
+        if url.starts_with("ssl://") {
+            return Err(Error::Electrum(
+                "ssl is not supported, use tcp to a local server or through a tunnel".to_string(),
+            ));
+        }
+        let address = url.trim_start_matches("tcp://");
+        let addrs: Vec<SocketAddr> = address.to_socket_addrs()?.collect();
+        if !insecure && !addrs.iter().all(|a| a.ip().is_loopback()) {
+            return Err(Error::Electrum(format!(
+                "{} is not a loopback address and the connection is unencrypted, use a tunnel or `--insecure`",
+                address
+            )));
+        }
+        debug!("connecting to electrum server {}", address);
+        let stream = TcpStream::connect(&addrs[..])?;
+        let client = ElectrumClient {
+            stream: RefCell::new(BufReader::new(stream)),
+            id: RefCell::new(0),
+        };
+        let _ = client.call("server.version", json!(["firma", "1.4"]))?;
+
+        let header = client.call("blockchain.block.header", json!([0]))?;
+        let header: BlockHeader = deserialize(&hex::decode(header.as_str().unwrap_or(""))?)?;
+        let firma_genesis = genesis_block(network).block_hash();
+        if header.block_hash() != firma_genesis {
+            return Err(Error::IncompatibleGenesis {
+                node: header.block_hash(),
+                firma: firma_genesis,
+            });
+        }
+        Ok(client)
+    }
+
+    fn next_id(&self) -> usize {
+        let mut id = self.id.borrow_mut();
+        *id += 1;
+        *id
+    }
+
+    fn send(&self, request: &Value) -> Result<()> {
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+        let mut stream = self.stream.borrow_mut();
+        stream.get_mut().write_all(&line)?;
+        stream.get_mut().flush()?;
+        Ok(())
+    }
+
+    /// read the next response, skipping notifications
+    fn receive(&self) -> Result<Value> {
+        loop {
+            let mut line = String::new();
+            if self.stream.borrow_mut().read_line(&mut line)? == 0 {
+                return Err(Error::Electrum("connection closed".to_string()));
+            }
+            let value: Value = serde_json::from_str(&line)?;
+            if value.is_array() || value.get("id").is_some() {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn result(response: &Value) -> Result<Value> {
+        match response.get("error") {
+            Some(error) if !error.is_null() => Err(Error::Electrum(error.to_string())),
+            _ => Ok(response.get("result").cloned().unwrap_or(Value::Null)),
+        }
+    }
+
+    pub fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id();
+        self.send(&json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))?;
+        let response = self.receive()?;
+        ElectrumClient::result(&response)
+    }
+
+    /// call `method` once for every element of `params` in a single batch request
+    pub fn batch_call(&self, method: &str, params: &[Value]) -> Result<Vec<Value>> {
+        if params.is_empty() {
+            return Ok(vec![]);
+        }
+        let first = self.next_id();
+        let requests: Vec<Value> = params
+            .iter()
+            .map(|p| json!({"jsonrpc": "2.0", "id": self.next_id(), "method": method, "params": p}))
+            .collect();
+        self.send(&Value::Array(requests))?;
+        let responses = match self.receive()? {
+            Value::Array(responses) => responses,
+            _ => return Err(Error::Electrum("expected batch response".to_string())),
+        };
+        let mut results = vec![Value::Null; params.len()];
+        for response in responses.iter() {
+            let id = response["id"].as_u64().unwrap_or(0) as usize;
+            let position = id
+                .checked_sub(first + 1)
+                .filter(|p| *p < params.len())
+                .ok_or_else(|| Error::Electrum(format!("unexpected id {}", id)))?;
+            results[position] = ElectrumClient::result(response)?;
+        }
+        Ok(results)
+    }
+
+    pub fn tip_height(&self) -> Result<u64> {
+        let header: HeaderNotification =
+            serde_json::from_value(self.call("blockchain.headers.subscribe", json!([]))?)?;
+        Ok(header.height)
+    }
+}
+
+/// the electrum script hash, the sha256 of the script in reverse order
+pub fn script_hash(script: &Script) -> String {
+    let mut hash = sha256::Hash::hash(script.as_bytes()).into_inner();
+    hash.reverse();
+    hex::encode(hash)
+}
+
+/// decode the transaction returned by the server, checking it's the one requested
+fn checked_transaction(txid: &Txid, result: &Value) -> Result<Transaction> {
+    let tx: Transaction = deserialize(&hex::decode(result.as_str().unwrap_or(""))?)?;
+    if tx.txid() != *txid {
+        return Err(Error::Generic(format!(
+            "server returned transaction {} instead of {}",
+            tx.txid(),
+            txid
+        )));
+    }
+    Ok(tx)
+}
+
+/// A wallet backed by an electrum server, scripts are derived locally from the descriptor
+pub struct ElectrumBackend {
+    client: ElectrumClient,
+    network: Network,
+    wallet: Wallet,
+    indexes: WalletIndexes,
+}
+
+impl ElectrumBackend {
+    pub fn new(
+        opts: &DaemonOpts,
+        network: Network,
+        wallet: Wallet,
+        indexes: WalletIndexes,
+    ) -> Result<Self> {
+        Ok(ElectrumBackend {
+            client: ElectrumClient::new(&opts.url, network, opts.insecure)?,
+            network,
+            wallet,
+            indexes,
+        })
+    }
+
+    /// unspent outputs of the wallet scripts with their height, 0 if unconfirmed
+    fn wallet_coins(&self) -> Result<Vec<(WalletCoin, u64)>> {
+        let scripts = wallet_scripts(&self.wallet, &self.indexes, self.network)?;
+        let params: Vec<Value> = scripts.iter().map(|s| json!([script_hash(&s.0)])).collect();
+        let results = self
+            .client
+            .batch_call("blockchain.scripthash.listunspent", &params)?;
+        let mut coins = vec![];
+        for ((script, chain, index), result) in scripts.into_iter().zip(results) {
+            let entries: Vec<ListUnspentEntry> = serde_json::from_value(result)?;
+            for entry in entries {
+                let coin = WalletCoin {
+                    outpoint: OutPoint::new(entry.tx_hash, entry.tx_pos),
+                    txout: TxOut {
+                        value: entry.value,
+                        script_pubkey: script.clone(),
+                    },
+                    chain,
+                    index,
+                };
+                coins.push((coin, entry.height.max(0) as u64));
+            }
+        }
+        Ok(coins)
+    }
+}
+
+impl ChainBackend for ElectrumBackend {
+    fn balance(&self) -> Result<BalanceOutput> {
+        let (mut confirmed, mut pending) = (0u64, 0u64);
+        for (coin, height) in self.wallet_coins()? {
+            if height > 0 {
+                confirmed += coin.txout.value;
+            } else {
+                pending += coin.txout.value;
+            }
+        }
+        let pending = match pending {
+            0 => None,
+            _ => Some(Amount::from_sat(pending).into()),
+        };
+        Ok(BalanceOutput {
+            confirmed: Amount::from_sat(confirmed).into(),
+            pending,
+        })
+    }
+
+    fn list_unspent(&self, min_conf: u32) -> Result<Vec<Utxo>> {
+        let tip = self.client.tip_height()?;
+        let mut utxos = vec![];
+        for (coin, height) in self.wallet_coins()? {
+            let confirmations = match height {
+                0 => 0,
+                _ => (tip + 1).saturating_sub(height) as u32,
+            };
+            if confirmations >= min_conf {
+                utxos.push(Utxo {
+                    outpoint: coin.outpoint,
+                    amount: Amount::from_sat(coin.txout.value),
+                    script_pubkey: coin.txout.script_pubkey,
+                    confirmations,
+                    derivation: None,
+                });
+            }
+        }
+        Ok(utxos)
+    }
+
+    fn transaction(&self, txid: &Txid) -> Result<Transaction> {
+        let tx = self
+            .client
+            .call("blockchain.transaction.get", json!([txid.to_string()]))?;
+        checked_transaction(txid, &tx)
+    }
+
+    fn fee_rate(&self, conf_target: u16) -> Result<f64> {
+        let btc_per_kvb = self
+            .client
+            .call("blockchain.estimatefee", json!([conf_target]))?
+            .as_f64()
+            .unwrap_or(-1.0);
+        if btc_per_kvb <= 0.0 {
+            info!(
+                "server cannot estimate fee, using {} sat/vB",
+                FALLBACK_FEE_RATE
+            );
+            return Ok(FALLBACK_FEE_RATE);
+        }
+        Ok(btc_per_kvb * 100_000.0)
+    }
+
+    fn history(&self) -> Result<Vec<WalletTx>> {
+        let scripts: Vec<Script> = wallet_scripts(&self.wallet, &self.indexes, self.network)?
+            .into_iter()
+            .map(|(script, _, _)| script)
+            .collect();
+        let params: Vec<Value> = scripts.iter().map(|s| json!([script_hash(s)])).collect();
+        let mut heights: HashMap<Txid, i64> = HashMap::new();
+        for result in self
+            .client
+            .batch_call("blockchain.scripthash.get_history", &params)?
+        {
+            let entries: Vec<HistoryEntry> = serde_json::from_value(result)?;
+            for entry in entries {
+                heights.insert(entry.tx_hash, entry.height);
+            }
+        }
+        let txids: Vec<Txid> = heights.keys().cloned().collect();
+        let params: Vec<Value> = txids.iter().map(|t| json!([t.to_string()])).collect();
+        let mut txs = HashMap::new();
+        for (txid, result) in txids.iter().zip(
+            self.client
+                .batch_call("blockchain.transaction.get", &params)?,
+        ) {
+            txs.insert(*txid, checked_transaction(txid, &result)?);
+        }
+        let confirmed: Vec<u32> = heights
+            .values()
+            .filter(|h| **h > 0)
+            .map(|h| *h as u32)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let params: Vec<Value> = confirmed.iter().map(|h| json!([h])).collect();
+        let mut times = HashMap::new();
+        for (height, result) in confirmed
+            .iter()
+            .zip(self.client.batch_call("blockchain.block.header", &params)?)
+        {
+            let header: BlockHeader = deserialize(&hex::decode(result.as_str().unwrap_or(""))?)?;
+            times.insert(*height, header.time as u64);
+        }
+
+        let tip = self.client.tip_height()?;
+        let is_mine = |output: &TxOut| scripts.contains(&output.script_pubkey);
+        let prevout = |outpoint: &OutPoint| {
+            txs.get(&outpoint.txid)
+                .and_then(|tx| tx.output.get(outpoint.vout as usize))
+        };
+        let mut history = vec![];
+        for (txid, tx) in txs.iter() {
+            let received: u64 = tx
+                .output
+                .iter()
+                .filter(|o| is_mine(o))
+                .map(|o| o.value)
+                .sum();
+            let prevouts: Vec<_> = tx
+                .input
+                .iter()
+                .map(|i| prevout(&i.previous_output))
+                .collect();
+            let spent: u64 = prevouts
+                .iter()
+                .flatten()
+                .filter(|o| is_mine(o))
+                .map(|o| o.value)
+                .sum();
+            let all_mine = prevouts.iter().all(|o| o.map(is_mine).unwrap_or(false));
+            let fee = if all_mine {
+                let input_value: u64 = prevouts.iter().flatten().map(|o| o.value).sum();
+                let output_value: u64 = tx.output.iter().map(|o| o.value).sum();
+                Some(input_value.saturating_sub(output_value))
+            } else {
+                None
+            };
+            let height = Some(heights[txid]).filter(|h| *h > 0).map(|h| h as u32);
+            history.push(WalletTx {
+                txid: *txid,
+                net: received as i64 - spent as i64,
+                fee,
+                confirmations: height.map_or(0, |h| (tip + 1).saturating_sub(h as u64) as u32),
+                height,
+                time: height.and_then(|h| times.get(&h).cloned()),
+            });
+        }
+        Ok(history)
+    }
+
+    fn received(&self, scripts: &[Script]) -> Result<Vec<bool>> {
+        let params: Vec<Value> = scripts.iter().map(|s| json!([script_hash(s)])).collect();
+        let results = self
+            .client
+            .batch_call("blockchain.scripthash.get_history", &params)?;
+        Ok(results
+            .iter()
+            .map(|h| h.as_array().map(|h| !h.is_empty()).unwrap_or(false))
+            .collect())
+    }
+
+    fn is_address_reused(&self, address: &Address) -> Result<bool> {
+        // as in the node wallet, reused means a transaction paying `address` spends wallet coins
+        let history = self.client.call(
+            "blockchain.scripthash.get_history",
+            json!([script_hash(&address.script_pubkey())]),
+        )?;
+        let entries: Vec<HistoryEntry> = serde_json::from_value(history)?;
+        if entries.is_empty() {
+            return Ok(false);
+        }
+        let scripts: Vec<Script> = wallet_scripts(&self.wallet, &self.indexes, self.network)?
+            .into_iter()
+            .map(|(script, _, _)| script)
+            .collect();
+        for entry in entries {
+            let tx = self.transaction(&entry.tx_hash)?;
+            for input in tx.input.iter().filter(|i| !i.previous_output.is_null()) {
+                let outpoint = input.previous_output;
+                let prev_tx = self.transaction(&outpoint.txid)?;
+                if let Some(prevout) = prev_tx.output.get(outpoint.vout as usize) {
+                    if scripts.contains(&prevout.script_pubkey) {
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        let txid = self.client.call(
+            "blockchain.transaction.broadcast",
+            json!([hex::encode(serialize(tx))]),
+        )?;
+        Txid::from_str(txid.as_str().unwrap_or(""))
+            .map_err(|_| Error::Electrum(format!("invalid txid {}", txid)))
+    }
+
+    fn rescan(&self, _start_from: usize) -> Result<usize> {
+        // the server indexes the whole chain, there is nothing to rescan
+        Ok(self.client.tip_height()? as usize)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::offline::descriptor::{derive_address, DeriveAddressOptions};
+    use crate::online::backend::BackendKind;
+    use crate::online::backend::ChainBackend;
+    use crate::online::create_wallet::DEFAULT_IMPORT_RANGE;
+    use crate::online::electrum::{script_hash, ElectrumBackend, ElectrumClient};
+    use crate::online::fund_psbt::{fill_non_witness_utxos, fund_psbt, wallet_coins, FundRequest};
+    use crate::{DaemonOpts, Identifier, Kind, Wallet, WalletIndexes};
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::consensus::serialize;
+    use bitcoin::{Address, Network, OutPoint, Script, Transaction, TxIn, TxOut, Txid};
+    use serde_json::{json, Value};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::thread;
+
+    /// electrum server answering with `handler(method, params)`, returns its address
+    pub fn mock_server<F>(handler: F) -> String
+    where
+        F: Fn(&str, &Value) -> Value + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handler = Arc::new(handler);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let handler = handler.clone();
+                thread::spawn(move || {
+                    let mut stream = stream.unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                        let request: Value = serde_json::from_str(&line).unwrap();
+                        let answer = |r: &Value| {
+                            let method = r["method"].as_str().unwrap();
+                            let result = match method {
+                                "server.version" => json!(["mock", "1.4"]),
+                                "blockchain.block.header" => json!(hex::encode(serialize(
+                                    &genesis_block(Network::Testnet).header
+                                ))),
+                                _ => handler(method, &r["params"]),
+                            };
+                            json!({"jsonrpc": "2.0", "id": r["id"], "result": result})
+                        };
+                        let response = match &request {
+                            Value::Array(requests) => {
+                                Value::Array(requests.iter().map(answer).collect())
+                            }
+                            request => answer(request),
+                        };
+                        let mut response = serde_json::to_vec(&response).unwrap();
+                        response.push(b'\n');
+                        stream.write_all(&response).unwrap();
+                        line.clear();
+                    }
+                });
+            }
+        });
+        address
+    }
+
+    #[test]
+    fn test_electrum_backend() {
+        let descriptor = "wsh(multi(1,[a2ebe04e/48'/1'/0'/2']tpubDEXDRpvW2srXCSjAvC36zYkSE3jxT1wf7JXDo35Ln4NZpmaMNhq8o9coH9U9BQ5bAN4WDGxXV9d426iYKGorFF5wvv4Wv63cZsCotiXGGkD/0/*))";
+        let wallet = Wallet::new(descriptor, Network::Testnet);
+        let indexes = WalletIndexes {
+            id: Identifier::new(Network::Testnet, Kind::WalletIndexes, "electrum"),
+            main: 2,
+            change: 0,
+            imported: DEFAULT_IMPORT_RANGE,
+        };
+        let opts = DeriveAddressOptions {
+            descriptor: descriptor.to_string(),
+            index: 1,
+        };
+        let address = derive_address(Network::Testnet, &opts).unwrap().address;
+        let funded = script_hash(&address.script_pubkey());
+        let prev_tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: 100000,
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        let prev_txid = prev_tx.txid();
+        let prev_hex = hex::encode(serialize(&prev_tx));
+        let paid = Address::p2wsh(&Script::new(), Network::Testnet);
+        let paid_hash = script_hash(&paid.script_pubkey());
+        let spend_tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(prev_txid, 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: 90000,
+                script_pubkey: paid.script_pubkey(),
+            }],
+        };
+        let spend_txid = spend_tx.txid();
+        let spend_hex = hex::encode(serialize(&spend_tx));
+
+        let url = mock_server(move |method, params| match method {
+            "blockchain.headers.subscribe" => json!({"height": 110, "hex": ""}),
+            "blockchain.estimatefee" => json!(0.00002),
+            "blockchain.scripthash.listunspent" if params[0] == funded => json!([
+                {"tx_hash": prev_txid.to_string(), "tx_pos": 0, "height": 101, "value": 100000},
+                {"tx_hash": "f2b1a1fb2fe6d0b07ad33a8e2f1e5e2b0f6d0ce0f2a7b2ce3a4c7b3cfa0d0ac2", "tx_pos": 1, "height": 0, "value": 5000},
+            ]),
+            "blockchain.scripthash.listunspent" => json!([]),
+            "blockchain.scripthash.get_history" if params[0] == funded => {
+                json!([{"tx_hash": prev_txid.to_string(), "height": 101}])
+            }
+            "blockchain.scripthash.get_history" if params[0] == paid_hash => {
+                json!([{"tx_hash": spend_txid.to_string(), "height": 0}])
+            }
+            "blockchain.scripthash.get_history" => json!([]),
+            "blockchain.transaction.get" if params[0] == spend_txid.to_string() => {
+                json!(spend_hex)
+            }
+            "blockchain.transaction.get" if params[0] == prev_txid.to_string() => {
+                json!(prev_hex)
+            }
+            // a lying server, answering with a different transaction
+            "blockchain.transaction.get" => json!(prev_hex),
+            _ => Value::Null,
+        });
+
+        let daemon_opts = DaemonOpts {
+            url,
+            cookie_file: None,
+            backend: BackendKind::Electrum,
+            insecure: false,
+        };
+        let backend =
+            ElectrumBackend::new(&daemon_opts, Network::Testnet, wallet, indexes).unwrap();
+        let balance = backend.balance().unwrap();
+        assert_eq!(balance.confirmed.satoshi, 100000);
+        assert_eq!(balance.pending.unwrap().satoshi, 5000);
+
+        let utxos = backend.list_unspent(0).unwrap();
+        assert_eq!(utxos.len(), 2);
+        let utxos = backend.list_unspent(1).unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].confirmations, 10);
+
+        let recipient: Address = "tb1q5nrregep899vnvaa5vdpxcwg8794jqy38nu304kl4d7wm4e92yeqz4jfmk"
+            .parse()
+            .unwrap();
+        let coins = wallet_coins(&backend.wallet, &backend.indexes, &utxos).unwrap();
+        assert_eq!(coins.len(), 1);
+        assert_eq!((coins[0].chain, coins[0].index), (0, 1));
+        let req = FundRequest {
+            wallet: &backend.wallet,
+            coins: &coins,
+            selected: &[],
+            outputs: vec![TxOut {
+                value: 50_000,
+                script_pubkey: recipient.script_pubkey(),
+            }],
+            change_chain: 1,
+            change_index: 0,
+            fee_rate: backend.fee_rate(6).unwrap(),
+            replaceable: false,
+            subtract_fee_from: None,
+        };
+        assert_eq!(req.fee_rate, 2.0);
+        let mut psbt = fund_psbt(&req).unwrap();
+        fill_non_witness_utxos(&mut psbt, &backend).unwrap();
+        let input = &psbt.inputs[0];
+        assert_eq!(input.non_witness_utxo.as_ref().unwrap().txid(), prev_txid);
+        assert_eq!(
+            psbt.global.unsigned_tx.input[0].previous_output.txid,
+            prev_txid
+        );
+        assert!(!backend.is_address_reused(&recipient).unwrap());
+        assert!(
+            !backend.is_address_reused(&address).unwrap(),
+            "received only, the wallet didn't send to it"
+        );
+        assert!(backend.is_address_reused(&paid).unwrap());
+
+        let scripts = [address.script_pubkey(), recipient.script_pubkey()];
+        assert_eq!(backend.received(&scripts).unwrap(), vec![true, false]);
+
+        let history = backend.history().unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].txid, prev_txid);
+        assert_eq!(history[0].net, 100000);
+        assert_eq!(history[0].fee, None);
+        assert_eq!(history[0].confirmations, 10);
+        assert_eq!(history[0].height, Some(101));
+        let genesis_time = genesis_block(Network::Testnet).header.time as u64;
+        assert_eq!(history[0].time, Some(genesis_time));
+
+        let unknown_txid =
+            Txid::from_str("f2b1a1fb2fe6d0b07ad33a8e2f1e5e2b0f6d0ce0f2a7b2ce3a4c7b3cfa0d0ac2")
+                .unwrap();
+        let err = backend.transaction(&unknown_txid);
+        assert!(err.err().unwrap().to_string().contains("instead of"));
+
+        assert_eq!(backend.rescan(0).unwrap(), 110);
+        assert!(ElectrumBackend::new(
+            &daemon_opts,
+            Network::Bitcoin,
+            Wallet::new(descriptor, Network::Testnet),
+            backend.indexes.clone(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_electrum_insecure() {
+        let err = ElectrumClient::new("192.0.2.1:50001", Network::Testnet, false)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("--insecure"));
+        assert!(ElectrumClient::new("ssl://127.0.0.1:50002", Network::Testnet, true).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use crate::offline::descriptor::{derive_address, DeriveAddressOptions};
+use crate::online::backend::{BackendKind, ChainBackend};
+use crate::online::create_wallet::{import_descriptor, DEFAULT_IMPORT_RANGE};
+use crate::*;
+use log::info;
+use structopt::StructOpt;
+
+/// when the address given out is closer than this to the end of the imported range, the range is
+/// extended
+pub const EXTEND_MARGIN: u32 = 100;
+
+#[derive(StructOpt, Debug)]
+pub struct ExtendRangeOptions {
+    /// The name of the wallet
+    #[structopt(long = "wallet-name")]
+    pub wallet_name: String,
+
+    /// New last derivation index imported in the node for the receive and change chains
+    #[structopt(long)]
+    pub to: u32,
+}
+
+/// the new end of the imported range if `index` is too close to the `imported` one
+pub fn extended_range(index: u32, imported: u32) -> Option<u32> {
+    if index.saturating_add(EXTEND_MARGIN) > imported {
+        Some(index.saturating_add(DEFAULT_IMPORT_RANGE))
+    } else {
+        None
+    }
+}
+
+impl OnlineContext {
+    /// import the wallet descriptors in the node up to index `to`, funds already received by the
+    /// new addresses are found with `rescan`
+    pub fn extend_range(&self, opt: &ExtendRangeOptions) -> Result<WalletIndexes> {
+        if self.read_daemon_opts()?.backend != BackendKind::Bitcoind {
+            return Err("the import range is used only with the bitcoind backend".into());
+        }
+        let mut indexes: WalletIndexes = self.read(&opt.wallet_name)?;
+        if opt.to <= indexes.imported {
+            return Err(format!("index {} is already imported", indexes.imported).into());
+        }
+        self.import_up_to(&opt.wallet_name, &mut indexes, opt.to)?;
+        Ok(indexes)
+    }
+
+    /// import the wallet descriptors in the node from the end of the imported range to `to`
+    /// and save the new range in `indexes`
+    pub(crate) fn import_up_to(
+        &self,
+        wallet_name: &str,
+        indexes: &mut WalletIndexes,
+        to: u32,
+    ) -> Result<()> {
+        info!("Extending imported range of {} to {}", wallet_name, to);
+        let wallet: Wallet = self.read(wallet_name)?;
+        let client = self.make_client(wallet_name)?;
+        let range = (indexes.imported + 1, to);
+        let receive = wallet.receive_descriptor()?;
+        import_descriptor(&client, self.network, &receive, false, range)?;
+        let change = wallet.change_descriptor()?;
+        // wallets created before the change chain was introduced don't have it imported
+        let derive_opts = DeriveAddressOptions {
+            descriptor: change.clone(),
+            index: 0,
+        };
+        let first_change = derive_address(self.network, &derive_opts)?.address;
+        let change_range = if ChainBackend::is_watched(&client, &first_change)? {
+            range
+        } else {
+            info!("Importing the change chain of {} from 0", wallet_name);
+            (0, to)
+        };
+        import_descriptor(&client, self.network, &change, true, change_range)?;
+        indexes.imported = to;
+        self.write(indexes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::online::extend_range::extended_range;
+
+    #[test]
+    fn test_extended_range() {
+        assert_eq!(extended_range(0, 1000), None);
+        assert_eq!(extended_range(900, 1000), None);
+        assert_eq!(extended_range(901, 1000), Some(1901));
+        assert_eq!(extended_range(1500, 1000), Some(2500));
+        assert_eq!(extended_range(u32::MAX, 1000), Some(u32::MAX));
+    }
+}
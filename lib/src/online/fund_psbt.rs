@@ -0,0 +1,657 @@
+use crate::offline::descriptor::{
+    derive_address, parse_descriptor_with_checksum, DeriveAddressOptions,
+};
+use crate::offline::taproot::{is_taproot, is_v1_p2tr, TrMulti};
+use crate::online::backend::{ChainBackend, Utxo};
+use crate::*;
+use bitcoin::consensus::serialize;
+use bitcoin::secp256k1::{schnorrsig, Secp256k1};
+use bitcoin::util::bip32::KeySource;
+use bitcoin::util::psbt::{Input, Output};
+use bitcoin::{Network, OutPoint, Script, Transaction, TxIn, TxOut};
+use log::{debug, info};
+use miniscript::descriptor::ShInner;
+use miniscript::{Descriptor, DescriptorPublicKey, DescriptorTrait, ForEachKey, TranslatePk2};
+
+/// weight of outpoint, sequence and script_sig length of an input
+const TXIN_BASE_WEIGHT: usize = (32 + 4 + 4) * 4;
+
+/// public keys with their origin
+type KeySources = Vec<(bitcoin::PublicKey, KeySource)>;
+
+/// scripts derived after the last used index of the wallet chains
+pub const GAP_LIMIT: u32 = 20;
+
+/// max number of branches explored by the branch and bound selection
+const BNB_TRIES: usize = 100_000;
+
+/// sequence enabling the locktime without signaling replaceability
+const SEQUENCE: u32 = 0xFFFF_FFFE;
+
+/// sequence signaling replaceability (BIP125)
+pub const SEQUENCE_RBF: u32 = 0xFFFF_FFFD;
+
+/// An unspent output of the wallet with the derivation of its script
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalletCoin {
+    pub outpoint: OutPoint,
+    pub txout: TxOut,
+    /// 0 for the receive chain, 1 for the change chain
+    pub chain: u32,
+    pub index: u32,
+}
+
+/// A PSBT to be funded with the coins of `wallet`
+pub struct FundRequest<'a> {
+    pub wallet: &'a Wallet,
+    /// spendable coins of the wallet
+    pub coins: &'a [WalletCoin],
+    /// coins that must be spent, if empty they are selected from `coins`
+    pub selected: &'a [OutPoint],
+    pub outputs: Vec<TxOut>,
+    /// chain of the change address, 1 unless the change chain isn't watched
+    pub change_chain: u32,
+    /// index of the change address in `change_chain`
+    pub change_index: u32,
+    /// fee rate in satoshi per virtual byte
+    pub fee_rate: f64,
+    /// signal replaceability of the transaction (BIP125)
+    pub replaceable: bool,
+    /// index in `outputs` of the output paying the fee, instead of the inputs
+    pub subtract_fee_from: Option<usize>,
+}
+
+/// build an unsigned PSBT spending the given coins or selecting them, preferring a branch and
+/// bound selection without change and falling back to largest first, inputs and change output
+/// contain what is needed by the offline signers
+pub fn fund_psbt(req: &FundRequest) -> Result<BitcoinPsbt> {
+    let network = req.wallet.id.network;
+    let change_descriptor = chain_descriptor(req.wallet, req.change_chain)?;
+    let change_script = derive_address(
+        network,
+        &DeriveAddressOptions {
+            descriptor: change_descriptor,
+            index: req.change_index,
+        },
+    )?
+    .address
+    .script_pubkey();
+
+    let candidates: Vec<&WalletCoin> = if req.selected.is_empty() {
+        let mut coins: Vec<_> = req.coins.iter().collect();
+        coins.sort_by_key(|c| std::cmp::Reverse(c.txout.value));
+        coins
+    } else {
+        req.selected
+            .iter()
+            .map(|outpoint| {
+                req.coins
+                    .iter()
+                    .find(|c| &c.outpoint == outpoint)
+                    .ok_or_else(|| Error::Generic(format!("coin {} not in the wallet", outpoint)))
+            })
+            .collect::<Result<_>>()?
+    };
+
+    let mut tx = Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![],
+        output: req.outputs.clone(),
+    };
+    let target: u64 = req.outputs.iter().map(|o| o.value).sum();
+    let base_weight = tx.get_weight() + 2; // segwit marker and flag
+    let input_weight = TXIN_BASE_WEIGHT + satisfaction_weight(&req.wallet.descriptor)?;
+    let change_output = TxOut {
+        value: 0,
+        script_pubkey: change_script.clone(),
+    };
+    let change_weight = serialize(&change_output).len() * 4;
+    let fee = |weight: usize| (((weight + 3) / 4) as f64 * req.fee_rate).ceil() as u64;
+
+    let subtract = req.subtract_fee_from.is_some();
+    // when the fee is paid by an output, the coins must cover only the outputs
+    let inputs_fee = |weight: usize| if subtract { 0 } else { fee(weight) };
+
+    let bnb = if req.selected.is_empty() && !subtract {
+        // creating the change and spending it later
+        let cost_of_change = fee(change_weight) + fee(input_weight);
+        let values: Vec<u64> = candidates
+            .iter()
+            .map(|c| c.txout.value.saturating_sub(fee(input_weight)))
+            .collect();
+        branch_and_bound(&values, target + fee(base_weight), cost_of_change)
+    } else {
+        None
+    };
+
+    let (selected, with_change) = match bnb {
+        Some(indexes) => (indexes.iter().map(|i| candidates[*i]).collect(), false),
+        None => (
+            largest_first(
+                &candidates,
+                req,
+                |n| inputs_fee(base_weight + n * input_weight),
+                target,
+            ),
+            true,
+        ),
+    };
+    let total: u64 = selected.iter().map(|c| c.txout.value).sum();
+
+    let weight = base_weight + selected.len() * input_weight;
+    let needed = target + inputs_fee(weight);
+    if total < needed {
+        return Err(Error::InsufficientFunds {
+            available: total,
+            needed,
+        });
+    }
+    let change = total.saturating_sub(target + inputs_fee(weight + change_weight));
+    let has_change = with_change && change > change_script.dust_value().as_sat();
+    if has_change {
+        tx.output.push(TxOut {
+            value: change,
+            script_pubkey: change_script,
+        });
+    }
+    debug!(
+        "selected {} coins, total {} change {} bnb {}",
+        selected.len(),
+        total,
+        change,
+        !with_change
+    );
+
+    let sequence = if req.replaceable {
+        SEQUENCE_RBF
+    } else {
+        SEQUENCE
+    };
+    tx.input = selected
+        .iter()
+        .map(|coin| TxIn {
+            previous_output: coin.outpoint,
+            sequence,
+            ..Default::default()
+        })
+        .collect();
+
+    let mut psbt = BitcoinPsbt::from_unsigned_tx(tx)?;
+    for (input, coin) in psbt.inputs.iter_mut().zip(selected.iter()) {
+        fill_input(req.wallet, coin, input)?;
+    }
+    if has_change {
+        let output = psbt.outputs.last_mut().expect("change output");
+        fill_output(req.wallet, req.change_chain, req.change_index, output)?;
+    }
+
+    // the fee is set on the same weight estimation used when printing the PSBT, so that the
+    // printed fee rate is the requested one, taproot inputs aren't supported by the estimation
+    let estimated = match estimate_weight(&psbt) {
+        Ok(estimated) => estimated,
+        Err(_) => weight + if has_change { change_weight } else { 0 },
+    };
+    let final_fee = fee(estimated);
+    let tx = &mut psbt.global.unsigned_tx;
+    if let Some(index) = req.subtract_fee_from {
+        let output = tx
+            .output
+            .get_mut(index)
+            .ok_or_else(|| Error::Generic(format!("no output at index {}", index)))?;
+        output.value = output
+            .value
+            .checked_sub(final_fee)
+            .filter(|value| *value > output.script_pubkey.dust_value().as_sat())
+            .ok_or_else(|| Error::Generic(format!("output {} too small to pay the fee", index)))?;
+        if has_change {
+            tx.output.last_mut().expect("change output").value = total - target;
+        }
+    } else if has_change {
+        let needed = target + final_fee;
+        tx.output.last_mut().expect("change output").value =
+            total.checked_sub(needed).ok_or(Error::InsufficientFunds {
+                available: total,
+                needed,
+            })?;
+    }
+    Ok(psbt)
+}
+
+/// coins in the order of `candidates` until `target` plus the fee for the number of inputs
+/// is reached, all of them if the coins are chosen by the user
+fn largest_first<'a, F>(
+    candidates: &[&'a WalletCoin],
+    req: &FundRequest,
+    fee: F,
+    target: u64,
+) -> Vec<&'a WalletCoin>
+where
+    F: Fn(usize) -> u64,
+{
+    let mut selected = vec![];
+    let mut total = 0u64;
+    for coin in candidates {
+        selected.push(*coin);
+        total += coin.txout.value;
+        if req.selected.is_empty() && total >= target + fee(selected.len()) {
+            break;
+        }
+    }
+    selected
+}
+
+/// depth first search of the `values` combination between `target` and
+/// `target + cost_of_change`, so that no change is needed, with the least excess
+struct BranchAndBound<'a> {
+    values: &'a [u64],
+    target: u64,
+    upper: u64,
+    tries: usize,
+    current: Vec<usize>,
+    best: Option<(u64, Vec<usize>)>,
+}
+
+impl BranchAndBound<'_> {
+    fn search(&mut self, index: usize, sum: u64, remaining: u64) {
+        if self.tries == 0 || sum > self.upper {
+            return;
+        }
+        self.tries -= 1;
+        if sum >= self.target {
+            let excess = sum - self.target;
+            let better = match self.best.as_ref() {
+                Some((best, _)) => excess < *best,
+                None => true,
+            };
+            if better {
+                self.best = Some((excess, self.current.clone()));
+            }
+            return;
+        }
+        if index == self.values.len() || sum + remaining < self.target {
+            return;
+        }
+        let value = self.values[index];
+        self.current.push(index);
+        self.search(index + 1, sum + value, remaining - value);
+        self.current.pop();
+        self.search(index + 1, sum, remaining - value);
+    }
+}
+
+/// indexes of `values`, sorted descending, summing to `target` without exceeding it by more
+/// than `cost_of_change`
+fn branch_and_bound(values: &[u64], target: u64, cost_of_change: u64) -> Option<Vec<usize>> {
+    let mut bnb = BranchAndBound {
+        values,
+        target,
+        upper: target + cost_of_change,
+        tries: BNB_TRIES,
+        current: vec![],
+        best: None,
+    };
+    bnb.search(0, 0, values.iter().sum());
+    bnb.best.map(|(_, selection)| selection)
+}
+
+/// wallet scripts with their chain and index, up to the gap limit after the wallet indexes
+pub fn wallet_scripts(
+    wallet: &Wallet,
+    indexes: &WalletIndexes,
+    network: Network,
+) -> Result<Vec<(Script, u32, u32)>> {
+    let mut scripts = vec![];
+    for (chain, descriptor, last) in [
+        (0, wallet.receive_descriptor()?, indexes.main),
+        (1, wallet.change_descriptor()?, indexes.change),
+    ] {
+        for index in 0..last.saturating_add(GAP_LIMIT) {
+            let opts = DeriveAddressOptions {
+                descriptor: descriptor.clone(),
+                index,
+            };
+            let address = derive_address(network, &opts)?.address;
+            scripts.push((address.script_pubkey(), chain, index));
+        }
+    }
+    Ok(scripts)
+}
+
+/// the wallet script at `index` of `chain`
+pub fn wallet_script(wallet: &Wallet, chain: u32, index: u32) -> Result<Script> {
+    let opts = DeriveAddressOptions {
+        descriptor: chain_descriptor(wallet, chain)?,
+        index,
+    };
+    Ok(derive_address(wallet.id.network, &opts)?
+        .address
+        .script_pubkey())
+}
+
+/// the `utxos` with a script of the wallet, with their chain and index. Scripts beyond the gap
+/// limit are matched with the derivation reported by the backend, like the ones of addresses
+/// given out with an explicit index
+pub fn wallet_coins(
+    wallet: &Wallet,
+    indexes: &WalletIndexes,
+    utxos: &[Utxo],
+) -> Result<Vec<WalletCoin>> {
+    let scripts = wallet_scripts(wallet, indexes, wallet.id.network)?;
+    let mut coins = vec![];
+    for utxo in utxos {
+        let derivation = match scripts.iter().find(|(s, _, _)| *s == utxo.script_pubkey) {
+            Some((_, chain, index)) => Some((*chain, *index)),
+            None => match utxo.derivation {
+                Some((chain, index)) if chain < 2 => {
+                    let script = wallet_script(wallet, chain, index)?;
+                    Some((chain, index)).filter(|_| script == utxo.script_pubkey)
+                }
+                _ => None,
+            },
+        };
+        match derivation {
+            Some((chain, index)) => coins.push(WalletCoin {
+                outpoint: utxo.outpoint,
+                txout: TxOut {
+                    value: utxo.amount.as_sat(),
+                    script_pubkey: utxo.script_pubkey.clone(),
+                },
+                chain,
+                index,
+            }),
+            None => info!(
+                "coin {} not derived from the wallet, skipping",
+                utxo.outpoint
+            ),
+        }
+    }
+    Ok(coins)
+}
+
+/// fill the previous transactions of the inputs, needed by the signers of segwit v0 inputs
+pub fn fill_non_witness_utxos(psbt: &mut BitcoinPsbt, backend: &dyn ChainBackend) -> Result<()> {
+    for (input, txin) in psbt
+        .inputs
+        .iter_mut()
+        .zip(psbt.global.unsigned_tx.input.iter())
+    {
+        let is_taproot = match input.witness_utxo.as_ref() {
+            Some(utxo) => is_v1_p2tr(&utxo.script_pubkey),
+            None => false,
+        };
+        if !is_taproot {
+            input.non_witness_utxo = Some(backend.transaction(&txin.previous_output.txid)?);
+        }
+    }
+    Ok(())
+}
+
+fn chain_descriptor(wallet: &Wallet, chain: u32) -> Result<String> {
+    match chain {
+        0 => wallet.receive_descriptor(),
+        _ => wallet.change_descriptor(),
+    }
+}
+
+/// witness weight satisfying an input of the wallet, signatures are considered at max size
+fn satisfaction_weight(descriptor: &str) -> Result<usize> {
+    if is_taproot(descriptor) {
+        let tr_multi: TrMulti = descriptor.parse()?;
+        let keys = tr_multi.keys.len();
+        let script = keys * 34 + 3;
+        // stack items, signatures, empty pushes for missing ones, leaf script and control block
+        Ok(1 + tr_multi.required_sigs * 66 + (keys - tr_multi.required_sigs) + 3 + script + 34)
+    } else {
+        Ok(parse_descriptor_with_checksum(descriptor)?.max_satisfaction_weight()?)
+    }
+}
+
+/// the public keys of `keys` derived at `index` with their origin
+fn key_sources(keys: &[DescriptorPublicKey], index: u32) -> Result<KeySources> {
+    let secp = Secp256k1::verification_only();
+    keys.iter()
+        .map(|key| {
+            let derived = key.clone().derive(index);
+            let public_key = derived.derive_public_key(&secp)?;
+            let source = match &derived {
+                DescriptorPublicKey::XPub(xpub) => match &xpub.origin {
+                    Some((fingerprint, path)) => (*fingerprint, path.extend(&xpub.derivation_path)),
+                    None => (xpub.xkey.fingerprint(), xpub.derivation_path.clone()),
+                },
+                DescriptorPublicKey::SinglePub(single) => {
+                    single.origin.clone().ok_or(Error::MissingKey)?
+                }
+            };
+            Ok((public_key, source))
+        })
+        .collect()
+}
+
+/// witness script, redeem script and key sources of the wallet script at `index`
+fn script_fields(
+    descriptor: &str,
+    index: u32,
+) -> Result<(Option<Script>, Option<Script>, KeySources)> {
+    let descriptor = parse_descriptor_with_checksum(descriptor)?;
+    let mut keys = vec![];
+    descriptor.for_each_key(|k| {
+        keys.push(k.as_key().clone());
+        true
+    });
+    let secp = Secp256k1::verification_only();
+    let derived = descriptor
+        .derive(index)
+        .translate_pk2(|xpk| xpk.derive_public_key(&secp))?;
+    let script = derived.explicit_script();
+    let (witness_script, redeem_script) = match &derived {
+        Descriptor::Wsh(_) => (Some(script), None),
+        Descriptor::Sh(sh) => match sh.as_inner() {
+            ShInner::Wsh(_) => (Some(script.clone()), Some(script.to_v0_p2wsh())),
+            _ => (None, Some(script)),
+        },
+        _ => (None, None),
+    };
+    Ok((witness_script, redeem_script, key_sources(&keys, index)?))
+}
+
+/// fill the fields needed by the signers to sign an input spending `coin`
+pub fn fill_input(wallet: &Wallet, coin: &WalletCoin, input: &mut Input) -> Result<()> {
+    let descriptor = chain_descriptor(wallet, coin.chain)?;
+    input.witness_utxo = Some(coin.txout.clone());
+    if is_taproot(&descriptor) {
+        let tr_multi: TrMulti = descriptor.parse()?;
+        let secp = Secp256k1::verification_only();
+        let derived = tr_multi.derive(&secp, coin.index)?;
+        let sources = key_sources(&tr_multi.keys, coin.index)?;
+        // derived keys may be sorted, origins must follow the same order
+        let origins = derived
+            .keys
+            .iter()
+            .map(|key| {
+                sources
+                    .iter()
+                    .find(|(public_key, _)| schnorrsig::PublicKey::from(public_key.key) == *key)
+                    .map(|(_, source)| source.clone())
+                    .ok_or(Error::MissingKey)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        derived.fill_input(input, &origins);
+    } else {
+        let (witness_script, redeem_script, sources) = script_fields(&descriptor, coin.index)?;
+        input.witness_script = witness_script;
+        input.redeem_script = redeem_script;
+        input.bip32_derivation = sources.into_iter().collect();
+    }
+    Ok(())
+}
+
+/// fill the fields identifying an output to the wallet at `index` of `chain`, taproot outputs
+/// are left untouched
+pub fn fill_output(wallet: &Wallet, chain: u32, index: u32, output: &mut Output) -> Result<()> {
+    let descriptor = chain_descriptor(wallet, chain)?;
+    if !is_taproot(&descriptor) {
+        let (witness_script, redeem_script, sources) = script_fields(&descriptor, index)?;
+        output.witness_script = witness_script;
+        output.redeem_script = redeem_script;
+        output.bip32_derivation = sources.into_iter().collect();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::offline::descriptor::{derive_address, DeriveAddressOptions};
+    use crate::offline::print::pretty_print;
+    use crate::online::backend::Utxo;
+    use crate::online::create_wallet::DEFAULT_IMPORT_RANGE;
+    use crate::online::fund_psbt::{
+        branch_and_bound, fund_psbt, wallet_coins, FundRequest, WalletCoin, SEQUENCE_RBF,
+    };
+    use crate::{estimate_weight, Error, Identifier, Kind, Wallet, WalletIndexes};
+    use bitcoin::{Amount, Network, OutPoint, TxOut, Txid};
+    use std::str::FromStr;
+
+    pub const DESCRIPTOR: &str = "wsh(multi(2,[a2ebe04e/48'/1'/0'/2']tpubDEXDRpvW2srXCSjAvC36zYkSE3jxT1wf7JXDo35Ln4NZpmaMNhq8o9coH9U9BQ5bAN4WDGxXV9d426iYKGorFF5wvv4Wv63cZsCotiXGGkD/0/*,[1f5e43d8/48'/1'/0'/2']tpubDFU4parcXvV8tBYt4rS4a8rGNF1DA32DCnRfhzVL6b3MSiDomV95rv9mb7W7jAPMTohyEYpbhVS8FbmTsuQsFRxDWPJX2ZFEeRPMFz3R1gh/0/*))";
+
+    pub fn coin(wallet: &Wallet, vout: u32, value: u64, index: u32) -> WalletCoin {
+        let opts = DeriveAddressOptions {
+            descriptor: wallet.descriptor.clone(),
+            index,
+        };
+        let address = derive_address(Network::Testnet, &opts).unwrap().address;
+        WalletCoin {
+            outpoint: OutPoint::new(Txid::default(), vout),
+            txout: TxOut {
+                value,
+                script_pubkey: address.script_pubkey(),
+            },
+            chain: 0,
+            index,
+        }
+    }
+
+    #[test]
+    fn test_fund_psbt() {
+        let wallet = Wallet::new(DESCRIPTOR, Network::Testnet);
+        let coins = vec![
+            coin(&wallet, 0, 10_000, 0),
+            coin(&wallet, 1, 50_000, 1),
+            coin(&wallet, 2, 20_000, 2),
+        ];
+        let recipient = coin(&wallet, 9, 40_000, 9).txout;
+        let mut req = FundRequest {
+            wallet: &wallet,
+            coins: &coins,
+            selected: &[],
+            outputs: vec![recipient],
+            change_chain: 1,
+            change_index: 0,
+            fee_rate: 2.0,
+            replaceable: false,
+            subtract_fee_from: None,
+        };
+        let psbt = fund_psbt(&req).unwrap();
+        let tx = &psbt.global.unsigned_tx;
+        assert_eq!(tx.input.len(), 1, "largest coin is enough");
+        assert_eq!(tx.input[0].previous_output.vout, 1);
+        assert_eq!(tx.output.len(), 2);
+        let input = &psbt.inputs[0];
+        assert!(input.witness_script.is_some());
+        assert_eq!(input.bip32_derivation.len(), 2);
+        let (_, path) = input.bip32_derivation.values().next().unwrap();
+        assert!(path.to_string().ends_with("/0/1"));
+        assert_eq!(psbt.outputs[1].bip32_derivation.len(), 2);
+
+        let fee = 50_000 - tx.output.iter().map(|o| o.value).sum::<u64>();
+        let vsize = (estimate_weight(&psbt).unwrap() + 3) as u64 / 4;
+        assert_eq!(fee, vsize * 2);
+        let rate = pretty_print(&psbt, Network::Testnet, &[]).unwrap().fee.rate;
+        assert_eq!(rate, Some(2.0));
+        assert!(tx.input.iter().all(|i| i.sequence == 0xFFFF_FFFE));
+
+        req.replaceable = true;
+        req.subtract_fee_from = Some(0);
+        let psbt = fund_psbt(&req).unwrap();
+        let tx = &psbt.global.unsigned_tx;
+        assert!(tx.input.iter().all(|i| i.sequence == SEQUENCE_RBF));
+        let vsize = (estimate_weight(&psbt).unwrap() + 3) as u64 / 4;
+        assert_eq!(tx.output[0].value, 40_000 - vsize * 2);
+        assert_eq!(tx.output[1].value, 10_000);
+        req.replaceable = false;
+        req.subtract_fee_from = None;
+
+        let selected = [coins[2].outpoint, coins[1].outpoint];
+        req.selected = &selected;
+        let psbt = fund_psbt(&req).unwrap();
+        assert_eq!(psbt.global.unsigned_tx.input.len(), 2);
+        req.selected = &[];
+
+        // 20_000 and 10_000 coins match the target without change
+        req.outputs[0].value = 29_650;
+        req.fee_rate = 1.0;
+        let psbt = fund_psbt(&req).unwrap();
+        let tx = &psbt.global.unsigned_tx;
+        assert_eq!(tx.input.len(), 2);
+        assert_eq!(tx.output.len(), 1);
+        req.outputs[0].value = 40_000;
+
+        req.fee_rate = 1000.0;
+        assert_matches!(fund_psbt(&req), Err(Error::InsufficientFunds { .. }));
+
+        let unknown = [OutPoint::from_str(
+            "0000000000000000000000000000000000000000000000000000000000000001:0",
+        )
+        .unwrap()];
+        req.selected = &unknown;
+        assert!(fund_psbt(&req).is_err());
+    }
+
+    #[test]
+    fn test_wallet_coins() {
+        let wallet = Wallet::new(DESCRIPTOR, Network::Testnet);
+        let indexes = WalletIndexes {
+            id: Identifier::new(Network::Testnet, Kind::WalletIndexes, "coins"),
+            main: 0,
+            change: 0,
+            imported: DEFAULT_IMPORT_RANGE,
+        };
+        let utxo = |coin: &WalletCoin, derivation| Utxo {
+            outpoint: coin.outpoint,
+            amount: Amount::from_sat(coin.txout.value),
+            script_pubkey: coin.txout.script_pubkey.clone(),
+            confirmations: 1,
+            derivation,
+        };
+        let near = coin(&wallet, 0, 1_000, 3);
+        // an address given out with an explicit index, beyond the gap limit
+        let far = coin(&wallet, 1, 2_000, 500);
+        let utxos = vec![
+            utxo(&near, None),
+            utxo(&far, None),
+            utxo(&far, Some((0, 501))),
+            utxo(&far, Some((1, 500))),
+        ];
+        assert_eq!(
+            wallet_coins(&wallet, &indexes, &utxos).unwrap(),
+            vec![near.clone()]
+        );
+
+        let utxos = vec![utxo(&near, None), utxo(&far, Some((0, 500)))];
+        assert_eq!(
+            wallet_coins(&wallet, &indexes, &utxos).unwrap(),
+            vec![near, far]
+        );
+    }
+
+    #[test]
+    fn test_branch_and_bound() {
+        let values = [100, 60, 50, 30, 10];
+        assert_eq!(branch_and_bound(&values, 90, 0), Some(vec![1, 3]));
+        assert_eq!(branch_and_bound(&values, 85, 5), Some(vec![1, 3]));
+        assert_eq!(branch_and_bound(&values, 140, 0), Some(vec![0, 3, 4]));
+        assert_eq!(branch_and_bound(&values, 95, 3), None);
+        assert_eq!(branch_and_bound(&values, 300, 10), None);
+        assert_eq!(branch_and_bound(&[], 1, 10), None);
+    }
+}
@@ -1,11 +1,18 @@
 use crate::offline::descriptor::DeriveAddressOptions;
 use crate::offline::sign_wallet::verify_wallet_internal;
+use crate::online::backend::BackendKind;
+use crate::online::backend::ChainBackend;
+use crate::online::extend_range::extended_range;
 use crate::*;
 use bitcoin::secp256k1::Secp256k1;
-use bitcoincore_rpc::RpcApi;
 use log::info;
 use structopt::StructOpt;
 
+/// warning given when change is sent to the receive chain
+pub const CHANGE_NOT_WATCHED: &str =
+    "the change chain is not imported in the node, change is sent \
+to the receive chain, use `extend-range` to import it and `rescan` if it already received funds";
+
 #[derive(StructOpt, Debug, Default)]
 pub struct GetAddressOptions {
     /// The name of the wallet to be created
@@ -18,11 +25,15 @@ pub struct GetAddressOptions {
 
     #[structopt(long)]
     pub verify_wallet: bool,
+
+    /// Derive the address from the change chain `/1/*` using the change index
+    #[structopt(long)]
+    pub change: bool,
 }
 
 impl OnlineContext {
     pub fn get_address(&self, opt: &GetAddressOptions) -> Result<GetAddressOutput> {
-        let client = self.make_client(&opt.wallet_name)?;
+        let backend = self.make_backend(&opt.wallet_name)?;
         let wallet: Wallet = self.read(&opt.wallet_name)?;
         let mut indexes: WalletIndexes = self.read(&opt.wallet_name)?;
 
@@ -32,30 +43,79 @@ impl OnlineContext {
             let _ = verify_wallet_internal(&secp, &wallet, &wallet_signature, self.network)?;
         }
 
-        let index = opt.index.unwrap_or(indexes.main);
-        let descriptor = wallet.descriptor;
+        let (index, descriptor) = if opt.change {
+            (
+                opt.index.unwrap_or(indexes.change),
+                wallet.change_descriptor()?,
+            )
+        } else {
+            (
+                opt.index.unwrap_or(indexes.main),
+                wallet.receive_descriptor()?,
+            )
+        };
 
         info!("Creating address at index {} for {}", index, &descriptor);
 
-        let addresses = client.derive_addresses(&descriptor, Some([index, index]))?;
-
-        let address = addresses.first().ok_or(Error::MissingAddress)?.clone();
-        if address.network != self.network {
-            return Err("address returned is not on the same network as given".into());
+        let node_address = backend.derive_address(&descriptor, index)?;
+        if let Some(address) = node_address.as_ref() {
+            if address.network != self.network {
+                return Err("address returned is not on the same network as given".into());
+            }
+            info!("{}", address);
         }
-        info!("{}", address);
 
         let derive_opts = DeriveAddressOptions { descriptor, index };
         let derive_address =
             crate::offline::descriptor::derive_address(self.network, &derive_opts)?;
-        assert_eq!(
-            derive_address.address, address,
-            "address generated from the node differs from the one generated from miniscript"
-        );
+        if let Some(address) = node_address {
+            assert_eq!(
+                derive_address.address, address,
+                "address generated from the node differs from the one generated from miniscript"
+            );
+        }
 
-        indexes.main += 1;
+        if self.read_daemon_opts()?.backend == BackendKind::Bitcoind {
+            // addresses near the end of the range must be imported before giving them out
+            if let Some(to) = extended_range(index, indexes.imported) {
+                self.import_up_to(&opt.wallet_name, &mut indexes, to)?;
+            }
+        }
+
+        if opt.change {
+            indexes.change += 1;
+        } else {
+            indexes.main += 1;
+        }
         self.write(&indexes)?;
 
         Ok(derive_address)
     }
+
+    /// the next change address of the wallet with its chain, the receive chain is used when the
+    /// change chain isn't watched by the node, as in wallets created before change tracking
+    pub(crate) fn change_address(
+        &self,
+        wallet_name: &str,
+        backend: &dyn ChainBackend,
+    ) -> Result<(u32, GetAddressOutput)> {
+        let wallet: Wallet = self.read(wallet_name)?;
+        let derive_opts = DeriveAddressOptions {
+            descriptor: wallet.change_descriptor()?,
+            index: 0,
+        };
+        let first_change =
+            crate::offline::descriptor::derive_address(self.network, &derive_opts)?.address;
+        let change = backend.is_watched(&first_change)?;
+        if !change {
+            info!("change chain not watched by the node, using the receive chain");
+        }
+        let get_addr_opts = GetAddressOptions {
+            wallet_name: wallet_name.to_string(),
+            index: None,
+            verify_wallet: false,
+            change,
+        };
+        Ok((change as u32, self.get_address(&get_addr_opts)?))
+    }
 }
@@ -0,0 +1,211 @@
+use crate::*;
+use bitcoin::OutPoint;
+use log::debug;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct LabelOptions {
+    /// The name of the wallet
+    #[structopt(long = "wallet-name")]
+    pub wallet_name: String,
+
+    /// What is labeled: tx, addr, pubkey, input, output or xpub
+    #[structopt(long = "type")]
+    pub label_type: LabelType,
+
+    /// The txid, address, outpoint (txid:vout) or key labeled
+    #[structopt(long = "ref")]
+    pub reference: String,
+
+    /// The label
+    #[structopt(long)]
+    pub label: String,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct FreezeOptions {
+    /// The name of the wallet
+    #[structopt(long = "wallet-name")]
+    pub wallet_name: String,
+
+    /// Coin specified as txid:vout see list-coins
+    #[structopt(long)]
+    pub coin: OutPoint,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct LabelsFileOptions {
+    /// The name of the wallet
+    #[structopt(long = "wallet-name")]
+    pub wallet_name: String,
+
+    /// File in the BIP329 format, JSON lines
+    #[structopt(short, long)]
+    pub path: PathBuf,
+}
+
+impl Context {
+    /// the labels of the wallet, empty if none has been set yet
+    pub fn read_labels(&self, wallet_name: &str) -> Result<WalletLabels> {
+        let id = Identifier::new(self.network, Kind::WalletLabels, wallet_name);
+        if id.as_path_buf(&self.datadir, false)?.exists() {
+            self.read(wallet_name)
+        } else {
+            Ok(WalletLabels::new(self.network, wallet_name))
+        }
+    }
+
+    fn update_labels(&self, wallet_name: &str, labels: Vec<Label>) -> Result<WalletLabels> {
+        // checks the wallet exists
+        let _: Wallet = self.read(wallet_name)?;
+        let mut wallet_labels = self.read_labels(wallet_name)?;
+        for label in labels {
+            wallet_labels.set(label);
+        }
+        self.write(&wallet_labels)?;
+        Ok(wallet_labels)
+    }
+
+    pub fn label(&self, opt: &LabelOptions) -> Result<WalletLabels> {
+        let label = Label {
+            label_type: opt.label_type,
+            reference: opt.reference.to_string(),
+            label: Some(opt.label.to_string()),
+            origin: None,
+            spendable: None,
+        };
+        self.update_labels(&opt.wallet_name, vec![label])
+    }
+
+    /// mark the coin as not spendable, it is not selected by `create_tx` unless explicitly
+    /// given with `--coin`
+    pub fn freeze(&self, opt: &FreezeOptions, frozen: bool) -> Result<WalletLabels> {
+        let label = Label {
+            label_type: LabelType::Output,
+            reference: opt.coin.to_string(),
+            label: None,
+            origin: None,
+            spendable: Some(!frozen),
+        };
+        self.update_labels(&opt.wallet_name, vec![label])
+    }
+
+    pub fn import_labels(&self, opt: &LabelsFileOptions) -> Result<WalletLabels> {
+        let content = std::fs::read_to_string(&opt.path)?;
+        let labels = WalletLabels::from_bip329(&content)?;
+        debug!("importing {} labels", labels.len());
+        self.update_labels(&opt.wallet_name, labels)
+    }
+
+    pub fn export_labels(&self, opt: &LabelsFileOptions) -> Result<WalletLabels> {
+        let labels = self.read_labels(&opt.wallet_name)?;
+        std::fs::write(&opt.path, labels.to_bip329()?)?;
+        Ok(labels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::tests::TestContext;
+    use crate::online::fund_psbt::tests::DESCRIPTOR;
+    use crate::online::labels::{FreezeOptions, LabelsFileOptions};
+    use crate::{Error, Label, LabelType, Wallet, WalletLabels};
+    use bitcoin::{Network, OutPoint};
+    use std::str::FromStr;
+    use tempfile::NamedTempFile;
+
+    // from BIP329 test vectors
+    const BIP329: &str = r#"{ "type": "tx", "ref": "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd", "label": "Transaction", "origin": "wpkh([d34db33f/84'/0'/0'])" }
+{ "type": "addr", "ref": "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c", "label": "Address" }
+
+{ "type": "output", "ref": "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd:1", "label": "Output", "spendable" : false }
+"#;
+
+    #[test]
+    fn test_bip329() {
+        let labels = WalletLabels::from_bip329(BIP329).unwrap();
+        assert_eq!(labels.len(), 3);
+        let mut wallet_labels = WalletLabels::new(Network::Testnet, "labels");
+        for label in labels {
+            wallet_labels.set(label);
+        }
+        let outpoint = OutPoint::from_str(
+            "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd:1",
+        )
+        .unwrap();
+        assert!(wallet_labels.is_frozen(&outpoint));
+        assert_eq!(
+            wallet_labels.label(LabelType::Output, &outpoint.to_string()),
+            Some("Output")
+        );
+
+        wallet_labels.set(Label {
+            label_type: LabelType::Output,
+            reference: outpoint.to_string(),
+            label: None,
+            origin: None,
+            spendable: Some(true),
+        });
+        assert!(!wallet_labels.is_frozen(&outpoint));
+        assert_eq!(
+            wallet_labels.label(LabelType::Output, &outpoint.to_string()),
+            Some("Output"),
+            "unfreezing keeps the label"
+        );
+        assert_eq!(wallet_labels.labels.len(), 3);
+
+        let exported = wallet_labels.to_bip329().unwrap();
+        assert_eq!(exported.lines().count(), 3);
+        assert_eq!(
+            WalletLabels::from_bip329(&exported).unwrap(),
+            wallet_labels.labels
+        );
+        assert!(WalletLabels::from_bip329("{ \"type\": \"unknown\", \"ref\": \"\" }").is_err());
+    }
+
+    #[test]
+    fn test_labels_context() {
+        let context = TestContext::default();
+        let wallet = Wallet::new(DESCRIPTOR, Network::Testnet);
+        let name = wallet.id.name.to_string();
+        let file = NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), BIP329).unwrap();
+        let opt = LabelsFileOptions {
+            wallet_name: name.clone(),
+            path: file.path().to_path_buf(),
+        };
+        assert_matches!(
+            context.import_labels(&opt),
+            Err(Error::FileNotFoundOrCorrupt(_, _))
+        );
+        context.write(&wallet).unwrap();
+        assert!(context.read_labels(&name).unwrap().labels.is_empty());
+        let labels = context.import_labels(&opt).unwrap();
+        assert_eq!(labels.labels.len(), 3);
+
+        let freeze = FreezeOptions {
+            wallet_name: name.clone(),
+            coin: OutPoint::default(),
+        };
+        assert!(context
+            .freeze(&freeze, true)
+            .unwrap()
+            .is_frozen(&freeze.coin));
+        assert!(context.read_labels(&name).unwrap().is_frozen(&freeze.coin));
+        assert!(!context
+            .freeze(&freeze, false)
+            .unwrap()
+            .is_frozen(&freeze.coin));
+
+        let exported = NamedTempFile::new().unwrap();
+        let opt = LabelsFileOptions {
+            wallet_name: name,
+            path: exported.path().to_path_buf(),
+        };
+        let labels = context.export_labels(&opt).unwrap();
+        let content = std::fs::read_to_string(exported.path()).unwrap();
+        assert_eq!(WalletLabels::from_bip329(&content).unwrap(), labels.labels);
+        assert_eq!(labels.labels.len(), 4);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::offline::descriptor::{derive_address, DeriveAddressOptions};
+use crate::online::backend::BackendKind;
+use crate::*;
+use bitcoin::util::bip32::{ChildNumber, DerivationPath};
+use structopt::StructOpt;
+
+/// maximum number of addresses listed, since each one is derived and queried to the backend
+pub const MAX_LISTED_ADDRESSES: u32 = 1000;
+
+#[derive(StructOpt, Debug)]
+pub struct ListAddressesOptions {
+    /// The name of the wallet
+    #[structopt(long = "wallet-name")]
+    pub wallet_name: String,
+
+    /// First derivation index listed
+    #[structopt(long, default_value = "0")]
+    pub from: u32,
+
+    /// Last derivation index listed, by default the last address given out, at most 1000
+    /// addresses are listed
+    #[structopt(long)]
+    pub to: Option<u32>,
+
+    /// List the addresses of the change chain `/1/*`
+    #[structopt(long)]
+    pub change: bool,
+}
+
+impl OnlineContext {
+    /// the addresses of the wallet derived locally, with their labels and if they received funds
+    pub fn list_addresses(&self, opt: &ListAddressesOptions) -> Result<ListAddressesOutput> {
+        let wallet: Wallet = self.read(&opt.wallet_name)?;
+        let indexes: WalletIndexes = self.read(&opt.wallet_name)?;
+        let labels = self.read_labels(&opt.wallet_name)?;
+        let (chain, descriptor, next) = if opt.change {
+            (1, wallet.change_descriptor()?, indexes.change)
+        } else {
+            (0, wallet.receive_descriptor()?, indexes.main)
+        };
+        let last = opt.from.saturating_add(MAX_LISTED_ADDRESSES - 1);
+        let to = opt.to.unwrap_or_else(|| next.saturating_sub(1).min(last));
+        if to < opt.from {
+            return Err("--to must be greater or equal than --from".into());
+        }
+        if to > last {
+            return Err(format!(
+                "at most {} addresses are listed, --to must be at most {}",
+                MAX_LISTED_ADDRESSES, last
+            )
+            .into());
+        }
+
+        let mut addresses = vec![];
+        for index in opt.from..=to {
+            let derive_opts = DeriveAddressOptions {
+                descriptor: descriptor.clone(),
+                index,
+            };
+            let address = derive_address(self.network, &derive_opts)?.address;
+            let path =
+                DerivationPath::from(vec![ChildNumber::from(chain), ChildNumber::from(index)]);
+            addresses.push(AddressEntry {
+                index,
+                label: labels
+                    .label(LabelType::Addr, &address.to_string())
+                    .map(ToString::to_string),
+                address,
+                path,
+                given_out: index < next,
+                received: false,
+            });
+        }
+
+        let backend = self.make_backend(&opt.wallet_name)?;
+        let scripts: Vec<_> = addresses
+            .iter()
+            .map(|a| a.address.script_pubkey())
+            .collect();
+        for (entry, received) in addresses.iter_mut().zip(backend.received(&scripts)?) {
+            entry.received = received;
+        }
+
+        let beyond_range = to.max(next) > indexes.imported;
+        let warning = match self.read_daemon_opts()?.backend {
+            BackendKind::Bitcoind if beyond_range => Some(format!(
+                "addresses after index {} are not imported in the node, funds they receive are not detected, use `extend-range`",
+                indexes.imported
+            )),
+            _ => None,
+        };
+
+        Ok(ListAddressesOutput { addresses, warning })
+    }
+}
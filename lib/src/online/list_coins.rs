@@ -1,27 +1,60 @@
+use crate::online::fund_psbt::wallet_coins;
 use crate::online::WalletNameOptions;
 use crate::*;
-use bitcoin::OutPoint;
-use bitcoincore_rpc::RpcApi;
+use bitcoin::util::bip32::{ChildNumber, DerivationPath};
+use bitcoin::Address;
 
 impl OnlineContext {
     pub fn list_coins(&self, opt: &WalletNameOptions) -> Result<ListCoinsOutput> {
-        let client = self.make_client(&opt.wallet_name)?;
-        let mut list_coins = client.list_unspent(Some(0), None, None, None, None)?;
+        let backend = self.make_backend(&opt.wallet_name)?;
+        let wallet: Wallet = self.read(&opt.wallet_name)?;
+        let indexes: WalletIndexes = self.read(&opt.wallet_name)?;
+        let labels = self.read_labels(&opt.wallet_name)?;
+        let mut list_coins = backend.list_unspent(0)?;
         list_coins.sort_by(|a, b| a.amount.cmp(&b.amount));
+        let derived = wallet_coins(&wallet, &indexes, &list_coins)?;
         let mut coins = vec![];
         for utxo in list_coins.iter() {
-            log::info!("{}:{} {}", utxo.txid, utxo.vout, utxo.amount);
-            let outpoint = OutPoint::new(utxo.txid, utxo.vout);
+            log::info!("{} {}", utxo.outpoint, utxo.amount);
+            let outpoint = utxo.outpoint;
             let amount = utxo.amount.as_sat();
             let unconfirmed = if utxo.confirmations == 0 {
                 Some(true)
             } else {
                 None
             };
+            let address = Address::from_script(&utxo.script_pubkey, self.network);
+            let path = derived
+                .iter()
+                .find(|coin| coin.outpoint == outpoint)
+                .map(|coin| {
+                    DerivationPath::from(vec![
+                        ChildNumber::from(coin.chain),
+                        ChildNumber::from(coin.index),
+                    ])
+                });
+            let label = labels
+                .label(LabelType::Output, &outpoint.to_string())
+                .or_else(|| {
+                    address
+                        .as_ref()
+                        .and_then(|a| labels.label(LabelType::Addr, &a.to_string()))
+                })
+                .or_else(|| labels.label(LabelType::Tx, &outpoint.txid.to_string()))
+                .map(ToString::to_string);
+            let frozen = if labels.is_frozen(&outpoint) {
+                Some(true)
+            } else {
+                None
+            };
             coins.push(Coin {
                 outpoint,
                 amount,
                 unconfirmed,
+                address,
+                path,
+                label,
+                frozen,
             });
         }
         coins.sort_by(|a, b| a.amount.cmp(&b.amount));
@@ -0,0 +1,61 @@
+use crate::list::ListOptions;
+use crate::*;
+use bitcoin::Txid;
+use std::collections::HashMap;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ListTransactionsOptions {
+    /// The name of the wallet
+    #[structopt(long = "wallet-name")]
+    pub wallet_name: String,
+
+    /// Number of most recent transactions to skip
+    #[structopt(long, default_value = "0")]
+    pub skip: usize,
+
+    /// Max number of transactions returned
+    #[structopt(long, default_value = "20")]
+    pub count: usize,
+}
+
+impl OnlineContext {
+    /// the transactions of the wallet, unconfirmed first then from the most recent
+    pub fn list_transactions(
+        &self,
+        opt: &ListTransactionsOptions,
+    ) -> Result<ListTransactionsOutput> {
+        let backend = self.make_backend(&opt.wallet_name)?;
+        let mut history = backend.history()?;
+        history.sort_by_key(|tx| (tx.height.map_or(0, |h| u32::MAX - h), tx.txid));
+        let total = history.len();
+
+        let psbts = self.list(&ListOptions { kind: Kind::Psbt })?.psbts;
+        let psbt_names: HashMap<Txid, String> = psbts
+            .iter()
+            .filter_map(|p| {
+                let txid = p.psbt().ok()?.global.unsigned_tx.txid();
+                Some((txid, p.id.name.to_string()))
+            })
+            .collect();
+
+        let transactions = history
+            .into_iter()
+            .skip(opt.skip)
+            .take(opt.count)
+            .map(|tx| TransactionEntry {
+                txid: tx.txid,
+                net_amount: tx.net,
+                fee: tx.fee,
+                confirmations: tx.confirmations,
+                block_height: tx.height,
+                block_time: tx.time,
+                psbt_name: psbt_names.get(&tx.txid).cloned(),
+            })
+            .collect();
+        Ok(ListTransactionsOutput {
+            transactions,
+            total,
+        })
+    }
+}
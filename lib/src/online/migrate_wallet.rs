@@ -0,0 +1,42 @@
+use crate::online::backend::BackendKind;
+use crate::online::create_wallet::{import_descriptor, is_descriptor_wallet};
+use crate::*;
+use bitcoincore_rpc::RpcApi;
+use log::info;
+use serde_json::Value;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct MigrateWalletOptions {
+    /// The name of the wallet
+    #[structopt(long = "wallet-name")]
+    pub wallet_name: String,
+}
+
+impl OnlineContext {
+    /// migrate the legacy watch-only wallet in the node to a descriptor wallet with the same name,
+    /// transactions are kept and the wallet descriptors are imported as active up to the imported
+    /// range. Returns the result of the node `migratewallet`, containing the backup path
+    pub fn migrate_wallet(&self, opt: &MigrateWalletOptions) -> Result<Value> {
+        if self.read_daemon_opts()?.backend != BackendKind::Bitcoind {
+            return Err("migration is needed only with the bitcoind backend".into());
+        }
+        let wallet: Wallet = self.read(&opt.wallet_name)?;
+        let indexes: WalletIndexes = self.read(&opt.wallet_name)?;
+        let client = self.make_client(&opt.wallet_name)?;
+        if is_descriptor_wallet(&client)? {
+            return Err(format!("{} is already a descriptor wallet", opt.wallet_name).into());
+        }
+
+        let result: Value = client.call("migratewallet", &[])?;
+        info!("migratewallet {}", result);
+
+        let range = (0, indexes.imported);
+        let receive = wallet.receive_descriptor()?;
+        import_descriptor(&client, self.network, &receive, false, range)?;
+        let change = wallet.change_descriptor()?;
+        import_descriptor(&client, self.network, &change, true, range)?;
+
+        Ok(result)
+    }
+}
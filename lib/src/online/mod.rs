@@ -1,13 +1,27 @@
+use crate::list::ListOptions;
 use crate::*;
+use bitcoin::Txid;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
+pub mod backend;
 pub mod balance;
+pub mod bump_fee;
+pub mod consolidate;
+pub mod cpfp;
 pub mod create_tx;
 pub mod create_wallet;
+pub mod electrum;
+pub mod extend_range;
+pub mod fund_psbt;
 pub mod get_address;
+pub mod labels;
+pub mod list_addresses;
 pub mod list_coins;
+pub mod list_transactions;
+pub mod migrate_wallet;
 pub mod rescan;
 pub mod send_tx;
 
@@ -37,3 +51,34 @@ pub struct ConnectOptions {
     #[structopt(flatten)]
     pub daemon_opts: DaemonOpts,
 }
+
+impl OnlineContext {
+    /// an unused PSBT name derived from the PSBT of the transaction `txid` if stored, or from
+    /// the txid itself, eg. `test-bump`, `test-bump-2`
+    pub fn psbt_name_after(&self, txid: &Txid, suffix: &str) -> Result<String> {
+        let psbts = self.list(&ListOptions { kind: Kind::Psbt })?.psbts;
+        let original = find_psbt(&psbts, txid)
+            .map(|p| p.id.name.to_string())
+            .unwrap_or_else(|| txid.to_string());
+        let names: HashSet<_> = psbts.iter().map(|p| p.id.name.to_string()).collect();
+        let mut name = format!("{}-{}", original, suffix);
+        let mut counter = 1u32;
+        while names.contains(&name) {
+            counter += 1;
+            name = format!("{}-{}-{}", original, suffix, counter);
+        }
+        Ok(name)
+    }
+
+    /// the stored PSBT of the transaction `txid`, if any
+    pub fn stored_psbt(&self, txid: &Txid) -> Result<Option<BitcoinPsbt>> {
+        let psbts = self.list(&ListOptions { kind: Kind::Psbt })?.psbts;
+        find_psbt(&psbts, txid).map(Psbt::psbt).transpose()
+    }
+}
+
+fn find_psbt<'a>(psbts: &'a [Psbt], txid: &Txid) -> Option<&'a Psbt> {
+    psbts
+        .iter()
+        .find(|p| p.psbt().map(|p| p.global.unsigned_tx.txid()).ok() == Some(*txid))
+}
@@ -1,6 +1,5 @@
 use crate::OnlineContext;
 use bitcoin::Network;
-use bitcoincore_rpc::RpcApi;
 use serde_json::Value;
 use structopt::StructOpt;
 
@@ -28,9 +27,7 @@ impl OnlineContext {
             }
         });
 
-        let (_a, b) = self
-            .make_client(&opt.wallet_name)?
-            .rescan_blockchain(Some(start_from), None)?;
-        Ok(b.ok_or(crate::Error::MissingRescanUpTo)?.into())
+        let up_to = self.make_backend(&opt.wallet_name)?.rescan(start_from)?;
+        Ok(up_to.into())
     }
 }
@@ -1,8 +1,6 @@
 use crate::*;
-use bitcoin::consensus::deserialize;
-use bitcoin::Transaction;
-use bitcoincore_rpc::RpcApi;
-use log::{debug, info};
+use bitcoin::consensus::serialize;
+use log::info;
 
 #[derive(structopt::StructOpt, Debug)]
 pub struct SendTxOptions {
@@ -35,7 +33,7 @@ impl SendTxOptions {
 impl OnlineContext {
     pub fn send_tx(&self, opt: &SendTxOptions) -> Result<SendTxOutput> {
         opt.validate()?;
-        let client = self.make_client(&opt.wallet_name)?;
+        let backend = self.make_backend(&opt.wallet_name)?;
         let mut psbts = vec![];
         for psbt_name in opt.psbts_name.iter() {
             let json: Psbt = self.read(psbt_name)?;
@@ -43,25 +41,19 @@ impl OnlineContext {
         }
         psbts.extend(opt.psbts.clone());
 
-        let combined = client.combine_psbt(&psbts)?;
-        debug!("combined {:?}", combined);
-
-        let finalized = client.finalize_psbt(&combined, Some(true))?;
-        debug!("finalized {:?}", finalized);
-
-        let bytes = finalized.hex.ok_or(Error::MissingHex)?;
-        let hex = hex::encode(&bytes);
+        let tx = backend.finalize(&psbts)?;
+        let hex = hex::encode(serialize(&tx));
 
         let mut broadcasted = false;
         if opt.broadcast {
-            let hash = client.send_raw_transaction(&bytes)?;
+            let hash = backend.broadcast(&tx)?;
             broadcasted = true;
             info!("{:?}", hash);
         } else {
             info!("{}", hex);
         }
 
-        let txid = deserialize::<Transaction>(&hex::decode(&hex)?)?.txid();
+        let txid = tx.txid();
         let send_tx = SendTxOutput {
             hex,
             txid,